@@ -1,20 +1,15 @@
 extern crate sorcent;
-extern crate image;
 
 use std::fs::File;
 use sorcent::vtf::VTFFile;
-use sorcent::vtf::image::VTFImage;
-use image::png::PNGEncoder;
 
 fn main() {
     let mut file = File::open("target/bgrtest.vtf").unwrap();
     let vtf_file = VTFFile::open(&mut file).unwrap();
-    
-    let vtf_image = vtf_file.image.expose();
-    let rgb = vtf_image.to_rgba8_raw();
-    println!("Image converted to RGB888");
-    let mut png_file = File::create("target/bgrtest.png").unwrap();
 
-    PNGEncoder::new(&mut png_file).encode(&rgb[..], vtf_image.get_width() as u32, vtf_image.get_height() as u32, image::ColorType::RGBA(8)).unwrap();
+    let rgba = vtf_file.image().to_rgba_image();
+    println!("Image converted to RGBA8888");
+
+    rgba.save("target/bgrtest.png").unwrap();
     println!("Image saved!");
-}
\ No newline at end of file
+}
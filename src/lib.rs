@@ -1,5 +1,8 @@
 extern crate libc;
 extern crate num;
+extern crate image;
+#[cfg(feature = "parallel")]
+extern crate rayon;
 
 pub mod vtf;
 pub mod vmt;
@@ -0,0 +1,531 @@
+//! Conversion between VTF and DDS files. Image data is copied as it's stored, so
+//! compressed formats carry over without being decoded and re-encoded.
+//!
+//! Formats are written with a legacy DDS pixel format wherever one exists, so older
+//! tools can read them. The DX10 extended header is only used for formats that need
+//! it, and for textures with several animation frames, which become a texture array.
+
+use std::io::{Read, Write, Cursor};
+use std::cmp;
+
+use super::{VTFFile, VTFFrame, VTFFace, ImageFormat, VTFFlag, VTFFlags};
+use super::image::VTFImageWrapper;
+use super::error::{VTFLoadError, VTFError};
+use super::import::{new_header, alpha_flag};
+use super::resource::{read_u32, write_u32};
+use super::mipmap;
+
+const DDS_MAGIC: u32 = 0x20534444; // "DDS "
+const DDS_HEADER_SIZE: u32 = 124;
+const DDS_PIXELFORMAT_SIZE: u32 = 32;
+
+// Header flags
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+const DDSD_LINEARSIZE: u32 = 0x80000;
+const DDSD_DEPTH: u32 = 0x800000;
+
+// Pixel format flags
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_ALPHA: u32 = 0x2;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x20000;
+const DDPF_BUMPLUMINANCE: u32 = 0x40000;
+const DDPF_BUMPDUDV: u32 = 0x80000;
+/// The flags that say what kind of data a mask-based pixel format holds
+const DDPF_TYPE_MASK: u32 = DDPF_ALPHA | DDPF_RGB | DDPF_LUMINANCE | DDPF_BUMPLUMINANCE | DDPF_BUMPDUDV;
+
+// Caps
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x400000;
+const DDSCAPS2_CUBEMAP: u32 = 0x200;
+const DDSCAPS2_CUBEMAP_ALLFACES: u32 = 0xFC00;
+const DDSCAPS2_VOLUME: u32 = 0x200000;
+
+// DX10 header values
+const FOURCC_DX10: u32 = 0x30315844;
+const DIMENSION_TEXTURE2D: u32 = 3;
+const DIMENSION_TEXTURE3D: u32 = 4;
+const MISC_TEXTURECUBE: u32 = 0x4;
+
+/// How a VTF format is described in a DDS file
+#[derive(Debug, Clone, Copy)]
+enum LegacyFormat {
+    /// A four-character code, or a D3D9 format number stored in its place
+    FourCC(u32),
+    /// Pixel format flags, bits per pixel and the red, green, blue and alpha masks
+    Masks(u32, u32, [u32; 4]),
+    /// Only described by a DXGI format
+    None
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FormatMapping {
+    format: ImageFormat,
+    legacy: LegacyFormat,
+    /// The DXGI format, or 0 if there isn't one
+    dxgi: u32,
+    /// The sRGB version of the DXGI format, or 0 if there isn't one
+    dxgi_srgb: u32
+}
+
+const FORMATS: [FormatMapping; 29] = [
+    FormatMapping {format: ImageFormat::DXT1, legacy: LegacyFormat::FourCC(0x31545844), dxgi: 71, dxgi_srgb: 72},
+    FormatMapping {format: ImageFormat::DXT1_ONEBITALPHA, legacy: LegacyFormat::FourCC(0x31545844), dxgi: 71, dxgi_srgb: 72},
+    FormatMapping {format: ImageFormat::DXT3, legacy: LegacyFormat::FourCC(0x33545844), dxgi: 74, dxgi_srgb: 75},
+    FormatMapping {format: ImageFormat::DXT5, legacy: LegacyFormat::FourCC(0x35545844), dxgi: 77, dxgi_srgb: 78},
+    FormatMapping {format: ImageFormat::ATI1N, legacy: LegacyFormat::FourCC(0x31495441), dxgi: 80, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::ATI2N, legacy: LegacyFormat::FourCC(0x32495441), dxgi: 83, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::RGBA8888, legacy: LegacyFormat::Masks(DDPF_RGB | DDPF_ALPHAPIXELS, 32, [0xFF, 0xFF00, 0xFF0000, 0xFF000000]), dxgi: 28, dxgi_srgb: 29},
+    FormatMapping {format: ImageFormat::ABGR8888, legacy: LegacyFormat::Masks(DDPF_RGB | DDPF_ALPHAPIXELS, 32, [0xFF000000, 0xFF0000, 0xFF00, 0xFF]), dxgi: 0, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::ARGB8888, legacy: LegacyFormat::Masks(DDPF_RGB | DDPF_ALPHAPIXELS, 32, [0xFF00, 0xFF0000, 0xFF000000, 0xFF]), dxgi: 0, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::BGRA8888, legacy: LegacyFormat::Masks(DDPF_RGB | DDPF_ALPHAPIXELS, 32, [0xFF0000, 0xFF00, 0xFF, 0xFF000000]), dxgi: 87, dxgi_srgb: 91},
+    FormatMapping {format: ImageFormat::BGRX8888, legacy: LegacyFormat::Masks(DDPF_RGB, 32, [0xFF0000, 0xFF00, 0xFF, 0]), dxgi: 88, dxgi_srgb: 93},
+    FormatMapping {format: ImageFormat::RGB888, legacy: LegacyFormat::Masks(DDPF_RGB, 24, [0xFF, 0xFF00, 0xFF0000, 0]), dxgi: 0, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::BGR888, legacy: LegacyFormat::Masks(DDPF_RGB, 24, [0xFF0000, 0xFF00, 0xFF, 0]), dxgi: 0, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::RGB565, legacy: LegacyFormat::Masks(DDPF_RGB, 16, [0x1F, 0x7E0, 0xF800, 0]), dxgi: 0, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::BGR565, legacy: LegacyFormat::Masks(DDPF_RGB, 16, [0xF800, 0x7E0, 0x1F, 0]), dxgi: 85, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::BGRX5551, legacy: LegacyFormat::Masks(DDPF_RGB, 16, [0x7C00, 0x3E0, 0x1F, 0]), dxgi: 0, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::BGRA5551, legacy: LegacyFormat::Masks(DDPF_RGB | DDPF_ALPHAPIXELS, 16, [0x7C00, 0x3E0, 0x1F, 0x8000]), dxgi: 86, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::BGRA4444, legacy: LegacyFormat::Masks(DDPF_RGB | DDPF_ALPHAPIXELS, 16, [0xF00, 0xF0, 0xF, 0xF000]), dxgi: 115, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::I8, legacy: LegacyFormat::Masks(DDPF_LUMINANCE, 8, [0xFF, 0, 0, 0]), dxgi: 61, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::IA88, legacy: LegacyFormat::Masks(DDPF_LUMINANCE | DDPF_ALPHAPIXELS, 16, [0xFF, 0, 0, 0xFF00]), dxgi: 49, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::A8, legacy: LegacyFormat::Masks(DDPF_ALPHA, 8, [0, 0, 0, 0xFF]), dxgi: 65, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::UV88, legacy: LegacyFormat::Masks(DDPF_BUMPDUDV, 16, [0xFF, 0xFF00, 0, 0]), dxgi: 51, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::UVWQ8888, legacy: LegacyFormat::Masks(DDPF_BUMPDUDV, 32, [0xFF, 0xFF00, 0xFF0000, 0xFF000000]), dxgi: 31, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::UVLX8888, legacy: LegacyFormat::Masks(DDPF_BUMPLUMINANCE, 32, [0xFF, 0xFF00, 0xFF0000, 0]), dxgi: 0, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::RGBA16161616F, legacy: LegacyFormat::FourCC(113), dxgi: 10, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::RGBA16161616, legacy: LegacyFormat::FourCC(36), dxgi: 11, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::R32F, legacy: LegacyFormat::FourCC(114), dxgi: 41, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::RGB323232F, legacy: LegacyFormat::None, dxgi: 6, dxgi_srgb: 0},
+    FormatMapping {format: ImageFormat::RGBA32323232F, legacy: LegacyFormat::FourCC(116), dxgi: 2, dxgi_srgb: 0}
+];
+
+/// Other four-character codes used for formats that are already in `FORMATS`
+const FOURCC_ALIASES: [(u32, ImageFormat); 2] = [
+    (0x55344342, ImageFormat::ATI1N), // "BC4U"
+    (0x55354342, ImageFormat::ATI2N)  // "BC5U"
+];
+
+impl VTFFile {
+    /// Writes the file as a DDS file. See `to_dds_bytes` for how it's laid out.
+    pub fn to_dds_writer<W>(&self, dest: &mut W) -> Result<(), VTFLoadError> where W: Write {
+        let bytes = try!(self.to_dds_bytes());
+        dest.write_all(&bytes).map_err(VTFLoadError::Io)
+    }
+
+    /// Converts the file to a DDS file, keeping every mip level, cubemap face and
+    /// depth slice. Files with several frames are written as a texture array. The
+    /// spheremap face of older environment maps, the thumbnail and any resources are
+    /// dropped.
+    ///
+    /// `P8` and the bluescreen formats have no DDS equivalent, and return
+    /// `UnsupportedFormat`. So do files with several frames in a format that has no
+    /// DXGI equivalent, since texture arrays need the DX10 header.
+    pub fn to_dds_bytes(&self) -> Result<Vec<u8>, VTFLoadError> {
+        let header = &self.header;
+        let header70 = header.get_h70();
+        let format = header70.image_format;
+        let mapping = match FORMATS.iter().find(|m| m.format == format) {
+            Some(mapping) => *mapping,
+            None => return Err(VTFLoadError::VTF(VTFError::UnsupportedFormat(format)))
+        };
+
+        let flags = header.flags();
+        let is_cubemap = flags.check(VTFFlag::ENVMAP);
+        let face_count = if is_cubemap {6} else {1};
+        let depth = header.depth();
+        let mip_count = VTFFile::mip_count(header);
+        let frame_count = VTFFile::frame_count_of(header);
+
+        let dxgi = match flags.check(VTFFlag::PRE_SRGB) && mapping.dxgi_srgb != 0 {
+            true => mapping.dxgi_srgb,
+            false => mapping.dxgi
+        };
+        let use_dx10 = match mapping.legacy {
+            LegacyFormat::None => true,
+            _ => frame_count > 1
+        };
+        if use_dx10 && dxgi == 0 {
+            return Err(VTFLoadError::VTF(VTFError::UnsupportedFormat(format)));
+        }
+
+        let mut header_flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT;
+        let pitch = match format.is_compressed() {
+            true => {
+                header_flags |= DDSD_LINEARSIZE;
+                format.data_size(header70.width, header70.height).unwrap_or(0) as u32
+            },
+            false => {
+                header_flags |= DDSD_PITCH;
+                header70.width as u32 * format.block_size().unwrap_or(0)
+            }
+        };
+        let mut caps = DDSCAPS_TEXTURE;
+        let mut caps2 = 0;
+        if mip_count > 1 {
+            header_flags |= DDSD_MIPMAPCOUNT;
+            caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+        }
+        if is_cubemap {
+            caps |= DDSCAPS_COMPLEX;
+            caps2 |= DDSCAPS2_CUBEMAP | DDSCAPS2_CUBEMAP_ALLFACES;
+        }
+        if depth > 1 {
+            header_flags |= DDSD_DEPTH;
+            caps |= DDSCAPS_COMPLEX;
+            caps2 |= DDSCAPS2_VOLUME;
+        }
+
+        let (pf_flags, fourcc, bit_count, masks) = match (use_dx10, mapping.legacy) {
+            (true, _) => (DDPF_FOURCC, FOURCC_DX10, 0, [0; 4]),
+            (false, LegacyFormat::FourCC(fourcc)) => (DDPF_FOURCC, fourcc, 0, [0; 4]),
+            (false, LegacyFormat::Masks(pf_flags, bit_count, masks)) => (pf_flags, 0, bit_count, masks),
+            (false, LegacyFormat::None) => unreachable!()
+        };
+
+        let mut data = Vec::new();
+        let fields = [
+            DDS_MAGIC, DDS_HEADER_SIZE, header_flags, header70.height as u32, header70.width as u32,
+            pitch, if depth > 1 {depth as u32} else {0}, mip_count as u32,
+            0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            DDS_PIXELFORMAT_SIZE, pf_flags, fourcc, bit_count, masks[0], masks[1], masks[2], masks[3],
+            caps, caps2, 0, 0, 0
+        ];
+        for &field in fields.iter() {
+            write_u32(&mut data, field);
+        }
+
+        if use_dx10 {
+            let dimension = if depth > 1 {DIMENSION_TEXTURE3D} else {DIMENSION_TEXTURE2D};
+            let misc_flags = if is_cubemap {MISC_TEXTURECUBE} else {0};
+            for &field in [dxgi, dimension, misc_flags, frame_count as u32, 0].iter() {
+                write_u32(&mut data, field);
+            }
+        }
+
+        // DDS stores each face's full mip chain before moving on to the next face
+        for frame in &self.frames {
+            for face_index in 0..face_count {
+                let face = match frame.faces.get(face_index) {
+                    Some(face) => face,
+                    None => return Err(VTFLoadError::VTF(VTFError::ImageMismatch))
+                };
+
+                for mip_level in 0..mip_count as usize {
+                    let slices = match face.mip_slices(mip_level) {
+                        Some(slices) => slices,
+                        None => return Err(VTFLoadError::VTF(VTFError::ImageMismatch))
+                    };
+
+                    for slice in slices {
+                        try!(slice.save(&mut data).map_err(VTFLoadError::Io));
+                    }
+                }
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Loads a DDS file from any source. See `from_dds_bytes`.
+    pub fn from_dds_reader<R>(source: &mut R) -> Result<VTFFile, VTFLoadError> where R: Read {
+        let mut bytes = Vec::new();
+        try!(source.read_to_end(&mut bytes).map_err(VTFLoadError::Io));
+        VTFFile::from_dds_bytes(&bytes)
+    }
+
+    /// Converts a DDS file to a VTF file, keeping every mip level, cubemap face and
+    /// depth slice. Texture arrays become animation frames. The thumbnail and
+    /// reflectivity are generated from the converted image.
+    ///
+    /// Environment maps are created as version 7.5 files, since earlier versions
+    /// expect a spheremap face that DDS files don't have. Everything else is created
+    /// as a version 7.2 file.
+    pub fn from_dds_bytes(bytes: &[u8]) -> Result<VTFFile, VTFLoadError> {
+        let mut pos = 0;
+        let mut fields = [0; 32];
+        for field in fields.iter_mut() {
+            *field = try!(read_u32(bytes, &mut pos).map_err(VTFLoadError::VTF));
+        }
+
+        if fields[0] != DDS_MAGIC || fields[1] != DDS_HEADER_SIZE || fields[19] != DDS_PIXELFORMAT_SIZE {
+            return Err(VTFLoadError::VTF(VTFError::DdsHeader));
+        }
+
+        let header_flags = fields[2];
+        let (height, width) = (fields[3], fields[4]);
+        let pf_flags = fields[20];
+        let fourcc = fields[21];
+        let caps2 = fields[28];
+
+        let mut depth = match header_flags & DDSD_DEPTH != 0 && caps2 & DDSCAPS2_VOLUME != 0 {
+            true => fields[6],
+            false => 1
+        };
+        let mip_count = match header_flags & DDSD_MIPMAPCOUNT != 0 {
+            true => fields[7],
+            false => 1
+        };
+        let mut is_cubemap = caps2 & DDSCAPS2_CUBEMAP != 0;
+        let mut frame_count = 1;
+        let mut is_srgb = false;
+
+        let is_dx10 = pf_flags & DDPF_FOURCC != 0 && fourcc == FOURCC_DX10;
+        let format;
+        if is_dx10 {
+            let mut dx10 = [0; 5];
+            for field in dx10.iter_mut() {
+                *field = try!(read_u32(bytes, &mut pos).map_err(VTFLoadError::VTF));
+            }
+
+            let dxgi = dx10[0];
+            // Zero is DXGI_FORMAT_UNKNOWN, which the table also uses for formats that
+            // have no DXGI equivalent
+            if dxgi == 0 {
+                return Err(VTFLoadError::VTF(VTFError::DdsFormat));
+            }
+            format = match FORMATS.iter().find(|m| m.dxgi == dxgi || (m.dxgi_srgb != 0 && m.dxgi_srgb == dxgi)) {
+                Some(mapping) => {
+                    is_srgb = mapping.dxgi_srgb == dxgi;
+                    mapping.format
+                },
+                None => return Err(VTFLoadError::VTF(VTFError::DdsFormat))
+            };
+
+            is_cubemap = dx10[2] & MISC_TEXTURECUBE != 0;
+            if dx10[1] != DIMENSION_TEXTURE3D {
+                depth = 1;
+            }
+            frame_count = dx10[3];
+        } else if pf_flags & DDPF_FOURCC != 0 {
+            let mapping = FORMATS.iter()
+                .find(|m| match m.legacy {
+                    LegacyFormat::FourCC(f) => f == fourcc,
+                    _ => false
+                })
+                .map(|m| m.format)
+                .or(FOURCC_ALIASES.iter().find(|&&(f, _)| f == fourcc).map(|&(_, format)| format));
+
+            format = match mapping {
+                Some(format) => format,
+                None => return Err(VTFLoadError::VTF(VTFError::DdsFormat))
+            };
+        } else {
+            let bit_count = fields[22];
+            // Bump map formats store their fourth channel in the alpha mask without
+            // flagging it as alpha
+            let has_alpha = pf_flags & (DDPF_ALPHAPIXELS | DDPF_ALPHA | DDPF_BUMPDUDV) != 0;
+            let masks = [fields[23], fields[24], fields[25], if has_alpha {fields[26]} else {0}];
+
+            format = match FORMATS.iter().find(|m| match m.legacy {
+                LegacyFormat::Masks(f, b, m) => f & DDPF_TYPE_MASK == pf_flags & DDPF_TYPE_MASK && b == bit_count && m == masks,
+                _ => false
+            }) {
+                Some(mapping) => mapping.format,
+                None => return Err(VTFLoadError::VTF(VTFError::DdsFormat))
+            };
+        }
+
+        if width > u16::max_value() as u32 || height > u16::max_value() as u32 || depth > u16::max_value() as u32 {
+            return Err(VTFLoadError::VTF(VTFError::ImageSize));
+        }
+        let (width, height) = (width as u16, height as u16);
+        if !(width.is_power_of_two() && height.is_power_of_two()) {
+            return Err(VTFLoadError::VTF(VTFError::ImageSize));
+        }
+        // Only DX10 files can leave out the list of cubemap faces, and cubemaps missing
+        // faces have no VTF equivalent
+        let has_all_faces = is_dx10 || caps2 & DDSCAPS2_CUBEMAP_ALLFACES == DDSCAPS2_CUBEMAP_ALLFACES;
+        if is_cubemap && (!has_all_faces || depth > 1) {
+            return Err(VTFLoadError::VTF(VTFError::DdsHeader));
+        }
+        if frame_count < 1 || frame_count > u16::max_value() as u32 {
+            return Err(VTFLoadError::VTF(VTFError::DdsHeader));
+        }
+
+        let depth = cmp::max(depth, 1) as u16;
+        let mip_count = cmp::min(cmp::max(mip_count, 1), mipmap::full_mip_count(width, height) as u32) as u8;
+
+        let face_count = if is_cubemap {6} else {1};
+        let mut source = Cursor::new(&bytes[pos..]);
+        let mut frames = Vec::with_capacity(frame_count as usize);
+        for _ in 0..frame_count {
+            let mut faces = Vec::with_capacity(face_count);
+            for _ in 0..face_count {
+                let mut mips = Vec::with_capacity(mip_count as usize);
+                for mip_level in 0..mip_count {
+                    let (mip_width, mip_height) = VTFFile::compute_mip_dimensions(width, height, mip_level);
+                    let mip_depth = VTFFile::compute_mip_depth(depth, mip_level);
+
+                    let mut slices = Vec::with_capacity(mip_depth as usize);
+                    for _ in 0..mip_depth {
+                        slices.push(try!(VTFImageWrapper::load(&mut source, mip_width, mip_height, format)));
+                    }
+                    mips.push(slices);
+                }
+
+                let slices = mips.remove(0);
+                faces.push(VTFFace {mips: mips, slices: slices});
+            }
+            frames.push(VTFFrame {faces: faces});
+        }
+
+        let mut flags = VTFFlags::empty();
+        flags.set_to(VTFFlag::ENVMAP, is_cubemap);
+        flags.set_to(VTFFlag::PRE_SRGB, is_srgb);
+        if let Some(alpha_flag) = alpha_flag(format) {
+            flags.set(alpha_flag);
+        }
+
+        let version = if is_cubemap {5} else {2};
+        let header = new_header(version, width, height, depth, frame_count as u16, flags, format, mip_count);
+        let mut vtf = VTFFile::from_parts(header, frames);
+        vtf.update_reflectivity();
+        vtf.generate_thumbnail();
+
+        Ok(vtf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DDS_MAGIC, DDS_HEADER_SIZE, DDS_PIXELFORMAT_SIZE, DDSD_CAPS, DDSD_HEIGHT, DDSD_WIDTH, DDSD_PIXELFORMAT,
+                DDPF_FOURCC, DDSCAPS_TEXTURE, FOURCC_DX10, DIMENSION_TEXTURE2D};
+    use super::super::{VTFFile, VTFFrame, VTFFace, VTFFlag, VTFFlags, ImageFormat, ImportOptions};
+    use super::super::image::{VTFImageWrapper, Rgba8, DxtQuality};
+    use super::super::error::{VTFLoadError, VTFError};
+    use super::super::resource::write_u32;
+    use super::super::import::new_header;
+    use super::super::mipmap;
+
+    /// A 4x4 DDS file with a DX10 header and a single block of data
+    fn dx10_file(dxgi: u32) -> Vec<u8> {
+        let mut fields = [0u32; 37];
+        fields[0] = DDS_MAGIC;
+        fields[1] = DDS_HEADER_SIZE;
+        fields[2] = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT;
+        fields[3] = 4;
+        fields[4] = 4;
+        fields[19] = DDS_PIXELFORMAT_SIZE;
+        fields[20] = DDPF_FOURCC;
+        fields[21] = FOURCC_DX10;
+        fields[27] = DDSCAPS_TEXTURE;
+        fields[32] = dxgi;
+        fields[33] = DIMENSION_TEXTURE2D;
+        fields[35] = 1;
+
+        let mut bytes = Vec::new();
+        for field in fields.iter() {
+            write_u32(&mut bytes, *field);
+        }
+        bytes.extend_from_slice(&[0; 64]);
+        bytes
+    }
+
+    #[test]
+    fn unknown_dxgi_format_is_rejected() {
+        match VTFFile::from_dds_bytes(&dx10_file(0)) {
+            Err(VTFLoadError::VTF(VTFError::DdsFormat)) => (),
+            other => panic!("Unexpected result: {:?}", other.map(|vtf| vtf.header))
+        }
+
+        // DXGI_FORMAT_BC1_UNORM
+        let vtf = VTFFile::from_dds_bytes(&dx10_file(71)).unwrap();
+        assert_eq!(vtf.header.get_h70().image_format, ImageFormat::DXT1);
+    }
+
+    #[test]
+    fn dxt1_smaller_than_a_block() {
+        let pixels = vec![Rgba8 {red: 200, green: 100, blue: 50, alpha: 255}; 16];
+        let options = ImportOptions {format: ImageFormat::DXT1, ..ImportOptions::default()};
+        let dds = VTFFile::from_rgba8(&pixels, 8, 2, &options).unwrap().to_dds_bytes().unwrap();
+
+        let vtf = VTFFile::from_dds_bytes(&dds).unwrap();
+        let header70 = vtf.header.get_h70();
+        assert_eq!((header70.width, header70.height, header70.mip_count), (8, 2, 4));
+        assert_eq!((header70.thumbnail_width, header70.thumbnail_height), (8, 2));
+    }
+
+    /// Builds a file with a solid color for every frame, face, mip and slice, so any of
+    /// them ending up in the wrong place changes its data
+    fn solid_file(width: u16, height: u16, depth: u16, frames: u16, flags: VTFFlags, format: ImageFormat) -> VTFFile {
+        let mip_count = mipmap::full_mip_count(width, height);
+        let header = new_header(5, width, height, depth, frames, flags, format, mip_count);
+        let face_count = header.face_count();
+
+        let mut color = 0u8;
+        let frames = (0..frames).map(|_| VTFFrame {
+            faces: (0..face_count).map(|_| {
+                let mut levels: Vec<Vec<VTFImageWrapper>> = (0..mip_count).map(|mip_level| {
+                    let (mip_width, mip_height) = VTFFile::compute_mip_dimensions(width, height, mip_level);
+                    let mip_depth = VTFFile::compute_mip_depth(depth, mip_level);
+                    (0..mip_depth).map(|_| {
+                        color = color.wrapping_add(37);
+                        let pixels = vec![Rgba8 {red: color, green: 255 - color, blue: color / 2, alpha: color}; mip_width as usize * mip_height as usize];
+                        VTFImageWrapper::encode(&pixels, mip_width, mip_height, format, DxtQuality::default()).unwrap()
+                    }).collect()
+                }).collect();
+                let slices = levels.remove(0);
+                VTFFace {mips: levels, slices: slices}
+            }).collect()
+        }).collect();
+
+        VTFFile::from_parts(header, frames)
+    }
+
+    fn assert_same_images(a: &VTFFile, b: &VTFFile) {
+        assert_eq!(a.frames.len(), b.frames.len());
+        for (frame_a, frame_b) in a.frames.iter().zip(&b.frames) {
+            assert_eq!(frame_a.faces.len(), frame_b.faces.len());
+            for (face_index, (face_a, face_b)) in frame_a.faces.iter().zip(&frame_b.faces).enumerate() {
+                let mut mip_level = 0;
+                while let Some(slices_a) = face_a.mip_slices(mip_level) {
+                    let slices_b = face_b.mip_slices(mip_level).unwrap();
+                    assert_eq!(slices_a.len(), slices_b.len());
+                    for (slice_a, slice_b) in slices_a.iter().zip(slices_b) {
+                        assert_eq!(slice_a.raw_data(), slice_b.raw_data(), "face {}, mip {}", face_index, mip_level);
+                    }
+                    mip_level += 1;
+                }
+                assert!(face_b.mip_slices(mip_level).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn dxt5_cubemap_array_round_trip() {
+        let mut flags = VTFFlags::empty();
+        flags.set(VTFFlag::ENVMAP);
+        let vtf = solid_file(8, 8, 1, 2, flags, ImageFormat::DXT5);
+        let dds = vtf.to_dds_bytes().unwrap();
+
+        // Several frames need the DX10 header
+        assert_eq!(&dds[84..88], b"DX10");
+
+        let converted = VTFFile::from_dds_bytes(&dds).unwrap();
+        assert_eq!(converted.header.get_h70().image_format, ImageFormat::DXT5);
+        assert_eq!(converted.header.face_count(), 6);
+        assert_same_images(&vtf, &converted);
+    }
+
+    #[test]
+    fn uncompressed_volume_round_trip() {
+        let vtf = solid_file(4, 4, 4, 1, VTFFlags::empty(), ImageFormat::BGR888);
+        let dds = vtf.to_dds_bytes().unwrap();
+
+        // BGR888 only has a legacy pixel format, given as bit masks
+        assert_eq!(&dds[84..88], &[0, 0, 0, 0]);
+        assert_eq!(&dds[88..92], &[24, 0, 0, 0]);
+
+        let converted = VTFFile::from_dds_bytes(&dds).unwrap();
+        assert_eq!(converted.header.depth(), 4);
+        assert_same_images(&vtf, &converted);
+    }
+}
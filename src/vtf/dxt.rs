@@ -0,0 +1,535 @@
+//! Block compression for the DXT formats. Each function compresses a single 4x4 block,
+//! given as 16 RGBA pixels ordered left to right, top to bottom.
+
+use super::image::{Rgb565, ColorType, interp_alpha_8bit, interp_alpha_6bit};
+
+/// How much effort to spend when compressing DXT blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DxtQuality {
+    /// Fits the endpoints to the extremes of each block's colors along their principal
+    /// axis. Fast, but can give visible banding on smooth gradients.
+    RangeFit,
+    /// Tries every way of splitting each block's colors into clusters along their
+    /// principal axis, and picks the endpoints that give the lowest error. Slower, but
+    /// gives noticeably better results.
+    ClusterFit
+}
+
+impl Default for DxtQuality {
+    fn default() -> DxtQuality {
+        DxtQuality::ClusterFit
+    }
+}
+
+/// Compresses the color part of a block. If `punch_through` is set, pixels with an
+/// alpha below 128 are encoded as transparent black, which forces the block into
+/// three-color mode.
+///
+/// Returns the two endpoints and the packed 2-bit indices.
+pub fn encode_color_block(pixels: &[[u8; 4]; 16], quality: DxtQuality, punch_through: bool) -> (u16, u16, [u8; 4]) {
+    let transparent: Vec<bool> = pixels.iter().map(|p| punch_through && p[3] < 128).collect();
+    let points: Vec<[f32; 3]> = pixels.iter().zip(&transparent)
+        .filter(|&(_, &t)| !t)
+        .map(|(p, _)| [p[0] as f32, p[1] as f32, p[2] as f32])
+        .collect();
+
+    // Fully transparent blocks only need the transparent index
+    if points.is_empty() {
+        return (0, 0, [0xFF; 4]);
+    }
+
+    let three_color = transparent.iter().any(|&t| t);
+
+    let (start, end) = match quality {
+        DxtQuality::RangeFit => range_fit(&points),
+        DxtQuality::ClusterFit => match three_color {
+            true => cluster_fit(&points, &[1.0, 0.5, 0.0]),
+            false => cluster_fit(&points, &[1.0, 2.0 / 3.0, 1.0 / 3.0, 0.0])
+        }
+    };
+
+    let mut c0 = quantize_565(start);
+    let mut c1 = quantize_565(end);
+
+    // The order of the endpoints picks the block's mode. Four-color blocks need c0 > c1,
+    // and three-color blocks need c0 <= c1.
+    if three_color {
+        if c0 > c1 {
+            let c = c0; c0 = c1; c1 = c;
+        }
+    } else if c0 < c1 {
+        let c = c0; c0 = c1; c1 = c;
+    } else if c0 == c1 {
+        // Identical endpoints decode as three-color, so every pixel uses c0
+        return (c0, c1, [0; 4]);
+    }
+
+    // Build the palette exactly as the decoder will, then pick the closest entry for
+    // each pixel
+    let p0 = Rgb565::load(c0).to_rgb8();
+    let p1 = Rgb565::load(c1).to_rgb8();
+    let p0 = [p0.red as i32, p0.green as i32, p0.blue as i32];
+    let p1 = [p1.red as i32, p1.green as i32, p1.blue as i32];
+    let palette: Vec<[i32; 3]> = match three_color {
+        true => vec![p0, p1, mix(p0, p1, 1, 1, 2)],
+        false => vec![p0, p1, mix(p0, p1, 2, 1, 3), mix(p0, p1, 1, 2, 3)]
+    };
+
+    let mut indices = [0u8; 4];
+    for (i, p) in pixels.iter().enumerate() {
+        let index = match transparent[i] {
+            true => 3,
+            false => nearest(&palette, [p[0] as i32, p[1] as i32, p[2] as i32])
+        };
+        indices[i / 4] |= index << ((i % 4) * 2);
+    }
+
+    (c0, c1, indices)
+}
+
+/// Compresses an alpha channel into DXT3's explicit 4-bit alphas.
+pub fn encode_explicit_alpha(alphas: &[u8; 16]) -> [u8; 8] {
+    let mut block = [0u8; 8];
+    for (i, &a) in alphas.iter().enumerate() {
+        let a4 = ((a as u32 * 15 + 127) / 255) as u8;
+        block[i / 2] |= a4 << ((i % 2) * 4);
+    }
+
+    block
+}
+
+/// Compresses an alpha channel into an interpolated block, as used by DXT5. Cluster
+/// fitting also tries the six-alpha mode, which stores 0 and 255 exactly.
+///
+/// Returns the two endpoints and the packed 3-bit indices.
+pub fn encode_interpolated_alpha(alphas: &[u8; 16], quality: DxtQuality) -> (u8, u8, [u8; 6]) {
+    let min = *alphas.iter().min().unwrap();
+    let max = *alphas.iter().max().unwrap();
+
+    // Eight-alpha mode needs a0 > a1
+    let eight = match max > min {
+        true => alpha_block(alphas, max, min),
+        false => return (max, min, [0; 6])
+    };
+
+    match quality {
+        DxtQuality::RangeFit => eight.0,
+        DxtQuality::ClusterFit => {
+            // Six-alpha mode needs a0 <= a1, and fits its endpoints to everything that
+            // isn't already covered by the explicit 0 and 255
+            let inner_min = alphas.iter().cloned().filter(|&a| a != 0 && a != 255).min().unwrap_or(0);
+            let inner_max = alphas.iter().cloned().filter(|&a| a != 0 && a != 255).max().unwrap_or(255);
+            let six = alpha_block(alphas, inner_min, inner_max);
+
+            match six.1 < eight.1 {
+                true => six.0,
+                false => eight.0
+            }
+        }
+    }
+}
+
+/// Builds an interpolated alpha block from the given endpoints, returning it along
+/// with its squared error.
+fn alpha_block(alphas: &[u8; 16], a0: u8, a1: u8) -> ((u8, u8, [u8; 6]), u32) {
+    // Built the same way the decoder builds it
+    let palette: [u8; 8] = match a0 > a1 {
+        true => [a0, a1,
+                 interp_alpha_8bit(a0, a1, 0), interp_alpha_8bit(a0, a1, 1), interp_alpha_8bit(a0, a1, 2),
+                 interp_alpha_8bit(a0, a1, 3), interp_alpha_8bit(a0, a1, 4), interp_alpha_8bit(a0, a1, 5)],
+        false => [a0, a1,
+                  interp_alpha_6bit(a0, a1, 0), interp_alpha_6bit(a0, a1, 1),
+                  interp_alpha_6bit(a0, a1, 2), interp_alpha_6bit(a0, a1, 3),
+                  0, 255]
+    };
+
+    let mut bits: u64 = 0;
+    let mut error = 0;
+    for (i, &a) in alphas.iter().enumerate() {
+        let (index, e) = palette.iter().enumerate()
+            .map(|(pi, &p)| (pi, (p as i32 - a as i32).pow(2) as u32))
+            .min_by_key(|&(_, e)| e)
+            .unwrap();
+        bits |= (index as u64) << (i * 3);
+        error += e;
+    }
+
+    let mut indices = [0u8; 6];
+    for (i, b) in indices.iter_mut().enumerate() {
+        *b = (bits >> (i * 8)) as u8;
+    }
+
+    ((a0, a1, indices), error)
+}
+
+/// Finds the endpoints by projecting every point onto the principal axis and taking
+/// the two extremes.
+fn range_fit(points: &[[f32; 3]]) -> ([f32; 3], [f32; 3]) {
+    let axis = principal_axis(points);
+
+    let mut min = (f32::MAX, points[0]);
+    let mut max = (f32::MIN, points[0]);
+    for p in points {
+        let d = dot(*p, axis);
+        if d < min.0 {
+            min = (d, *p);
+        }
+        if d > max.0 {
+            max = (d, *p);
+        }
+    }
+
+    (max.1, min.1)
+}
+
+/// Orders the points along the principal axis, then tries every split of them into
+/// `weights.len()` consecutive clusters. Each cluster is given a weight between the
+/// two endpoints, which are then solved for with least squares.
+fn cluster_fit(points: &[[f32; 3]], weights: &[f32]) -> ([f32; 3], [f32; 3]) {
+    let axis = principal_axis(points);
+    let mut order: Vec<[f32; 3]> = points.to_vec();
+    order.sort_by(|a, b| dot(*b, axis).partial_cmp(&dot(*a, axis)).unwrap_or(::std::cmp::Ordering::Equal));
+
+    // Running sums of the ordered points, so each cluster's sum can be found in
+    // constant time
+    let n = order.len();
+    let mut prefix = vec![[0.0f32; 3]; n + 1];
+    for (i, p) in order.iter().enumerate() {
+        for c in 0..3 {
+            prefix[i + 1][c] = prefix[i][c] + p[c];
+        }
+    }
+
+    let mut best = range_fit(points);
+    let mut best_error = f32::MAX;
+
+    // Cluster boundaries; cluster c holds points from bounds[c] up to bounds[c + 1]
+    let mut bounds = vec![0; weights.len() + 1];
+    bounds[weights.len()] = n;
+    loop {
+        if let Some((endpoints, error)) = solve_clusters(&prefix, &bounds, weights) {
+            if error < best_error {
+                best_error = error;
+                best = endpoints;
+            }
+        }
+
+        // Step to the next split, like an odometer whose digits never decrease
+        let mut c = weights.len() - 1;
+        loop {
+            if c == 0 {
+                return best;
+            }
+            if bounds[c] < bounds[c + 1] {
+                bounds[c] += 1;
+                for later in (c + 1)..weights.len() {
+                    bounds[later] = bounds[c];
+                }
+                break;
+            }
+            c -= 1;
+        }
+    }
+}
+
+/// Solves for the endpoints that best fit the clusters with least squares, then finds
+/// the error once the endpoints are quantized. The error leaves out the sum of the
+/// squared points, which is the same for every split. Returns `None` if the system is
+/// degenerate.
+fn solve_clusters(prefix: &[[f32; 3]], bounds: &[usize], weights: &[f32]) -> Option<(([f32; 3], [f32; 3]), f32)> {
+    let (mut aa, mut bb, mut ab) = (0.0, 0.0, 0.0);
+    let mut ax = [0.0; 3];
+    let mut bx = [0.0; 3];
+
+    for (c, &alpha) in weights.iter().enumerate() {
+        let beta = 1.0 - alpha;
+        let count = (bounds[c + 1] - bounds[c]) as f32;
+        aa += alpha * alpha * count;
+        bb += beta * beta * count;
+        ab += alpha * beta * count;
+        for i in 0..3 {
+            let sum = prefix[bounds[c + 1]][i] - prefix[bounds[c]][i];
+            ax[i] += alpha * sum;
+            bx[i] += beta * sum;
+        }
+    }
+
+    let det = aa * bb - ab * ab;
+    if det.abs() < 1e-6 {
+        return None;
+    }
+
+    let mut start = [0.0; 3];
+    let mut end = [0.0; 3];
+    for i in 0..3 {
+        start[i] = clamp_255((ax[i] * bb - bx[i] * ab) / det);
+        end[i] = clamp_255((bx[i] * aa - ax[i] * ab) / det);
+    }
+
+    let a = dequantize_565(quantize_565(start));
+    let b = dequantize_565(quantize_565(end));
+    let error = dot(a, a) * aa + dot(b, b) * bb + 2.0 * dot(a, b) * ab - 2.0 * dot(a, ax) - 2.0 * dot(b, bx);
+
+    Some(((start, end), error))
+}
+
+/// Finds the direction the points vary most in, using power iteration on their
+/// covariance matrix.
+fn principal_axis(points: &[[f32; 3]]) -> [f32; 3] {
+    let n = points.len() as f32;
+    let mut mean = [0.0; 3];
+    for p in points {
+        for i in 0..3 {
+            mean[i] += p[i] / n;
+        }
+    }
+
+    let mut cov = [[0.0f32; 3]; 3];
+    for p in points {
+        let d = [p[0] - mean[0], p[1] - mean[1], p[2] - mean[2]];
+        for i in 0..3 {
+            for j in 0..3 {
+                cov[i][j] += d[i] * d[j];
+            }
+        }
+    }
+
+    // Start from the channel that varies the most. A fixed starting vector can be
+    // orthogonal to the axis, such as [1, 1, 1] for a red to blue gradient.
+    let mut widest = 0;
+    for i in 1..3 {
+        if cov[i][i] > cov[widest][widest] {
+            widest = i;
+        }
+    }
+    let mut axis = match cov[widest][widest] > 0.0 {
+        true => [cov[0][widest], cov[1][widest], cov[2][widest]],
+        false => [1.0, 1.0, 1.0]
+    };
+    for _ in 0..8 {
+        let next = [dot(cov[0], axis), dot(cov[1], axis), dot(cov[2], axis)];
+        let len = dot(next, next).sqrt();
+        if len < 1e-6 {
+            break;
+        }
+        axis = [next[0] / len, next[1] / len, next[2] / len];
+    }
+
+    axis
+}
+
+/// Rounds an 8-bit color to the nearest RGB565 value, with red in the high bits.
+fn quantize_565(c: [f32; 3]) -> u16 {
+    let r = (clamp_255(c[0]) * 31.0 / 255.0 + 0.5) as u16;
+    let g = (clamp_255(c[1]) * 63.0 / 255.0 + 0.5) as u16;
+    let b = (clamp_255(c[2]) * 31.0 / 255.0 + 0.5) as u16;
+    r << 11 | g << 5 | b
+}
+
+fn dequantize_565(c: u16) -> [f32; 3] {
+    let rgb = Rgb565::load(c).to_rgb8();
+    [rgb.red as f32, rgb.green as f32, rgb.blue as f32]
+}
+
+/// Mixes two colors the same way the decoder does, with integer division.
+fn mix(c0: [i32; 3], c1: [i32; 3], w0: i32, w1: i32, div: i32) -> [i32; 3] {
+    [(w0 * c0[0] + w1 * c1[0]) / div,
+     (w0 * c0[1] + w1 * c1[1]) / div,
+     (w0 * c0[2] + w1 * c1[2]) / div]
+}
+
+fn nearest(palette: &[[i32; 3]], c: [i32; 3]) -> u8 {
+    let mut best = (0, i32::max_value());
+    for (i, p) in palette.iter().enumerate() {
+        let e = (p[0] - c[0]).pow(2) + (p[1] - c[1]).pow(2) + (p[2] - c[2]).pow(2);
+        if e < best.1 {
+            best = (i as u8, e);
+        }
+    }
+
+    best.0
+}
+
+#[inline]
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+#[inline]
+fn clamp_255(c: f32) -> f32 {
+    c.max(0.0).min(255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DxtQuality, encode_color_block, encode_explicit_alpha, encode_interpolated_alpha};
+    use super::super::image::{Rgb565, ColorType, Dxt1, Dxt3, Dxt5, VTFImage};
+
+    const QUALITIES: [DxtQuality; 2] = [DxtQuality::RangeFit, DxtQuality::ClusterFit];
+
+    fn dxt1_block(pixels: &[[u8; 4]; 16], quality: DxtQuality, punch_through: bool) -> Vec<u8> {
+        let (c0, c1, indices) = encode_color_block(pixels, quality, punch_through);
+        let mut block = vec![c0 as u8, (c0 >> 8) as u8, c1 as u8, (c1 >> 8) as u8];
+        block.extend_from_slice(&indices);
+        block
+    }
+
+    fn decode_dxt1(block: &[u8]) -> Vec<[u8; 4]> {
+        let image = Dxt1::load(&mut &block[..], 4, 4).unwrap();
+        image.to_rgba8().iter().map(|p| [p.red, p.green, p.blue, p.alpha]).collect()
+    }
+
+    fn decode_dxt3(block: &[u8]) -> Vec<[u8; 4]> {
+        let image = Dxt3::load(&mut &block[..], 4, 4).unwrap();
+        image.to_rgba8().iter().map(|p| [p.red, p.green, p.blue, p.alpha]).collect()
+    }
+
+    fn decode_dxt5(block: &[u8]) -> Vec<[u8; 4]> {
+        let image = Dxt5::load(&mut &block[..], 4, 4).unwrap();
+        image.to_rgba8().iter().map(|p| [p.red, p.green, p.blue, p.alpha]).collect()
+    }
+
+    /// The largest difference between any channel of the given pixels, ignoring alpha
+    fn max_color_error(a: &[[u8; 4]], b: &[[u8; 4]]) -> u8 {
+        a.iter().zip(b)
+            .flat_map(|(a, b)| (0..3).map(move |c| (a[c] as i32 - b[c] as i32).abs() as u8))
+            .max().unwrap()
+    }
+
+    fn gradient(from: [u8; 4], to: [u8; 4]) -> [[u8; 4]; 16] {
+        let mut pixels = [[0; 4]; 16];
+        for (i, p) in pixels.iter_mut().enumerate() {
+            for c in 0..4 {
+                p[c] = ((from[c] as u32 * (15 - i as u32) + to[c] as u32 * i as u32 + 7) / 15) as u8;
+            }
+        }
+        pixels
+    }
+
+    /// Colors that 565 endpoints can store exactly
+    fn solid_colors() -> Vec<[u8; 4]> {
+        [0x0000u16, 0xFFFF, 0xF800, 0x07E0, 0x001F, 0x8410, 0x4A69, 0xC618].iter()
+            .map(|&c| {
+                let c = Rgb565::load(c).to_rgb8();
+                [c.red, c.green, c.blue, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn solid_blocks_round_trip() {
+        for color in solid_colors() {
+            let pixels = [color; 16];
+            for &quality in &QUALITIES {
+                assert_eq!(decode_dxt1(&dxt1_block(&pixels, quality, false)), vec![color; 16]);
+                assert_eq!(decode_dxt1(&dxt1_block(&pixels, quality, true)), vec![color; 16]);
+            }
+        }
+    }
+
+    #[test]
+    fn solid_alpha_round_trips() {
+        for &alpha in &[0u8, 17, 34, 136, 255] {
+            let color = [24, 130, 66, 255];
+            let mut block = encode_explicit_alpha(&[alpha; 16]).to_vec();
+            block.extend(dxt1_block(&[color; 16], DxtQuality::default(), false));
+            assert!(decode_dxt3(&block).iter().all(|p| p[3] == alpha));
+        }
+
+        for alpha in 0..256 {
+            let alpha = alpha as u8;
+            for &quality in &QUALITIES {
+                let (a0, a1, indices) = encode_interpolated_alpha(&[alpha; 16], quality);
+                let mut block = vec![a0, a1];
+                block.extend_from_slice(&indices);
+                block.extend(dxt1_block(&[[0, 0, 0, 255]; 16], quality, false));
+                assert!(decode_dxt5(&block).iter().all(|p| p[3] == alpha));
+            }
+        }
+    }
+
+    #[test]
+    fn gradients_stay_close() {
+        let ends = [([0, 0, 0, 0], [255, 255, 255, 255]),
+                    ([255, 0, 0, 255], [0, 0, 255, 0]),
+                    ([30, 200, 90, 10], [120, 60, 220, 250]),
+                    ([100, 100, 100, 128], [110, 104, 96, 140])];
+
+        for &(from, to) in &ends {
+            let pixels = gradient(from, to);
+            let span = |c: usize| (from[c] as i32 - to[c] as i32).abs() as f32;
+            // Four colors and eight alphas are spread over sixteen steps, so allow a bit
+            // more than half the gap between palette entries
+            let color_bound = (0..3).map(span).fold(0.0, f32::max) / 8.0 + 4.0;
+            let alpha_bound = span(3) / 14.0 + 2.0;
+
+            let mut alphas = [0; 16];
+            for (a, p) in alphas.iter_mut().zip(pixels.iter()) {
+                *a = p[3];
+            }
+
+            for &quality in &QUALITIES {
+                let color = dxt1_block(&pixels, quality, false);
+                let error = max_color_error(&decode_dxt1(&color), &pixels);
+                assert!(error as f32 <= color_bound, "{:?} to {:?} with {:?} is off by {}", from, to, quality, error);
+
+                let (a0, a1, indices) = encode_interpolated_alpha(&alphas, quality);
+                let mut block = vec![a0, a1];
+                block.extend_from_slice(&indices);
+                block.extend_from_slice(&color);
+                for (p, a) in decode_dxt5(&block).iter().zip(alphas.iter()) {
+                    assert!((p[3] as i32 - *a as i32).abs() as f32 <= alpha_bound);
+                }
+            }
+
+            let mut block = encode_explicit_alpha(&alphas).to_vec();
+            block.extend(dxt1_block(&pixels, DxtQuality::default(), false));
+            for (p, a) in decode_dxt3(&block).iter().zip(alphas.iter()) {
+                assert!((p[3] as i32 - *a as i32).abs() <= 8);
+            }
+        }
+    }
+
+    #[test]
+    fn interpolated_alpha_keeps_extremes() {
+        // Six-alpha mode stores 0 and 255 exactly, so they shouldn't pick up any error
+        let alphas = [0, 255, 0, 255, 100, 110, 120, 130, 0, 255, 140, 150, 160, 170, 0, 255];
+        let (a0, a1, indices) = encode_interpolated_alpha(&alphas, DxtQuality::ClusterFit);
+        assert!(a0 <= a1);
+
+        let mut block = vec![a0, a1];
+        block.extend_from_slice(&indices);
+        block.extend(dxt1_block(&[[0, 0, 0, 255]; 16], DxtQuality::ClusterFit, false));
+        for (p, a) in decode_dxt5(&block).iter().zip(alphas.iter()) {
+            match *a {
+                0 | 255 => assert_eq!(p[3], *a),
+                a => assert!((p[3] as i32 - a as i32).abs() <= 6)
+            }
+        }
+    }
+
+    #[test]
+    fn punch_through_alpha_is_kept() {
+        let mut pixels = gradient([200, 40, 10, 255], [20, 180, 240, 255]);
+        for (i, p) in pixels.iter_mut().enumerate() {
+            if i % 3 == 0 {
+                p[3] = 127;
+            }
+        }
+
+        for &quality in &QUALITIES {
+            let decoded = decode_dxt1(&dxt1_block(&pixels, quality, true));
+            for (d, p) in decoded.iter().zip(pixels.iter()) {
+                match p[3] < 128 {
+                    true => assert_eq!(*d, [0, 0, 0, 0]),
+                    false => {
+                        assert_eq!(d[3], 255);
+                        assert!(max_color_error(&[*d], &[*p]) <= 48);
+                    }
+                }
+            }
+
+            assert_eq!(decode_dxt1(&dxt1_block(&[[90, 90, 90, 0]; 16], quality, true)), vec![[0, 0, 0, 0]; 16]);
+        }
+    }
+}
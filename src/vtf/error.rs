@@ -2,6 +2,8 @@ use std::error;
 use std::fmt;
 use std::io;
 
+use super::format::ImageFormat;
+
 #[derive(Debug)]
 pub enum VTFLoadError {
     Io(io::Error),
@@ -33,7 +35,30 @@ pub enum VTFError {
     HeaderImageFormat,
     ImageSize,
     FileSize,
-    ResourceID
+    ResourceID,
+    /// The image format is valid, but can't be loaded
+    UnsupportedFormat(ImageFormat),
+    /// The file ends before all of the data listed in the header. Sizes are in bytes.
+    Truncated {
+        expected: u64,
+        actual: u64
+    },
+    /// A resource or the header points outside of the file
+    BadResourceOffset,
+    /// The file doesn't contain a high-res image resource
+    MissingImage,
+    /// The requested frame, face, slice or mip level doesn't exist
+    ImageIndex,
+    /// The KeyValueData resource isn't valid KeyValues text
+    KeyValueSyntax,
+    /// An image doesn't match the format or dimensions given in the header, or is missing
+    ImageMismatch,
+    /// A resource stored in a data chunk has no data to write
+    MissingResourceData,
+    /// A DDS file's signature or header is invalid
+    DdsHeader,
+    /// A DDS file's pixel format has no VTF equivalent
+    DdsFormat
 }
 
 impl VTFError {
@@ -44,14 +69,28 @@ impl VTFError {
             &VTFError::HeaderImageFormat    => "Invalid Header; Invalid image format",
             &VTFError::ImageSize            => "Image width or height is not power of two",
             &VTFError::FileSize             => "File too small to contain header",
-            &VTFError::ResourceID           => "Invalid Resource ID"
+            &VTFError::ResourceID           => "Invalid Resource ID",
+            &VTFError::UnsupportedFormat(_) => "Unsupported image format",
+            &VTFError::Truncated{..}        => "File is truncated",
+            &VTFError::BadResourceOffset    => "Resource offset is outside of the file",
+            &VTFError::MissingImage         => "File does not contain an image resource",
+            &VTFError::ImageIndex           => "Requested image does not exist",
+            &VTFError::KeyValueSyntax       => "KeyValueData resource contains invalid KeyValues text",
+            &VTFError::ImageMismatch        => "Image does not match the header's format or dimensions",
+            &VTFError::MissingResourceData  => "Resource has no data to write",
+            &VTFError::DdsHeader            => "Invalid DDS header",
+            &VTFError::DdsFormat            => "DDS pixel format has no VTF equivalent"
         }
     }
 }
 
 impl fmt::Display for VTFError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.get_description().fmt(f)
+        match self {
+            &VTFError::UnsupportedFormat(format) => format!("Unsupported image format: {:?}", format).fmt(f),
+            &VTFError::Truncated{expected, actual} => format!("File is truncated; expected {} bytes, found {}", expected, actual).fmt(f),
+            _ => self.get_description().fmt(f)
+        }
     }
 }
 
@@ -59,4 +98,4 @@ impl error::Error for VTFError {
     fn description(&self) -> &str {
         self.get_description()
     }
-}
\ No newline at end of file
+}
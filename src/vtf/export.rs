@@ -0,0 +1,183 @@
+use std::cmp;
+use std::path::{Path, PathBuf};
+
+use image::{RgbaImage, ImageBuffer, Rgba};
+
+use super::VTFFile;
+use super::image::{VTFImageWrapper, decode_cropped};
+use super::error::VTFLoadError;
+
+/// The gap, in pixels, left between images on a contact sheet
+const SHEET_SPACING: u32 = 2;
+
+impl VTFFile {
+    /// Saves every image in the file as a separate PNG in `dir`, returning the paths
+    /// written. The thumbnail is saved as `<name>_thumb.png`, and every other image as
+    /// `<name>_frame<F>_face<N>_mip<M>_slice<S>.png`.
+    pub fn export_images<P>(&self, dir: P, name: &str) -> Result<Vec<PathBuf>, VTFLoadError> where P: AsRef<Path> {
+        let dir = dir.as_ref();
+        let mut paths = Vec::new();
+
+        if let Some(ref thumb) = self.thumb {
+            let path = dir.join(format!("{}_thumb.png", name));
+            try!(self.thumb_image(thumb).save(&path).map_err(VTFLoadError::Io));
+            paths.push(path);
+        }
+
+        for (frame_index, frame) in self.frames.iter().enumerate() {
+            for (face_index, face) in frame.faces.iter().enumerate() {
+                let mut mip_level = 0;
+                while let Some(slices) = face.mip_slices(mip_level) {
+                    for (slice_index, slice) in slices.iter().enumerate() {
+                        let path = dir.join(format!("{}_frame{}_face{}_mip{}_slice{}.png", name, frame_index, face_index, mip_level, slice_index));
+                        try!(self.mip_image(slice, mip_level).save(&path).map_err(VTFLoadError::Io));
+                        paths.push(path);
+                    }
+                    mip_level += 1;
+                }
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Lays every image in the file out on a single transparent image. The thumbnail
+    /// gets the first row to itself. After that, each depth slice of each face of each
+    /// frame gets a row, with its mip chain running from left to right.
+    pub fn contact_sheet(&self) -> RgbaImage {
+        let mut rows: Vec<Vec<RgbaImage>> = Vec::new();
+
+        if let Some(ref thumb) = self.thumb {
+            rows.push(vec![self.thumb_image(thumb)]);
+        }
+
+        for frame in &self.frames {
+            for face in &frame.faces {
+                for slice_index in 0..face.slices.len() {
+                    let mut row = Vec::new();
+                    let mut mip_level = 0;
+                    while let Some(slices) = face.mip_slices(mip_level) {
+                        // Deeper mips have fewer slices, so they run out before the shallower ones
+                        if let Some(slice) = slices.get(slice_index) {
+                            row.push(self.mip_image(slice, mip_level));
+                        }
+                        mip_level += 1;
+                    }
+                    rows.push(row);
+                }
+            }
+        }
+
+        let row_width = |row: &Vec<RgbaImage>| -> u32 {
+            row.iter().map(|image| image.width() + SHEET_SPACING).sum::<u32>()
+        };
+        let row_height = |row: &Vec<RgbaImage>| -> u32 {
+            row.iter().map(|image| image.height()).max().unwrap_or(0) + SHEET_SPACING
+        };
+
+        let sheet_width = rows.iter().map(|row| row_width(row)).max().unwrap_or(0);
+        let sheet_height = rows.iter().map(|row| row_height(row)).sum::<u32>();
+        let mut sheet = ImageBuffer::from_pixel(cmp::max(sheet_width, 1), cmp::max(sheet_height, 1), Rgba([0, 0, 0, 0]));
+
+        let mut y = 0;
+        for row in &rows {
+            let mut x = 0;
+            for image in row {
+                for (image_x, image_y, p) in image.enumerate_pixels() {
+                    sheet.put_pixel(x + image_x, y + image_y, *p);
+                }
+                x += image.width() + SHEET_SPACING;
+            }
+            y += row_height(row);
+        }
+
+        sheet
+    }
+
+    /// Saves the contact sheet from `contact_sheet` as a PNG.
+    pub fn save_contact_sheet<P>(&self, path: P) -> Result<(), VTFLoadError> where P: AsRef<Path> {
+        self.contact_sheet().save(path).map_err(VTFLoadError::Io)
+    }
+
+    /// Decodes a mip at the size the engine samples it, without any block padding.
+    fn mip_image(&self, image: &VTFImageWrapper, mip_level: usize) -> RgbaImage {
+        let header70 = self.header.get_h70();
+        let (width, height) = VTFFile::compute_mip_dimensions(header70.width, header70.height, mip_level as u8);
+        cropped_image(image, width, height)
+    }
+
+    fn thumb_image(&self, thumb: &VTFImageWrapper) -> RgbaImage {
+        let header70 = self.header.get_h70();
+        cropped_image(thumb, header70.thumbnail_width as u16, header70.thumbnail_height as u16)
+    }
+}
+
+fn cropped_image(image: &VTFImageWrapper, width: u16, height: u16) -> RgbaImage {
+    let width = cmp::min(width, image.expose().get_width());
+    let height = cmp::min(height, image.expose().get_height());
+
+    let mut raw = Vec::with_capacity(width as usize * height as usize * 4);
+    for p in decode_cropped(image, width, height) {
+        raw.extend_from_slice(&[p.red, p.green, p.blue, p.alpha]);
+    }
+    ImageBuffer::from_raw(width as u32, height as u32, raw)
+        .expect("Decoded image does not match its dimensions")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use image::{self, GenericImage};
+
+    use super::super::{VTFFile, ImageFormat, ImportOptions};
+    use super::super::image::Rgba8;
+
+    /// An 8x4 DXT1 file with a full mip chain and a thumbnail, filled with red
+    fn red_dxt1() -> VTFFile {
+        let pixels = vec![Rgba8 {red: 255, green: 0, blue: 0, alpha: 255}; 32];
+        let options = ImportOptions {format: ImageFormat::DXT1, ..ImportOptions::default()};
+        VTFFile::from_rgba8(&pixels, 8, 4, &options).unwrap()
+    }
+
+    #[test]
+    fn contact_sheet_layout() {
+        let sheet = red_dxt1().contact_sheet();
+
+        // The 8x4 thumbnail row, then the 8x4, 4x2, 2x1 and 1x1 mips, each followed by
+        // two pixels of spacing
+        assert_eq!(sheet.dimensions(), (23, 12));
+        let opaque = |x, y| sheet.get_pixel(x, y).data == [255, 0, 0, 255];
+        let empty = |x, y| sheet.get_pixel(x, y).data[3] == 0;
+
+        assert!(opaque(0, 0) && opaque(7, 3) && empty(8, 0) && empty(0, 4));
+        assert!(opaque(0, 6) && opaque(7, 9) && empty(8, 6));
+        assert!(opaque(10, 6) && opaque(13, 7) && empty(10, 8));
+        assert!(opaque(16, 6) && opaque(17, 6) && empty(16, 7));
+        assert!(opaque(20, 6) && empty(21, 6) && empty(20, 7));
+    }
+
+    #[test]
+    fn export_images_writes_every_mip() {
+        let dir = env::temp_dir().join(format!("sorcent_export_test_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let paths = red_dxt1().export_images(&dir, "red").unwrap();
+        let expected = [
+            ("red_thumb.png", (8, 4)),
+            ("red_frame0_face0_mip0_slice0.png", (8, 4)),
+            ("red_frame0_face0_mip1_slice0.png", (4, 2)),
+            ("red_frame0_face0_mip2_slice0.png", (2, 1)),
+            ("red_frame0_face0_mip3_slice0.png", (1, 1))
+        ];
+        assert_eq!(paths.len(), expected.len());
+        for (path, &(name, dimensions)) in paths.iter().zip(expected.iter()) {
+            assert_eq!(path, &dir.join(name));
+            assert_eq!(image::open(path).unwrap().dimensions(), dimensions);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
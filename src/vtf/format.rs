@@ -1,5 +1,5 @@
 use libc::{c_char, c_int, c_ushort, c_uint, c_float};
-use std::io::Read;
+use std::io::{Read, Write};
 use std::mem;
 use num::FromPrimitive;
 use super::error::*;
@@ -43,6 +43,52 @@ impl HeaderVersion {
             _ => None
         }
     }
+
+    pub fn get_h70_mut(&mut self) -> &mut Header70 {
+        match self {
+            &mut HeaderVersion::H70(_, ref mut h70) => h70,
+            &mut HeaderVersion::H72(_, ref mut h70, _) => h70,
+            &mut HeaderVersion::H73(_, ref mut h70, _, _) => h70
+        }
+    }
+
+    pub fn flags(&self) -> VTFFlags {
+        VTFFlags::from_bits(self.get_h70().flags)
+    }
+
+    pub fn set_flags(&mut self, flags: VTFFlags) {
+        self.get_h70_mut().flags = flags.bits();
+    }
+
+    /// Finds every contradictory combination of flags in the header.
+    pub fn flag_conflicts(&self) -> Vec<FlagConflict> {
+        self.flags().conflicts(self.get_h70().mip_count, self.depth())
+    }
+
+    /// The depth of the full-size image. Versions before 7.2 don't support volume
+    /// textures, so this is always 1 for them.
+    pub fn depth(&self) -> u16 {
+        match self.get_h72() {
+            Some(h72) if h72.depth > 1 => h72.depth,
+            _ => 1
+        }
+    }
+
+    /// The number of faces stored for each frame. Environment maps store six cubemap
+    /// faces, with versions before 7.5 storing an additional spheremap face unless the
+    /// start frame is set to 0xFFFF. All other textures have a single face.
+    pub fn face_count(&self) -> usize {
+        let root = self.get_root();
+        let header70 = self.get_h70();
+
+        if !self.flags().check(VTFFlag::ENVMAP) {
+            1
+        } else if header70.start_frame != 0xFFFF && root.version[1] < 5 {
+            7
+        } else {
+            6
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -57,7 +103,7 @@ impl HeaderRoot {
         let magic_number: [i8; 4] = unsafe{ mem::transmute(*b"VTF\0") };
         if self.type_string != magic_number {
             Err(VTFError::HeaderSignature)
-        } else if self.version[0] != 7 || match self.version[1] {1 ... 5 => false, _ => true} {
+        } else if self.version[0] != 7 || match self.version[1] {0 ... 5 => false, _ => true} {
             Err(VTFError::HeaderVersion)
         } else {
             Ok(())
@@ -70,7 +116,7 @@ impl VTFData for HeaderRoot {
         use std::mem::transmute;
 
         let mut root_header_buffer: [u8; 16] = [0; 16];
-        try!(source.read(&mut root_header_buffer).map_err(VTFLoadError::Io));
+        try!(source.read_exact(&mut root_header_buffer).map_err(VTFLoadError::Io));
         let rh: HeaderRootRaw = unsafe{ transmute(root_header_buffer) };
         let root_header = unsafe{ 
             HeaderRoot {
@@ -83,6 +129,22 @@ impl VTFData for HeaderRoot {
 
         Ok(root_header)
     }
+
+    fn save<W>(&self, dest: &mut W) -> Result<(), VTFLoadError> where W: Write {
+        use std::mem::transmute;
+
+        try!(self.verify().map_err(VTFLoadError::VTF));
+        let rh = unsafe {
+            HeaderRootRaw {
+                type_string: transmute(self.type_string),
+                version: transmute(self.version),
+                header_size: transmute(self.header_size)
+            }
+        };
+        let root_header_buffer: [u8; 16] = unsafe{ transmute(rh) };
+
+        dest.write_all(&root_header_buffer).map_err(VTFLoadError::Io)
+    }
 }
 
 #[derive(Debug)]
@@ -92,7 +154,10 @@ pub struct Header70 {
     pub flags               :c_uint,
     pub frames              :c_ushort,
     pub start_frame         :c_ushort, 
+    /// Unused. Kept so files can be written back out unchanged.
+    pub padding_0           :[u8; 4],
     pub reflectivity        :[c_float; 3],
+    pub padding_1           :[u8; 4],
     pub bump_scale          :c_float,
     pub image_format        :ImageFormat,
     pub mip_count           :u8,
@@ -117,7 +182,7 @@ impl VTFData for Header70 {
         use std::mem::transmute;
 
         let mut header70_buffer: [u8; 47] = [0; 47];
-        try!(source.read(&mut header70_buffer).map_err(VTFLoadError::Io));
+        try!(source.read_exact(&mut header70_buffer).map_err(VTFLoadError::Io));
         let h70: Header70Raw = unsafe{ transmute(header70_buffer) };
         let header70 = unsafe{ 
             Header70 {
@@ -126,7 +191,9 @@ impl VTFData for Header70 {
                 flags: transmute(h70.flags),
                 frames: transmute(h70.frames),
                 start_frame: transmute(h70.start_frame),
+                padding_0: h70.padding_0,
                 reflectivity: transmute(h70.reflectivity),
+                padding_1: h70.padding_1,
                 bump_scale: transmute(h70.bump_scale),
                 image_format: try!(ImageFormat::from_i32(transmute(h70.image_format))
                                 .ok_or(VTFLoadError::VTF(VTFError::HeaderImageFormat))),
@@ -141,6 +208,33 @@ impl VTFData for Header70 {
 
         Ok(header70)
     }
+
+    fn save<W>(&self, dest: &mut W) -> Result<(), VTFLoadError> where W: Write {
+        use std::mem::transmute;
+
+        try!(self.verify().map_err(VTFLoadError::VTF));
+        let h70 = unsafe {
+            Header70Raw {
+                width: transmute(self.width),
+                height: transmute(self.height),
+                flags: transmute(self.flags),
+                frames: transmute(self.frames),
+                start_frame: transmute(self.start_frame),
+                padding_0: self.padding_0,
+                reflectivity: transmute(self.reflectivity),
+                padding_1: self.padding_1,
+                bump_scale: transmute(self.bump_scale),
+                image_format: transmute(self.image_format as i32),
+                mip_count: self.mip_count,
+                thumbnail_format: transmute(self.thumbnail_format as i32),
+                thumbnail_width: self.thumbnail_width,
+                thumbnail_height: self.thumbnail_height
+            }
+        };
+        let header70_buffer: [u8; 47] = unsafe{ transmute(h70) };
+
+        dest.write_all(&header70_buffer).map_err(VTFLoadError::Io)
+    }
 }
 
 #[derive(Debug)]
@@ -153,7 +247,7 @@ impl VTFData for Header72 {
         use std::mem::transmute;
 
         let mut header72_buffer: [u8; 2] = [0; 2];
-        try!(source.read(&mut header72_buffer).map_err(VTFLoadError::Io));
+        try!(source.read_exact(&mut header72_buffer).map_err(VTFLoadError::Io));
         let h72: Header72Raw = unsafe{ transmute(header72_buffer) };
         let header72 = unsafe {
             Header72 {
@@ -163,10 +257,25 @@ impl VTFData for Header72 {
 
         Ok(header72)
     }
+
+    fn save<W>(&self, dest: &mut W) -> Result<(), VTFLoadError> where W: Write {
+        use std::mem::transmute;
+
+        let h72 = unsafe {
+            Header72Raw {
+                depth: transmute(self.depth)
+            }
+        };
+        let header72_buffer: [u8; 2] = unsafe{ transmute(h72) };
+
+        dest.write_all(&header72_buffer).map_err(VTFLoadError::Io)
+    }
 }
 
 #[derive(Debug)]
 pub struct Header73 {
+    /// Unused. Kept so files can be written back out unchanged.
+    pub padding         :[u8; 3],
     pub resource_count  :c_uint
 }
 
@@ -175,16 +284,31 @@ impl VTFData for Header73 {
         use std::mem::transmute;
 
         let mut header73_buffer: [u8; 7] = [0; 7];
-        try!(source.read(&mut header73_buffer).map_err(VTFLoadError::Io));
+        try!(source.read_exact(&mut header73_buffer).map_err(VTFLoadError::Io));
         let h73: Header73Raw = unsafe{ transmute(header73_buffer) };
         let header73 = unsafe {
             Header73 {
+                padding: h73.padding,
                 resource_count: transmute(h73.resource_count)
             }
         };
 
         Ok(header73)
     }
+
+    fn save<W>(&self, dest: &mut W) -> Result<(), VTFLoadError> where W: Write {
+        use std::mem::transmute;
+
+        let h73 = unsafe {
+            Header73Raw {
+                padding: self.padding,
+                resource_count: transmute(self.resource_count)
+            }
+        };
+        let header73_buffer: [u8; 7] = unsafe{ transmute(h73) };
+
+        dest.write_all(&header73_buffer).map_err(VTFLoadError::Io)
+    }
 }
 
 
@@ -199,7 +323,7 @@ impl VTFData for Resource {
         use std::mem::transmute;
 
         let mut resource_buffer: [u8; 8] = [0; 8];
-        try!(source.read(&mut resource_buffer).map_err(VTFLoadError::Io));
+        try!(source.read_exact(&mut resource_buffer).map_err(VTFLoadError::Io));
         let rsrc: ResourceRaw = unsafe{ transmute(resource_buffer) };
         let resource = unsafe {
             Resource {
@@ -211,12 +335,28 @@ impl VTFData for Resource {
 
         Ok(resource)
     }
+
+    fn save<W>(&self, dest: &mut W) -> Result<(), VTFLoadError> where W: Write {
+        use std::mem::transmute;
+
+        let rsrc = unsafe {
+            ResourceRaw {
+                id: transmute(self.id.to_u32()),
+                data: transmute(self.data)
+            }
+        };
+        let resource_buffer: [u8; 8] = unsafe{ transmute(rsrc) };
+
+        dest.write_all(&resource_buffer).map_err(VTFLoadError::Io)
+    }
 }
 
 pub trait VTFData 
     where Self: Sized {
 
     fn load<R>(source: &mut R) -> Result<Self, VTFLoadError> where R: Read;
+    /// Writes the data in the same layout `load` reads it from.
+    fn save<W>(&self, dest: &mut W) -> Result<(), VTFLoadError> where W: Write;
 }
 
 
@@ -302,17 +442,45 @@ fn make_vtf_rsrc_idf(a: u8, b: u8, c: u8, f: u8) -> i32 {
 */
 
 
-#[derive(Debug)]
-#[repr(u32)]
+/// Set in the high byte of a resource ID if the resource's data is stored inline in the
+/// resource table, rather than in a separate chunk.
+pub const RSRC_NO_DATA_CHUNK: u32 = 0x02000000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ResourceID {
-    LegacyLowResImage       = 0x01, //make_vtf_rsrc_id(0x01, 0, 0)
-    LegacyImage             = 0x30, //make_vtf_rsrc_id(0x30, 0, 0)
-    Sheet                   = 0x10, //make_vtf_rsrc_id(0x10, 0, 0)
-    Crc                     = 0x02435243, //make_vtf_rsrc_idf('C', 'R', 'C', RSRC_NO_DATA_CHUNK)
-    TextureLODSettings      = 0x02444f4c, //make_vtf_rsrc_idf('L', 'O', 'D', RSRC_NO_DATA_CHUNK)
-    TextureSettingsEx       = 0x024f5354, //make_vtf_rsrc_idf('T', 'S', 'O', RSRC_NO_DATA_CHUNK)
-    KeyValueData            = 0x44564b, //make_vtf_rsrc_id('K', 'V', 'D')
-    MaxDictionaryEntries    = 32, //32
+    LegacyLowResImage,      //make_vtf_rsrc_id(0x01, 0, 0)
+    LegacyImage,            //make_vtf_rsrc_id(0x30, 0, 0)
+    Sheet,                  //make_vtf_rsrc_id(0x10, 0, 0)
+    Crc,                    //make_vtf_rsrc_idf('C', 'R', 'C', RSRC_NO_DATA_CHUNK)
+    TextureLODSettings,     //make_vtf_rsrc_idf('L', 'O', 'D', RSRC_NO_DATA_CHUNK)
+    TextureSettingsEx,      //make_vtf_rsrc_idf('T', 'S', 'O', RSRC_NO_DATA_CHUNK)
+    KeyValueData,           //make_vtf_rsrc_id('K', 'V', 'D')
+    MaxDictionaryEntries,   //32
+    /// A resource this crate doesn't know about, along with its raw ID
+    Unknown(u32)
+}
+
+impl ResourceID {
+    /// Gets the raw ID as stored in the resource table.
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            &ResourceID::LegacyLowResImage      => 0x01,
+            &ResourceID::LegacyImage            => 0x30,
+            &ResourceID::Sheet                  => 0x10,
+            &ResourceID::Crc                    => 0x02435243,
+            &ResourceID::TextureLODSettings     => 0x02444f4c,
+            &ResourceID::TextureSettingsEx      => 0x024f5354,
+            &ResourceID::KeyValueData           => 0x44564b,
+            &ResourceID::MaxDictionaryEntries   => 32,
+            &ResourceID::Unknown(id)            => id
+        }
+    }
+
+    /// Whether the resource's data is stored in a separate chunk, with the resource
+    /// table holding its offset. Otherwise, the data is stored inline in the table.
+    pub fn has_data_chunk(&self) -> bool {
+        self.to_u32() & RSRC_NO_DATA_CHUNK == 0
+    }
 }
 
 impl FromPrimitive for ResourceID {
@@ -330,6 +498,7 @@ impl FromPrimitive for ResourceID {
             0x024f5354      => Some(ResourceID::TextureSettingsEx),
             0x44564b        => Some(ResourceID::KeyValueData),
             32              => Some(ResourceID::MaxDictionaryEntries),
+            n if n <= 0xFFFFFFFF => Some(ResourceID::Unknown(n as u32)),
             _               => None
         }
     }
@@ -471,6 +640,90 @@ impl FromPrimitive for ImageFormat {
     }
 }
 
+impl ImageFormat {
+    /// Whether or not the format is stored as 4x4 compressed blocks
+    pub fn is_compressed(&self) -> bool {
+        match *self {
+            ImageFormat::DXT1 |
+            ImageFormat::DXT1_ONEBITALPHA |
+            ImageFormat::DXT3 |
+            ImageFormat::DXT5 |
+            ImageFormat::ATI1N |
+            ImageFormat::ATI2N => true,
+            _ => false
+        }
+    }
+
+    /// The number of bytes in each 4x4 block for compressed formats, or in each pixel
+    /// for uncompressed formats. Returns `None` for `COUNT` and `NONE`.
+    pub fn block_size(&self) -> Option<u32> {
+        match *self {
+            ImageFormat::I8 |
+            ImageFormat::P8 |
+            ImageFormat::A8             => Some(1),
+
+            ImageFormat::RGB565 |
+            ImageFormat::IA88 |
+            ImageFormat::BGR565 |
+            ImageFormat::BGRX5551 |
+            ImageFormat::BGRA4444 |
+            ImageFormat::BGRA5551 |
+            ImageFormat::UV88 |
+            ImageFormat::NV_DST16 |
+            ImageFormat::ATI_DST16      => Some(2),
+
+            ImageFormat::RGB888 |
+            ImageFormat::BGR888 |
+            ImageFormat::RGB888_BLUESCREEN |
+            ImageFormat::BGR888_BLUESCREEN => Some(3),
+
+            ImageFormat::RGBA8888 |
+            ImageFormat::ABGR8888 |
+            ImageFormat::ARGB8888 |
+            ImageFormat::BGRA8888 |
+            ImageFormat::BGRX8888 |
+            ImageFormat::UVWQ8888 |
+            ImageFormat::UVLX8888 |
+            ImageFormat::R32F |
+            ImageFormat::NV_DST24 |
+            ImageFormat::NV_INTZ |
+            ImageFormat::NV_RAWZ |
+            ImageFormat::ATI_DST24 |
+            ImageFormat::NV_NULL        => Some(4),
+
+            ImageFormat::RGBA16161616F |
+            ImageFormat::RGBA16161616   => Some(8),
+            ImageFormat::RGB323232F     => Some(12),
+            ImageFormat::RGBA32323232F  => Some(16),
+
+            ImageFormat::DXT1 |
+            ImageFormat::DXT1_ONEBITALPHA |
+            ImageFormat::ATI1N          => Some(8),
+            ImageFormat::DXT3 |
+            ImageFormat::DXT5 |
+            ImageFormat::ATI2N          => Some(16),
+
+            ImageFormat::COUNT |
+            ImageFormat::NONE           => None
+        }
+    }
+
+    /// The number of bytes a single image of the given dimensions takes up in this
+    /// format. Compressed images are padded out to whole 4x4 blocks.
+    pub fn data_size(&self, width: u16, height: u16) -> Option<u64> {
+        let (width, height) = (width as u64, height as u64);
+
+        self.block_size().map(|block_size| {
+            let block_size = block_size as u64;
+            if self.is_compressed() {
+                ((width + 3) / 4) * ((height + 3) / 4) * block_size
+            } else {
+                width * height * block_size
+            }
+        })
+    }
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
@@ -518,4 +771,211 @@ pub enum VTFFlag {
  
     UNUSED_40000000    = 0x40000000,
     UNUSED_80000000    = 0x80000000,
-}
\ No newline at end of file
+}
+
+impl VTFFlag {
+    /// Gets the flag for a single bit. Returns `None` if more or less than one bit is set.
+    pub fn from_bit(bit: u32) -> Option<VTFFlag> {
+        match bit {
+            0x00000001 => Some(VTFFlag::POINTSAMPLE),
+            0x00000002 => Some(VTFFlag::TRILINEAR),
+            0x00000004 => Some(VTFFlag::CLAMPS),
+            0x00000008 => Some(VTFFlag::CLAMPT),
+            0x00000010 => Some(VTFFlag::ANISOTROPIC),
+            0x00000020 => Some(VTFFlag::HINT_DXT5),
+            0x00000040 => Some(VTFFlag::PWL_CORRECTED),
+            0x00000080 => Some(VTFFlag::NORMAL),
+            0x00000100 => Some(VTFFlag::NOMIP),
+            0x00000200 => Some(VTFFlag::NOLOD),
+            0x00000400 => Some(VTFFlag::ALL_MIPS),
+            0x00000800 => Some(VTFFlag::PROCEDURAL),
+            0x00001000 => Some(VTFFlag::ONEBITALPHA),
+            0x00002000 => Some(VTFFlag::EIGHTBITALPHA),
+            0x00004000 => Some(VTFFlag::ENVMAP),
+            0x00008000 => Some(VTFFlag::RENDERTARGET),
+            0x00010000 => Some(VTFFlag::DEPTHRENDERTARGET),
+            0x00020000 => Some(VTFFlag::NODEBUGOVERRIDE),
+            0x00040000 => Some(VTFFlag::SINGLECOPY),
+            0x00080000 => Some(VTFFlag::PRE_SRGB),
+            0x00100000 => Some(VTFFlag::UNUSED0),
+            0x00200000 => Some(VTFFlag::UNUSED1),
+            0x00400000 => Some(VTFFlag::UNUSED2),
+            0x00800000 => Some(VTFFlag::NODEPTHBUFFER),
+            0x01000000 => Some(VTFFlag::UNUSED3),
+            0x02000000 => Some(VTFFlag::CLAMPU),
+            0x04000000 => Some(VTFFlag::VERTEXTEXTURE),
+            0x08000000 => Some(VTFFlag::SSBUMP),
+            0x10000000 => Some(VTFFlag::UNUSED4),
+            0x20000000 => Some(VTFFlag::BORDER),
+            0x40000000 => Some(VTFFlag::UNUSED_40000000),
+            0x80000000 => Some(VTFFlag::UNUSED_80000000),
+            _          => None
+        }
+    }
+}
+
+/// A set of `VTFFlag`s, stored as the raw bitfield from the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VTFFlags {
+    bits: u32
+}
+
+impl VTFFlags {
+    pub fn empty() -> VTFFlags {
+        VTFFlags {bits: 0}
+    }
+
+    pub fn from_bits(bits: u32) -> VTFFlags {
+        VTFFlags {bits: bits}
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    pub fn check(&self, flag: VTFFlag) -> bool {
+        self.bits & flag as u32 != 0
+    }
+
+    pub fn set(&mut self, flag: VTFFlag) {
+        self.bits |= flag as u32;
+    }
+
+    pub fn clear(&mut self, flag: VTFFlag) {
+        self.bits &= !(flag as u32);
+    }
+
+    /// Sets the flag if `value` is true, and clears it otherwise.
+    pub fn set_to(&mut self, flag: VTFFlag, value: bool) {
+        match value {
+            true => self.set(flag),
+            false => self.clear(flag)
+        }
+    }
+
+    /// Iterates over every set flag, from the lowest bit to the highest.
+    pub fn iter(&self) -> VTFFlagIter {
+        VTFFlagIter {bits: self.bits, bit: 0}
+    }
+
+    /// Finds every pair of flags that contradict each other, or that contradict the
+    /// rest of the header.
+    pub fn conflicts(&self, mip_count: u8, depth: u16) -> Vec<FlagConflict> {
+        let mut conflicts = Vec::new();
+
+        if self.check(VTFFlag::NOMIP) && mip_count > 1 {
+            conflicts.push(FlagConflict::NoMipWithMips);
+        }
+        if self.check(VTFFlag::NOMIP) && self.check(VTFFlag::ALL_MIPS) {
+            conflicts.push(FlagConflict::NoMipAndAllMips);
+        }
+        if self.check(VTFFlag::ONEBITALPHA) && self.check(VTFFlag::EIGHTBITALPHA) {
+            conflicts.push(FlagConflict::OneBitAndEightBitAlpha);
+        }
+        if self.check(VTFFlag::POINTSAMPLE) && (self.check(VTFFlag::TRILINEAR) || self.check(VTFFlag::ANISOTROPIC)) {
+            conflicts.push(FlagConflict::PointSampleAndFiltered);
+        }
+        if self.check(VTFFlag::ENVMAP) && depth > 1 {
+            conflicts.push(FlagConflict::EnvMapVolume);
+        }
+        if self.check(VTFFlag::NORMAL) && self.check(VTFFlag::SSBUMP) {
+            conflicts.push(FlagConflict::NormalAndSSBump);
+        }
+
+        conflicts
+    }
+}
+
+impl From<VTFFlag> for VTFFlags {
+    fn from(flag: VTFFlag) -> VTFFlags {
+        VTFFlags {bits: flag as u32}
+    }
+}
+
+impl<'a> IntoIterator for &'a VTFFlags {
+    type Item = VTFFlag;
+    type IntoIter = VTFFlagIter;
+
+    fn into_iter(self) -> VTFFlagIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the flags set in a `VTFFlags`.
+#[derive(Debug, Clone)]
+pub struct VTFFlagIter {
+    bits: u32,
+    bit: u32
+}
+
+impl Iterator for VTFFlagIter {
+    type Item = VTFFlag;
+
+    fn next(&mut self) -> Option<VTFFlag> {
+        while self.bit < 32 {
+            let bit = 1 << self.bit;
+            self.bit += 1;
+
+            if self.bits & bit != 0 {
+                return VTFFlag::from_bit(bit);
+            }
+        }
+
+        None
+    }
+}
+
+/// A contradictory combination of flags, found by `VTFFlags::conflicts`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagConflict {
+    /// `NOMIP` is set, but the header lists more than one mip
+    NoMipWithMips,
+    /// `NOMIP` and `ALL_MIPS` are both set
+    NoMipAndAllMips,
+    /// `ONEBITALPHA` and `EIGHTBITALPHA` are both set
+    OneBitAndEightBitAlpha,
+    /// `POINTSAMPLE` is set along with `TRILINEAR` or `ANISOTROPIC`
+    PointSampleAndFiltered,
+    /// `ENVMAP` is set on a volume texture
+    EnvMapVolume,
+    /// `NORMAL` and `SSBUMP` are both set
+    NormalAndSSBump
+}
+
+/// The faces of an environment map, in the order they are stored in the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+    /// +X
+    Right = 0,
+    /// -X
+    Left,
+    /// +Y
+    Back,
+    /// -Y
+    Front,
+    /// +Z
+    Up,
+    /// -Z
+    Down,
+    /// Spheremap, only present in environment maps from versions before 7.5
+    Sphere
+}
+
+impl CubeFace {
+    pub fn from_index(index: usize) -> Option<CubeFace> {
+        match index {
+            0 => Some(CubeFace::Right),
+            1 => Some(CubeFace::Left),
+            2 => Some(CubeFace::Back),
+            3 => Some(CubeFace::Front),
+            4 => Some(CubeFace::Up),
+            5 => Some(CubeFace::Down),
+            6 => Some(CubeFace::Sphere),
+            _ => None
+        }
+    }
+}
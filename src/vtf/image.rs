@@ -1,7 +1,16 @@
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
+
+use std::cmp;
+use std::borrow::Cow;
 
 use super::format::ImageFormat;
+use super::dxt;
+pub use super::dxt::DxtQuality;
+use super::error::{VTFLoadError, VTFError};
+use ::image::{ImageBuffer, RgbaImage, DynamicImage};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 #[derive(Debug, Clone)]
 pub struct Rgb565 {
@@ -22,15 +31,10 @@ impl Rgb565 {
 
 impl ColorType for Rgb565 {
     fn to_rgb8(&self) -> Rgb8 {
-        // Conversion factor for 5-bit to 8-bit
-        const CONV58: f32 = 255.0/31.0;
-        // Conversion factor for 6-bit to 8-bit
-        const CONV68: f32 = 255.0/63.0;
-
         Rgb8 {
-            red: (self.red as f32 * CONV58) as u8,
-            green: (self.green as f32 * CONV68) as u8,
-            blue: (self.blue as f32 * CONV58) as u8,
+            red: expand_5bit(self.red),
+            green: expand_6bit(self.green),
+            blue: expand_5bit(self.blue),
         }
     }
 
@@ -176,443 +180,1892 @@ impl ColorType for Bgra8 {
     }
 }
 
-pub trait ColorType where Self: Sized {
-    fn to_rgb8(&self) -> Rgb8;
-    fn from_rgb888(rgb: Rgb8) -> Self;
+impl ColorType for Rgb8 {
+    fn to_rgb8(&self) -> Rgb8 {
+        self.clone()
+    }
 
-    fn to_rgba8(&self) -> Rgba8;
-    fn from_rgba8888(rgba: Rgba8) -> Self;
+    fn from_rgb888(rgb: Rgb8) -> Rgb8 {
+        rgb
+    }
+
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.red,
+            green: self.green,
+            blue: self.blue,
+            alpha: 255
+        }
+    }
+
+    fn from_rgba8888(rgba: Rgba8) -> Rgb8 {
+        Rgb8 {
+            red: rgba.red,
+            green: rgba.green,
+            blue: rgba.blue
+        }
+    }
+}
+
+impl ColorType for Rgba8 {
+    fn to_rgba8(&self) -> Rgba8 {
+        self.clone()
+    }
+
+    fn from_rgba8888(rgba: Rgba8) -> Rgba8 {
+        rgba
+    }
 }
 
 #[derive(Debug, Clone)]
-pub enum VTFImageWrapper {
-    DXT1 (Dxt1),
-    DXT3 (Dxt3),
-    DXT5 (Dxt5),
-    BGR888 (Bgr8Image),
-    BGRA8888 (Bgra8Image)
+#[repr(C)]
+pub struct Abgr8 {
+    pub alpha: u8,
+    pub blue: u8,
+    pub green: u8,
+    pub red: u8
 }
 
-impl VTFImageWrapper {
-    pub fn load<R>(source: &mut R, width: u16, height: u16, format: ImageFormat) -> Result<VTFImageWrapper, io::Error> where R: Read {
-        match format {
-            ImageFormat::DXT1 => Ok(VTFImageWrapper::DXT1(try!(Dxt1::load(&mut *source, width, height)))),
-            ImageFormat::DXT3 => Ok(VTFImageWrapper::DXT3(try!(Dxt3::load(&mut *source, width, height)))),
-            ImageFormat::DXT5 => Ok(VTFImageWrapper::DXT5(try!(Dxt5::load(&mut *source, width, height)))),
-            ImageFormat::BGR888 => Ok(VTFImageWrapper::BGR888(try!(Bgr8Image::load(&mut *source, width, height)))),
-            ImageFormat::BGRA8888 => Ok(VTFImageWrapper::BGRA8888(try!(Bgra8Image::load(&mut *source, width, height)))),
-            _ => panic!("Unsupported image format given!")
+impl ColorType for Abgr8 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.red,
+            green: self.green,
+            blue: self.blue,
+            alpha: self.alpha
         }
     }
 
-    pub fn expose(&self) -> &VTFImage {
-        match self {
-            &VTFImageWrapper::DXT1(ref im) => im,
-            &VTFImageWrapper::DXT3(ref im) => im,
-            &VTFImageWrapper::DXT5(ref im) => im,
-            &VTFImageWrapper::BGR888(ref im) => im,
-            &VTFImageWrapper::BGRA8888(ref im) => im
+    fn from_rgba8888(rgba: Rgba8) -> Abgr8 {
+        Abgr8 {
+            alpha: rgba.alpha,
+            blue: rgba.blue,
+            green: rgba.green,
+            red: rgba.red
         }
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct Dxt1 {
-    data: Vec<(u16, u16, [u8; 4])>,
-    width: u16,
-    height: u16
+#[repr(C)]
+pub struct Argb8 {
+    pub alpha: u8,
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8
 }
 
-impl Dxt1 {
-    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<Dxt1, io::Error> where R: Read {
-        use std::mem::transmute;
-
-        // Internally to the VTF file format, there are no images that are
-        // smaller than 4x4. This corrects for that. 
-        let mut width = width;
-        let mut height = height;
-        if width < 4 {
-            width = 4;
-        }
-        if height < 4 {
-            height = 4;
+impl ColorType for Argb8 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.red,
+            green: self.green,
+            blue: self.blue,
+            alpha: self.alpha
         }
-        let (width, height) = (width, height);
-
+    }
 
-        let pix_count = width as usize * height as usize;
-        
-        let mut data: Vec<(u16, u16, [u8; 4])> = Vec::with_capacity(pix_count / 16);
+    fn from_rgba8888(rgba: Rgba8) -> Argb8 {
+        Argb8 {
+            alpha: rgba.alpha,
+            red: rgba.red,
+            green: rgba.green,
+            blue: rgba.blue
+        }
+    }
+}
 
-        {
-            let mut data_buffer: [u8; 8] = [0; 8];
+/// Blue, green and red with an unused fourth byte
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct Bgrx8 {
+    pub blue: u8,
+    pub green: u8,
+    pub red: u8,
+    pub unused: u8
+}
 
-            let mut index = 0;
-            while index < pix_count / 2 {
-                try!(source.read(&mut data_buffer));
-                data.push(unsafe{ transmute(data_buffer) });
-                index += 8;
-            }
+impl ColorType for Bgrx8 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.red,
+            green: self.green,
+            blue: self.blue,
+            alpha: 255
         }
+    }
 
-        Ok(Dxt1 {data: data, width: width, height: height})
+    fn from_rgba8888(rgba: Rgba8) -> Bgrx8 {
+        Bgrx8 {
+            blue: rgba.blue,
+            green: rgba.green,
+            red: rgba.red,
+            unused: 0
+        }
     }
 }
 
-impl VTFImage for Dxt1 {
-    fn to_rgb8(&self) -> Vec<Rgb8> {
-
-        let pix_count = self.width as usize * self.height as usize;
-        let mut rgb: Vec<Rgb8> = Vec::with_capacity(pix_count);
-        unsafe{ rgb.set_len(pix_count) };
-
-        let mut chunk_offset = 0;
-        for c in &self.data {
-            let c0 = Rgb565::load(c.0).to_rgb8();
-            let c1 = Rgb565::load(c.1).to_rgb8();
-            let c2 = interp_color(&c0, &c1, true);
-            let c3 = interp_color(&c0, &c1, false); 
-
-
-            let color_data: [u8; 16] = [c.2[0] & 3, c.2[0] >> 2 & 3, c.2[0] >> 4 & 3, c.2[0] >> 6 & 3,
-                                        c.2[1] & 3, c.2[1] >> 2 & 3, c.2[1] >> 4 & 3, c.2[1] >> 6 & 3,
-                                        c.2[2] & 3, c.2[2] >> 2 & 3, c.2[2] >> 4 & 3, c.2[2] >> 6 & 3,
-                                        c.2[3] & 3, c.2[3] >> 2 & 3, c.2[3] >> 4 & 3, c.2[3] >> 6 & 3];
-
-            // The index for adding data to the chunk array
-            let mut index: usize = 0;
-            // The line of the chunk to write data to
-            let mut cline = 0;
-            for co in &color_data {
-                match *co {
-                    0 => rgb[chunk_offset + index + (self.width*cline) as usize] = c0.clone(),
-                    1 => rgb[chunk_offset + index + (self.width*cline) as usize] = c1.clone(),
-                    2 => rgb[chunk_offset + index + (self.width*cline) as usize] = c2.clone(),
-                    3 => rgb[chunk_offset + index + (self.width*cline) as usize] = c3.clone(),
-                    _ => unreachable!()
-                }
+/// Luminance
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct I8 {
+    pub luminance: u8
+}
 
-                index += 1;
-                if index > 3 {
-                    index = 0;
-                    cline += 1;
-                }
-            }
-            
-            chunk_offset += 4;
-            let wusize = self.width as usize;
-            if chunk_offset % wusize == 0 && chunk_offset >= wusize {
-                chunk_offset += wusize * 3;
-            }
+impl ColorType for I8 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.luminance,
+            green: self.luminance,
+            blue: self.luminance,
+            alpha: 255
         }
+    }
 
-        rgb
+    fn from_rgba8888(rgba: Rgba8) -> I8 {
+        I8 {luminance: luminance(rgba.red, rgba.green, rgba.blue)}
     }
+}
 
-    fn to_rgba8(&self) -> Vec<Rgba8> {
-        let rgb = self.to_rgb8();
+/// Luminance and alpha
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct Ia8 {
+    pub luminance: u8,
+    pub alpha: u8
+}
 
-        let mut rgba: Vec<Rgba8> = Vec::with_capacity(rgb.len());
-        for c in &rgb {
-            rgba.push(Rgba8{red: c.red, green: c.green, blue: c.blue, alpha: 255});
+impl ColorType for Ia8 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.luminance,
+            green: self.luminance,
+            blue: self.luminance,
+            alpha: self.alpha
         }
+    }
 
-        rgba
+    fn from_rgba8888(rgba: Rgba8) -> Ia8 {
+        Ia8 {
+            luminance: luminance(rgba.red, rgba.green, rgba.blue),
+            alpha: rgba.alpha
+        }
     }
+}
 
-    fn get_width(&self) -> u16 {
-        self.width
+/// Alpha only. Converts to black with the stored alpha.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct A8 {
+    pub alpha: u8
+}
+
+impl ColorType for A8 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: 0,
+            green: 0,
+            blue: 0,
+            alpha: self.alpha
+        }
     }
 
-    fn get_height(&self) -> u16 {
-        self.height
+    fn from_rgba8888(rgba: Rgba8) -> A8 {
+        A8 {alpha: rgba.alpha}
     }
 }
 
+/// Two channel DuDv/normal map data. U and V are mapped to red and green.
 #[derive(Debug, Clone)]
-pub struct Dxt3 {
-    data: Vec<([u8; 8], u16, u16, [u8; 4])>,
-    width: u16,
-    height: u16
+#[repr(C)]
+pub struct Uv8 {
+    pub u: u8,
+    pub v: u8
 }
 
-impl Dxt3 {
-    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<Dxt3, io::Error> where R: Read {
-        use std::mem::transmute;
-
-        // Internally to the VTF file format, there are no images that are
-        // smaller than 4x4. This corrects for that. 
-        let mut width = width;
-        let mut height = height;
-        if width < 4 {
-            width = 4;
-        }
-        if height < 4 {
-            height = 4;
+impl ColorType for Uv8 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.u,
+            green: self.v,
+            blue: 0,
+            alpha: 255
         }
-        let (width, height) = (width, height);
-
+    }
 
-        let pix_count = width as usize * height as usize;
-        
-        // Capacity is pix_count / 16 as that is the number of pixel chunks that the image gets
-        // compressed into. The reason that is the number is that each pixel chunk contains
-        // 16 pixels.
-        let mut data: Vec<([u8; 8], u16, u16, [u8; 4])> = Vec::with_capacity(pix_count / 16);
-
-        {
-            let mut data_buffer: [u8; 16] = [0; 16];
-
-            let mut index = 0;
-            while index < pix_count {
-                try!(source.read(&mut data_buffer));
-                data.push(unsafe{ transmute(data_buffer) });
-                index += 16;
-            }
+    fn from_rgba8888(rgba: Rgba8) -> Uv8 {
+        Uv8 {
+            u: rgba.red,
+            v: rgba.green
         }
-
-        Ok(Dxt3 {data: data, width: width, height: height})
     }
 }
 
-impl VTFImage for Dxt3 {
-    fn to_rgb8(&self) -> Vec<Rgb8> {
-        let rgba = self.to_rgba8();
+/// Four channel DuDv/normal map data. U, V, W and Q are mapped to red, green, blue
+/// and alpha.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct Uvwq8 {
+    pub u: u8,
+    pub v: u8,
+    pub w: u8,
+    pub q: u8
+}
 
-        let mut rgb: Vec<Rgb8> = Vec::with_capacity(rgba.len());
-        for c in &rgba {
-            rgb.push(Rgb8{red: c.red, green: c.green, blue: c.blue});
+impl ColorType for Uvwq8 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.u,
+            green: self.v,
+            blue: self.w,
+            alpha: self.q
         }
-
-        rgb
     }
 
-    fn to_rgba8(&self) -> Vec<Rgba8> {
-
-        let pix_count = self.width as usize * self.height as usize;
-        let mut rgba: Vec<Rgba8> = Vec::with_capacity(pix_count);
-        unsafe{ rgba.set_len(pix_count) };
+    fn from_rgba8888(rgba: Rgba8) -> Uvwq8 {
+        Uvwq8 {
+            u: rgba.red,
+            v: rgba.green,
+            w: rgba.blue,
+            q: rgba.alpha
+        }
+    }
+}
 
-        let mut chunk_offset = 0;
-        for c in &self.data {
-            // Compute color data
-            let c0 = Rgb565::load(c.1).to_rgb8();
-            let c1 = Rgb565::load(c.2).to_rgb8();
-            let c2 = interp_color(&c0, &c1, true);
-            let c3 = interp_color(&c0, &c1, false); 
-
-            let color_data: [u8; 16] = [c.3[0] & 3, c.3[0] >> 2 & 3, c.3[0] >> 4 & 3, c.3[0] >> 6 & 3,
-                                        c.3[1] & 3, c.3[1] >> 2 & 3, c.3[1] >> 4 & 3, c.3[1] >> 6 & 3,
-                                        c.3[2] & 3, c.3[2] >> 2 & 3, c.3[2] >> 4 & 3, c.3[2] >> 6 & 3,
-                                        c.3[3] & 3, c.3[3] >> 2 & 3, c.3[3] >> 4 & 3, c.3[3] >> 6 & 3];
-
-
-            let alpha_data: [u8; 16] = [c.0[0] & 15, c.0[0] >> 4 & 15, c.0[1] & 15, c.0[1] >> 4 & 15,
-                                        c.0[2] & 15, c.0[2] >> 4 & 15, c.0[3] & 15, c.0[3] >> 4 & 15,
-                                        c.0[4] & 15, c.0[4] >> 4 & 15, c.0[5] & 15, c.0[5] >> 4 & 15,
-                                        c.0[6] & 15, c.0[6] >> 4 & 15, c.0[7] & 15, c.0[7] >> 4 & 15];
-
-            // The index for adding data to the chunk array
-            let mut index: usize = 0;
-            // The line of the chunk to write data to
-            let mut cline = 0;
-            let mut i = 0;
-            while i < 16 {
-                let rgba_offset = chunk_offset + index + (self.width*cline) as usize;
-                match color_data[i] {
-                    0 => rgba[rgba_offset] = Rgba8{red: c0.red, green: c0.green, blue: c0.blue, alpha: alpha_data[i] * 17},
-                    1 => rgba[rgba_offset] = Rgba8{red: c1.red, green: c1.green, blue: c1.blue, alpha: alpha_data[i] * 17},
-                    2 => rgba[rgba_offset] = Rgba8{red: c2.red, green: c2.green, blue: c2.blue, alpha: alpha_data[i] * 17},
-                    3 => rgba[rgba_offset] = Rgba8{red: c3.red, green: c3.green, blue: c3.blue, alpha: alpha_data[i] * 17},
-                    _ => unreachable!()
-                }
-                
+/// Four channel DuDv/normal map data with a luminance channel. U, V, L and X are
+/// mapped to red, green, blue and alpha.
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct Uvlx8 {
+    pub u: u8,
+    pub v: u8,
+    pub l: u8,
+    pub x: u8
+}
 
-                index += 1;
-                if index > 3 {
-                    index = 0;
-                    cline += 1;
-                }
-                i += 1;
-            }
-            
-            chunk_offset += 4;
-            let wusize = self.width as usize;
-            if chunk_offset % wusize == 0 && chunk_offset >= wusize {
-                chunk_offset += wusize * 3;
-            }
+impl ColorType for Uvlx8 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.u,
+            green: self.v,
+            blue: self.l,
+            alpha: self.x
         }
+    }
 
-        rgba
+    fn from_rgba8888(rgba: Rgba8) -> Uvlx8 {
+        Uvlx8 {
+            u: rgba.red,
+            v: rgba.green,
+            l: rgba.blue,
+            x: rgba.alpha
+        }
     }
+}
 
-    fn get_width(&self) -> u16 {
-        self.width
+/// 16-bit color with blue stored in the lowest five bits and red in the highest
+/// five bits.
+#[derive(Debug, Clone)]
+pub struct Bgr565 {
+    pub blue: u8, // Five bits with three bits of padding
+    pub green: u8, // Six bits with two bits of padding
+    pub red: u8 // Five bits with three bits of padding
+}
+
+impl ColorType for Bgr565 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: expand_5bit(self.red),
+            green: expand_6bit(self.green),
+            blue: expand_5bit(self.blue),
+            alpha: 255
+        }
     }
 
-    fn get_height(&self) -> u16 {
-        self.height
+    fn from_rgba8888(rgba: Rgba8) -> Bgr565 {
+        Bgr565 {
+            blue: rgba.blue >> 3,
+            green: rgba.green >> 2,
+            red: rgba.red >> 3
+        }
     }
 }
 
+/// 16-bit color with five bits for each of blue, green and red, and an unused high bit.
 #[derive(Debug, Clone)]
-pub struct Dxt5 {
-    data: Vec<(u8, u8, [u8; 6], u16, u16, [u8; 4])>,
-    width: u16,
-    height: u16
+pub struct Bgrx5551 {
+    pub blue: u8,
+    pub green: u8,
+    pub red: u8,
+    pub unused: bool
 }
 
-impl Dxt5 {
-    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<Dxt5, io::Error> where R: Read {
-        use std::mem::transmute;
-
-        // Internally to the VTF file format, there are no images that are
-        // smaller than 4x4. This corrects for that. 
-        let mut width = width;
-        let mut height = height;
-        if width < 4 {
-            width = 4;
+impl ColorType for Bgrx5551 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: expand_5bit(self.red),
+            green: expand_5bit(self.green),
+            blue: expand_5bit(self.blue),
+            alpha: 255
         }
-        if height < 4 {
-            height = 4;
+    }
+
+    fn from_rgba8888(rgba: Rgba8) -> Bgrx5551 {
+        Bgrx5551 {
+            blue: rgba.blue >> 3,
+            green: rgba.green >> 3,
+            red: rgba.red >> 3,
+            unused: false
         }
-        let (width, height) = (width, height);
+    }
+}
 
+/// 16-bit color with five bits for each of blue, green and red, and a single alpha bit.
+#[derive(Debug, Clone)]
+pub struct Bgra5551 {
+    pub blue: u8,
+    pub green: u8,
+    pub red: u8,
+    pub alpha: bool
+}
 
-        let pix_count = width as usize * height as usize;
-        
-        // Capacity is pix_count / 16 as that is the number of pixel chunks that the image gets
-        // compressed into. The reason that is the number is that each pixel chunk contains
-        // 16 pixels.
-        let mut data: Vec<(u8, u8, [u8; 6], u16, u16, [u8; 4])> = Vec::with_capacity(pix_count / 16);
-
-        {
-            let mut data_buffer: [u8; 16] = [0; 16];
-
-            let mut index = 0;
-            while index < pix_count {
-                try!(source.read(&mut data_buffer));
-                data.push(unsafe{ transmute(data_buffer) });
-                index += 16;
-            }
+impl ColorType for Bgra5551 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: expand_5bit(self.red),
+            green: expand_5bit(self.green),
+            blue: expand_5bit(self.blue),
+            alpha: if self.alpha {255} else {0}
         }
+    }
 
-        Ok(Dxt5 {data: data, width: width, height: height})
+    fn from_rgba8888(rgba: Rgba8) -> Bgra5551 {
+        Bgra5551 {
+            blue: rgba.blue >> 3,
+            green: rgba.green >> 3,
+            red: rgba.red >> 3,
+            alpha: rgba.alpha >= 128
+        }
     }
 }
 
-impl VTFImage for Dxt5 {
-    fn to_rgb8(&self) -> Vec<Rgb8> {
-        let rgba = self.to_rgba8();
+/// 16-bit color with four bits for each channel.
+#[derive(Debug, Clone)]
+pub struct Bgra4444 {
+    pub blue: u8,
+    pub green: u8,
+    pub red: u8,
+    pub alpha: u8
+}
 
-        let mut rgb: Vec<Rgb8> = Vec::with_capacity(rgba.len());
-        for c in &rgba {
-            rgb.push(Rgb8{red: c.red, green: c.green, blue: c.blue});
+impl ColorType for Bgra4444 {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.red * 17,
+            green: self.green * 17,
+            blue: self.blue * 17,
+            alpha: self.alpha * 17
         }
+    }
 
-        rgb
+    fn from_rgba8888(rgba: Rgba8) -> Bgra4444 {
+        Bgra4444 {
+            blue: rgba.blue >> 4,
+            green: rgba.green >> 4,
+            red: rgba.red >> 4,
+            alpha: rgba.alpha >> 4
+        }
     }
+}
 
+/// Red, green and blue, with pure blue treated as fully transparent
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct Rgb8Bluescreen {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8
+}
+
+impl ColorType for Rgb8Bluescreen {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.red,
+            green: self.green,
+            blue: self.blue,
+            alpha: bluescreen_alpha(self.red, self.green, self.blue)
+        }
+    }
+
+    fn from_rgba8888(rgba: Rgba8) -> Rgb8Bluescreen {
+        match rgba.alpha {
+            0 => Rgb8Bluescreen {red: 0, green: 0, blue: 255},
+            _ => Rgb8Bluescreen {red: rgba.red, green: rgba.green, blue: rgba.blue}
+        }
+    }
+}
+
+/// Blue, green and red, with pure blue treated as fully transparent
+#[derive(Debug, Clone)]
+#[repr(C)]
+pub struct Bgr8Bluescreen {
+    pub blue: u8,
+    pub green: u8,
+    pub red: u8
+}
+
+impl ColorType for Bgr8Bluescreen {
+    fn to_rgba8(&self) -> Rgba8 {
+        Rgba8 {
+            red: self.red,
+            green: self.green,
+            blue: self.blue,
+            alpha: bluescreen_alpha(self.red, self.green, self.blue)
+        }
+    }
+
+    fn from_rgba8888(rgba: Rgba8) -> Bgr8Bluescreen {
+        match rgba.alpha {
+            0 => Bgr8Bluescreen {blue: 255, green: 0, red: 0},
+            _ => Bgr8Bluescreen {blue: rgba.blue, green: rgba.green, red: rgba.red}
+        }
+    }
+}
+
+/// A linear floating-point color, used for decoding HDR images
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgba32f {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    pub alpha: f32
+}
+
+/// The operator used to bring HDR colors into the 0-1 range when converting them to
+/// 8-bit colors. Alpha is always clamped rather than tone-mapped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMap {
+    /// Clamps each channel to the 0-1 range
+    Clamp,
+    /// Maps each channel with `c / (1 + c)`
+    Reinhard,
+    /// Multiplies each channel by the given exposure, then clamps it
+    Exposure(f32)
+}
+
+impl Default for ToneMap {
+    fn default() -> ToneMap {
+        ToneMap::Clamp
+    }
+}
+
+impl ToneMap {
+    pub fn apply(&self, color: Rgba32f) -> Rgba8 {
+        let map = |c: f32| -> f32 {
+            match *self {
+                ToneMap::Clamp => c,
+                ToneMap::Reinhard => if c > 0.0 {c / (1.0 + c)} else {0.0},
+                ToneMap::Exposure(exposure) => c * exposure
+            }
+        };
+
+        Rgba8 {
+            red: float_to_u8(map(color.red)),
+            green: float_to_u8(map(color.green)),
+            blue: float_to_u8(map(color.blue)),
+            alpha: float_to_u8(color.alpha)
+        }
+    }
+}
+
+/// A color type that is stored as HDR data in a VTF file. Multi-byte values are
+/// little-endian.
+pub trait HdrPixelType: Clone {
+    /// The number of bytes each pixel takes up in the file
+    fn byte_size() -> usize;
+    /// Loads a pixel from a slice exactly `byte_size()` bytes long
+    fn from_bytes(bytes: &[u8]) -> Self;
+    /// Writes the pixel to a slice exactly `byte_size()` bytes long
+    fn to_bytes(&self, bytes: &mut [u8]);
+
+    fn to_rgba32f(&self) -> Rgba32f;
+}
+
+/// Four half-precision floats
+#[derive(Debug, Clone)]
+pub struct Rgba16f {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+    pub alpha: u16
+}
+
+impl HdrPixelType for Rgba16f {
+    fn byte_size() -> usize {8}
+    fn from_bytes(b: &[u8]) -> Rgba16f {
+        Rgba16f {
+            red: read_u16(&b[0..2]),
+            green: read_u16(&b[2..4]),
+            blue: read_u16(&b[4..6]),
+            alpha: read_u16(&b[6..8])
+        }
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        write_u16(&mut b[0..2], self.red);
+        write_u16(&mut b[2..4], self.green);
+        write_u16(&mut b[4..6], self.blue);
+        write_u16(&mut b[6..8], self.alpha);
+    }
+
+    fn to_rgba32f(&self) -> Rgba32f {
+        Rgba32f {
+            red: half_to_f32(self.red),
+            green: half_to_f32(self.green),
+            blue: half_to_f32(self.blue),
+            alpha: half_to_f32(self.alpha)
+        }
+    }
+}
+
+/// Four unsigned 16-bit integers, with 65535 mapping to 1.0
+#[derive(Debug, Clone)]
+pub struct Rgba16 {
+    pub red: u16,
+    pub green: u16,
+    pub blue: u16,
+    pub alpha: u16
+}
+
+impl HdrPixelType for Rgba16 {
+    fn byte_size() -> usize {8}
+    fn from_bytes(b: &[u8]) -> Rgba16 {
+        Rgba16 {
+            red: read_u16(&b[0..2]),
+            green: read_u16(&b[2..4]),
+            blue: read_u16(&b[4..6]),
+            alpha: read_u16(&b[6..8])
+        }
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        write_u16(&mut b[0..2], self.red);
+        write_u16(&mut b[2..4], self.green);
+        write_u16(&mut b[4..6], self.blue);
+        write_u16(&mut b[6..8], self.alpha);
+    }
+
+    fn to_rgba32f(&self) -> Rgba32f {
+        const CONV: f32 = 1.0/65535.0;
+
+        Rgba32f {
+            red: self.red as f32 * CONV,
+            green: self.green as f32 * CONV,
+            blue: self.blue as f32 * CONV,
+            alpha: self.alpha as f32 * CONV
+        }
+    }
+}
+
+/// A single float, which is decoded as luminance
+#[derive(Debug, Clone)]
+pub struct R32f {
+    pub red: f32
+}
+
+impl HdrPixelType for R32f {
+    fn byte_size() -> usize {4}
+    fn from_bytes(b: &[u8]) -> R32f {
+        R32f {red: read_f32(b)}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        write_f32(b, self.red);
+    }
+
+    fn to_rgba32f(&self) -> Rgba32f {
+        Rgba32f {
+            red: self.red,
+            green: self.red,
+            blue: self.red,
+            alpha: 1.0
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Rgb32f {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32
+}
+
+impl HdrPixelType for Rgb32f {
+    fn byte_size() -> usize {12}
+    fn from_bytes(b: &[u8]) -> Rgb32f {
+        Rgb32f {
+            red: read_f32(&b[0..4]),
+            green: read_f32(&b[4..8]),
+            blue: read_f32(&b[8..12])
+        }
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        write_f32(&mut b[0..4], self.red);
+        write_f32(&mut b[4..8], self.green);
+        write_f32(&mut b[8..12], self.blue);
+    }
+
+    fn to_rgba32f(&self) -> Rgba32f {
+        Rgba32f {
+            red: self.red,
+            green: self.green,
+            blue: self.blue,
+            alpha: 1.0
+        }
+    }
+}
+
+impl HdrPixelType for Rgba32f {
+    fn byte_size() -> usize {16}
+    fn from_bytes(b: &[u8]) -> Rgba32f {
+        Rgba32f {
+            red: read_f32(&b[0..4]),
+            green: read_f32(&b[4..8]),
+            blue: read_f32(&b[8..12]),
+            alpha: read_f32(&b[12..16])
+        }
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        write_f32(&mut b[0..4], self.red);
+        write_f32(&mut b[4..8], self.green);
+        write_f32(&mut b[8..12], self.blue);
+        write_f32(&mut b[12..16], self.alpha);
+    }
+
+    fn to_rgba32f(&self) -> Rgba32f {
+        *self
+    }
+}
+
+pub trait ColorType where Self: Sized {
+    fn to_rgb8(&self) -> Rgb8 {
+        let rgba = self.to_rgba8();
+        Rgb8 {red: rgba.red, green: rgba.green, blue: rgba.blue}
+    }
+    fn from_rgb888(rgb: Rgb8) -> Self {
+        Self::from_rgba8888(Rgba8 {red: rgb.red, green: rgb.green, blue: rgb.blue, alpha: 255})
+    }
+
+    fn to_rgba8(&self) -> Rgba8;
+    fn from_rgba8888(rgba: Rgba8) -> Self;
+}
+
+/// A color type that is stored uncompressed in a VTF file, with a fixed number of
+/// bytes per pixel. Multi-byte values are little-endian.
+pub trait PixelType: ColorType + Clone {
+    /// The number of bytes each pixel takes up in the file
+    fn byte_size() -> usize;
+    /// Loads a pixel from a slice exactly `byte_size()` bytes long
+    fn from_bytes(bytes: &[u8]) -> Self;
+    /// Writes the pixel to a slice exactly `byte_size()` bytes long
+    fn to_bytes(&self, bytes: &mut [u8]);
+}
+
+impl PixelType for Rgba8 {
+    fn byte_size() -> usize {4}
+    fn from_bytes(b: &[u8]) -> Rgba8 {
+        Rgba8 {red: b[0], green: b[1], blue: b[2], alpha: b[3]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.red;
+        b[1] = self.green;
+        b[2] = self.blue;
+        b[3] = self.alpha;
+    }
+}
+
+impl PixelType for Abgr8 {
+    fn byte_size() -> usize {4}
+    fn from_bytes(b: &[u8]) -> Abgr8 {
+        Abgr8 {alpha: b[0], blue: b[1], green: b[2], red: b[3]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.alpha;
+        b[1] = self.blue;
+        b[2] = self.green;
+        b[3] = self.red;
+    }
+}
+
+impl PixelType for Rgb8 {
+    fn byte_size() -> usize {3}
+    fn from_bytes(b: &[u8]) -> Rgb8 {
+        Rgb8 {red: b[0], green: b[1], blue: b[2]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.red;
+        b[1] = self.green;
+        b[2] = self.blue;
+    }
+}
+
+impl PixelType for Bgr8 {
+    fn byte_size() -> usize {3}
+    fn from_bytes(b: &[u8]) -> Bgr8 {
+        Bgr8 {blue: b[0], green: b[1], red: b[2]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.blue;
+        b[1] = self.green;
+        b[2] = self.red;
+    }
+}
+
+impl PixelType for Argb8 {
+    fn byte_size() -> usize {4}
+    fn from_bytes(b: &[u8]) -> Argb8 {
+        Argb8 {alpha: b[0], red: b[1], green: b[2], blue: b[3]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.alpha;
+        b[1] = self.red;
+        b[2] = self.green;
+        b[3] = self.blue;
+    }
+}
+
+impl PixelType for Bgra8 {
+    fn byte_size() -> usize {4}
+    fn from_bytes(b: &[u8]) -> Bgra8 {
+        Bgra8 {blue: b[0], green: b[1], red: b[2], alpha: b[3]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.blue;
+        b[1] = self.green;
+        b[2] = self.red;
+        b[3] = self.alpha;
+    }
+}
+
+impl PixelType for Bgrx8 {
+    fn byte_size() -> usize {4}
+    fn from_bytes(b: &[u8]) -> Bgrx8 {
+        Bgrx8 {blue: b[0], green: b[1], red: b[2], unused: b[3]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.blue;
+        b[1] = self.green;
+        b[2] = self.red;
+        b[3] = self.unused;
+    }
+}
+
+impl PixelType for I8 {
+    fn byte_size() -> usize {1}
+    fn from_bytes(b: &[u8]) -> I8 {
+        I8 {luminance: b[0]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.luminance;
+    }
+}
+
+impl PixelType for Ia8 {
+    fn byte_size() -> usize {2}
+    fn from_bytes(b: &[u8]) -> Ia8 {
+        Ia8 {luminance: b[0], alpha: b[1]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.luminance;
+        b[1] = self.alpha;
+    }
+}
+
+impl PixelType for A8 {
+    fn byte_size() -> usize {1}
+    fn from_bytes(b: &[u8]) -> A8 {
+        A8 {alpha: b[0]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.alpha;
+    }
+}
+
+impl PixelType for Uv8 {
+    fn byte_size() -> usize {2}
+    fn from_bytes(b: &[u8]) -> Uv8 {
+        Uv8 {u: b[0], v: b[1]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.u;
+        b[1] = self.v;
+    }
+}
+
+impl PixelType for Uvwq8 {
+    fn byte_size() -> usize {4}
+    fn from_bytes(b: &[u8]) -> Uvwq8 {
+        Uvwq8 {u: b[0], v: b[1], w: b[2], q: b[3]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.u;
+        b[1] = self.v;
+        b[2] = self.w;
+        b[3] = self.q;
+    }
+}
+
+impl PixelType for Uvlx8 {
+    fn byte_size() -> usize {4}
+    fn from_bytes(b: &[u8]) -> Uvlx8 {
+        Uvlx8 {u: b[0], v: b[1], l: b[2], x: b[3]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.u;
+        b[1] = self.v;
+        b[2] = self.l;
+        b[3] = self.x;
+    }
+}
+
+impl PixelType for Rgb8Bluescreen {
+    fn byte_size() -> usize {3}
+    fn from_bytes(b: &[u8]) -> Rgb8Bluescreen {
+        Rgb8Bluescreen {red: b[0], green: b[1], blue: b[2]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.red;
+        b[1] = self.green;
+        b[2] = self.blue;
+    }
+}
+
+impl PixelType for Bgr8Bluescreen {
+    fn byte_size() -> usize {3}
+    fn from_bytes(b: &[u8]) -> Bgr8Bluescreen {
+        Bgr8Bluescreen {blue: b[0], green: b[1], red: b[2]}
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = self.blue;
+        b[1] = self.green;
+        b[2] = self.red;
+    }
+}
+
+/// RGB565 stores red in the lowest five bits, which is the opposite of the DXT
+/// endpoint layout that `Rgb565::load` reads.
+impl PixelType for Rgb565 {
+    fn byte_size() -> usize {2}
+    fn from_bytes(b: &[u8]) -> Rgb565 {
+        let source = b[0] as u16 | (b[1] as u16) << 8;
+        Rgb565 {
+            red: (source & 31) as u8,
+            green: ((source >> 5) & 63) as u8,
+            blue: (source >> 11) as u8
+        }
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        write_u16(b, (self.red as u16 & 31) | (self.green as u16 & 63) << 5 | (self.blue as u16 & 31) << 11);
+    }
+}
+
+impl PixelType for Bgr565 {
+    fn byte_size() -> usize {2}
+    fn from_bytes(b: &[u8]) -> Bgr565 {
+        let source = b[0] as u16 | (b[1] as u16) << 8;
+        Bgr565 {
+            blue: (source & 31) as u8,
+            green: ((source >> 5) & 63) as u8,
+            red: (source >> 11) as u8
+        }
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        write_u16(b, (self.blue as u16 & 31) | (self.green as u16 & 63) << 5 | (self.red as u16 & 31) << 11);
+    }
+}
+
+impl PixelType for Bgrx5551 {
+    fn byte_size() -> usize {2}
+    fn from_bytes(b: &[u8]) -> Bgrx5551 {
+        let source = b[0] as u16 | (b[1] as u16) << 8;
+        Bgrx5551 {
+            blue: (source & 31) as u8,
+            green: ((source >> 5) & 31) as u8,
+            red: ((source >> 10) & 31) as u8,
+            unused: source >> 15 != 0
+        }
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        write_u16(b, (self.blue as u16 & 31) | (self.green as u16 & 31) << 5 | (self.red as u16 & 31) << 10 | (self.unused as u16) << 15);
+    }
+}
+
+impl PixelType for Bgra5551 {
+    fn byte_size() -> usize {2}
+    fn from_bytes(b: &[u8]) -> Bgra5551 {
+        let source = b[0] as u16 | (b[1] as u16) << 8;
+        Bgra5551 {
+            blue: (source & 31) as u8,
+            green: ((source >> 5) & 31) as u8,
+            red: ((source >> 10) & 31) as u8,
+            alpha: source >> 15 != 0
+        }
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        write_u16(b, (self.blue as u16 & 31) | (self.green as u16 & 31) << 5 | (self.red as u16 & 31) << 10 | (self.alpha as u16) << 15);
+    }
+}
+
+impl PixelType for Bgra4444 {
+    fn byte_size() -> usize {2}
+    fn from_bytes(b: &[u8]) -> Bgra4444 {
+        Bgra4444 {
+            blue: b[0] & 15,
+            green: b[0] >> 4,
+            red: b[1] & 15,
+            alpha: b[1] >> 4
+        }
+    }
+
+    fn to_bytes(&self, b: &mut [u8]) {
+        b[0] = (self.blue & 15) | self.green << 4;
+        b[1] = (self.red & 15) | self.alpha << 4;
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone)]
+pub enum VTFImageWrapper {
+    RGBA8888 (Rgba8Image),
+    ABGR8888 (Abgr8Image),
+    RGB888 (Rgb8Image),
+    BGR888 (Bgr8Image),
+    RGB565 (Rgb565Image),
+    I8 (I8Image),
+    IA88 (Ia8Image),
+    P8 (P8Image),
+    A8 (A8Image),
+    RGB888_BLUESCREEN (Rgb8BluescreenImage),
+    BGR888_BLUESCREEN (Bgr8BluescreenImage),
+    ARGB8888 (Argb8Image),
+    BGRA8888 (Bgra8Image),
+    DXT1 (Dxt1),
+    DXT1_ONEBITALPHA (Dxt1),
+    DXT3 (Dxt3),
+    DXT5 (Dxt5),
+    BGRX8888 (Bgrx8Image),
+    BGR565 (Bgr565Image),
+    BGRX5551 (Bgrx5551Image),
+    BGRA4444 (Bgra4444Image),
+    BGRA5551 (Bgra5551Image),
+    UV88 (Uv8Image),
+    UVWQ8888 (Uvwq8Image),
+    RGBA16161616F (Rgba16fImage),
+    RGBA16161616 (Rgba16Image),
+    UVLX8888 (Uvlx8Image),
+    R32F (R32fImage),
+    RGB323232F (Rgb32fImage),
+    RGBA32323232F (Rgba32fImage),
+    ATI2N (Ati2n),
+    ATI1N (Ati1n)
+}
+
+impl VTFImageWrapper {
+    pub fn load<R>(source: &mut R, width: u16, height: u16, format: ImageFormat) -> Result<VTFImageWrapper, VTFLoadError> where R: Read {
+        let image = match format {
+            ImageFormat::RGBA8888 => Rgba8Image::load(&mut *source, width, height).map(VTFImageWrapper::RGBA8888),
+            ImageFormat::ABGR8888 => Abgr8Image::load(&mut *source, width, height).map(VTFImageWrapper::ABGR8888),
+            ImageFormat::RGB888 => Rgb8Image::load(&mut *source, width, height).map(VTFImageWrapper::RGB888),
+            ImageFormat::BGR888 => Bgr8Image::load(&mut *source, width, height).map(VTFImageWrapper::BGR888),
+            ImageFormat::RGB565 => Rgb565Image::load(&mut *source, width, height).map(VTFImageWrapper::RGB565),
+            ImageFormat::I8 => I8Image::load(&mut *source, width, height).map(VTFImageWrapper::I8),
+            ImageFormat::IA88 => Ia8Image::load(&mut *source, width, height).map(VTFImageWrapper::IA88),
+            ImageFormat::P8 => P8Image::load(&mut *source, width, height).map(VTFImageWrapper::P8),
+            ImageFormat::A8 => A8Image::load(&mut *source, width, height).map(VTFImageWrapper::A8),
+            ImageFormat::RGB888_BLUESCREEN => Rgb8BluescreenImage::load(&mut *source, width, height).map(VTFImageWrapper::RGB888_BLUESCREEN),
+            ImageFormat::BGR888_BLUESCREEN => Bgr8BluescreenImage::load(&mut *source, width, height).map(VTFImageWrapper::BGR888_BLUESCREEN),
+            ImageFormat::ARGB8888 => Argb8Image::load(&mut *source, width, height).map(VTFImageWrapper::ARGB8888),
+            ImageFormat::BGRA8888 => Bgra8Image::load(&mut *source, width, height).map(VTFImageWrapper::BGRA8888),
+            ImageFormat::DXT1 => Dxt1::load(&mut *source, width, height).map(VTFImageWrapper::DXT1),
+            ImageFormat::DXT1_ONEBITALPHA => Dxt1::load(&mut *source, width, height).map(VTFImageWrapper::DXT1_ONEBITALPHA),
+            ImageFormat::DXT3 => Dxt3::load(&mut *source, width, height).map(VTFImageWrapper::DXT3),
+            ImageFormat::DXT5 => Dxt5::load(&mut *source, width, height).map(VTFImageWrapper::DXT5),
+            ImageFormat::BGRX8888 => Bgrx8Image::load(&mut *source, width, height).map(VTFImageWrapper::BGRX8888),
+            ImageFormat::BGR565 => Bgr565Image::load(&mut *source, width, height).map(VTFImageWrapper::BGR565),
+            ImageFormat::BGRX5551 => Bgrx5551Image::load(&mut *source, width, height).map(VTFImageWrapper::BGRX5551),
+            ImageFormat::BGRA4444 => Bgra4444Image::load(&mut *source, width, height).map(VTFImageWrapper::BGRA4444),
+            ImageFormat::BGRA5551 => Bgra5551Image::load(&mut *source, width, height).map(VTFImageWrapper::BGRA5551),
+            ImageFormat::UV88 => Uv8Image::load(&mut *source, width, height).map(VTFImageWrapper::UV88),
+            ImageFormat::UVWQ8888 => Uvwq8Image::load(&mut *source, width, height).map(VTFImageWrapper::UVWQ8888),
+            ImageFormat::RGBA16161616F => Rgba16fImage::load(&mut *source, width, height).map(VTFImageWrapper::RGBA16161616F),
+            ImageFormat::RGBA16161616 => Rgba16Image::load(&mut *source, width, height).map(VTFImageWrapper::RGBA16161616),
+            ImageFormat::UVLX8888 => Uvlx8Image::load(&mut *source, width, height).map(VTFImageWrapper::UVLX8888),
+            ImageFormat::R32F => R32fImage::load(&mut *source, width, height).map(VTFImageWrapper::R32F),
+            ImageFormat::RGB323232F => Rgb32fImage::load(&mut *source, width, height).map(VTFImageWrapper::RGB323232F),
+            ImageFormat::RGBA32323232F => Rgba32fImage::load(&mut *source, width, height).map(VTFImageWrapper::RGBA32323232F),
+            ImageFormat::ATI2N => Ati2n::load(&mut *source, width, height).map(VTFImageWrapper::ATI2N),
+            ImageFormat::ATI1N => Ati1n::load(&mut *source, width, height).map(VTFImageWrapper::ATI1N),
+            _ => return Err(VTFLoadError::VTF(VTFError::UnsupportedFormat(format)))
+        };
+
+        image.map_err(VTFLoadError::Io)
+    }
+
+    /// The format the image is stored in.
+    pub fn format(&self) -> ImageFormat {
+        match self {
+            &VTFImageWrapper::RGBA8888(_) => ImageFormat::RGBA8888,
+            &VTFImageWrapper::ABGR8888(_) => ImageFormat::ABGR8888,
+            &VTFImageWrapper::RGB888(_) => ImageFormat::RGB888,
+            &VTFImageWrapper::BGR888(_) => ImageFormat::BGR888,
+            &VTFImageWrapper::RGB565(_) => ImageFormat::RGB565,
+            &VTFImageWrapper::I8(_) => ImageFormat::I8,
+            &VTFImageWrapper::IA88(_) => ImageFormat::IA88,
+            &VTFImageWrapper::P8(_) => ImageFormat::P8,
+            &VTFImageWrapper::A8(_) => ImageFormat::A8,
+            &VTFImageWrapper::RGB888_BLUESCREEN(_) => ImageFormat::RGB888_BLUESCREEN,
+            &VTFImageWrapper::BGR888_BLUESCREEN(_) => ImageFormat::BGR888_BLUESCREEN,
+            &VTFImageWrapper::ARGB8888(_) => ImageFormat::ARGB8888,
+            &VTFImageWrapper::BGRA8888(_) => ImageFormat::BGRA8888,
+            &VTFImageWrapper::DXT1(_) => ImageFormat::DXT1,
+            &VTFImageWrapper::DXT1_ONEBITALPHA(_) => ImageFormat::DXT1_ONEBITALPHA,
+            &VTFImageWrapper::DXT3(_) => ImageFormat::DXT3,
+            &VTFImageWrapper::DXT5(_) => ImageFormat::DXT5,
+            &VTFImageWrapper::BGRX8888(_) => ImageFormat::BGRX8888,
+            &VTFImageWrapper::BGR565(_) => ImageFormat::BGR565,
+            &VTFImageWrapper::BGRX5551(_) => ImageFormat::BGRX5551,
+            &VTFImageWrapper::BGRA4444(_) => ImageFormat::BGRA4444,
+            &VTFImageWrapper::BGRA5551(_) => ImageFormat::BGRA5551,
+            &VTFImageWrapper::UV88(_) => ImageFormat::UV88,
+            &VTFImageWrapper::UVWQ8888(_) => ImageFormat::UVWQ8888,
+            &VTFImageWrapper::RGBA16161616F(_) => ImageFormat::RGBA16161616F,
+            &VTFImageWrapper::RGBA16161616(_) => ImageFormat::RGBA16161616,
+            &VTFImageWrapper::UVLX8888(_) => ImageFormat::UVLX8888,
+            &VTFImageWrapper::R32F(_) => ImageFormat::R32F,
+            &VTFImageWrapper::RGB323232F(_) => ImageFormat::RGB323232F,
+            &VTFImageWrapper::RGBA32323232F(_) => ImageFormat::RGBA32323232F,
+            &VTFImageWrapper::ATI2N(_) => ImageFormat::ATI2N,
+            &VTFImageWrapper::ATI1N(_) => ImageFormat::ATI1N
+        }
+    }
+
+    /// Writes the image in its VTF format.
+    pub fn save<W>(&self, dest: &mut W) -> Result<(), io::Error> where W: Write {
+        match self {
+            &VTFImageWrapper::RGBA8888(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::ABGR8888(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::RGB888(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::BGR888(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::RGB565(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::I8(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::IA88(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::P8(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::A8(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::RGB888_BLUESCREEN(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::BGR888_BLUESCREEN(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::ARGB8888(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::BGRA8888(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::DXT1(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::DXT1_ONEBITALPHA(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::DXT3(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::DXT5(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::BGRX8888(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::BGR565(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::BGRX5551(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::BGRA4444(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::BGRA5551(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::UV88(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::UVWQ8888(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::RGBA16161616F(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::RGBA16161616(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::UVLX8888(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::R32F(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::RGB323232F(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::RGBA32323232F(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::ATI2N(ref im) => im.save(&mut *dest),
+            &VTFImageWrapper::ATI1N(ref im) => im.save(&mut *dest)
+        }
+    }
+
+    /// The image's data, laid out the way it's stored in the file. Compressed and
+    /// paletted images are borrowed as-is, so they can be uploaded to the GPU without
+    /// being decoded. Other formats are kept unpacked in memory, so their bytes get
+    /// written out to a new buffer.
+    pub fn raw_data(&self) -> Cow<[u8]> {
+        match self {
+            &VTFImageWrapper::DXT1(ref im) |
+            &VTFImageWrapper::DXT1_ONEBITALPHA(ref im) => Cow::Borrowed(im.get_blocks()),
+            &VTFImageWrapper::DXT3(ref im) => Cow::Borrowed(im.get_blocks()),
+            &VTFImageWrapper::DXT5(ref im) => Cow::Borrowed(im.get_blocks()),
+            &VTFImageWrapper::ATI2N(ref im) => Cow::Borrowed(im.get_blocks()),
+            &VTFImageWrapper::ATI1N(ref im) => Cow::Borrowed(im.get_blocks()),
+            &VTFImageWrapper::P8(ref im) => Cow::Borrowed(im.get_indices()),
+            _ => {
+                let mut data = Vec::with_capacity(self.block_rows() * self.row_pitch());
+                self.save(&mut data).expect("Writing to a Vec failed");
+                Cow::Owned(data)
+            }
+        }
+    }
+
+    /// The width and height, in pixels, of each block in `raw_data`. This is 4x4 for
+    /// compressed formats and 1x1 for everything else.
+    pub fn block_dimensions(&self) -> (u16, u16) {
+        match self.format().is_compressed() {
+            true => (4, 4),
+            false => (1, 1)
+        }
+    }
+
+    /// The number of bytes in each block of `raw_data`.
+    pub fn block_size(&self) -> u32 {
+        // Every format that can be loaded has a block size
+        self.format().block_size().unwrap()
+    }
+
+    /// The number of bytes between the start of one row of blocks in `raw_data` and
+    /// the start of the next.
+    pub fn row_pitch(&self) -> usize {
+        let block_width = self.block_dimensions().0 as usize;
+        let width = self.expose().get_width() as usize;
+        (width + block_width - 1) / block_width * self.block_size() as usize
+    }
+
+    /// The number of rows of blocks in `raw_data`.
+    pub fn block_rows(&self) -> usize {
+        let block_height = self.block_dimensions().1 as usize;
+        let height = self.expose().get_height() as usize;
+        (height + block_height - 1) / block_height
+    }
+
+    /// Converts an image, given as `width * height` pixels, to the given format.
+    /// Compressed formats use `quality` to pick their block encoder. HDR formats and
+    /// `P8` can't be encoded, and a pixel count that doesn't match the dimensions
+    /// returns `ImageMismatch`.
+    pub fn encode(pixels: &[Rgba8], width: u16, height: u16, format: ImageFormat, quality: DxtQuality) -> Result<VTFImageWrapper, VTFLoadError> {
+        if pixels.len() != width as usize * height as usize {
+            return Err(VTFLoadError::VTF(VTFError::ImageMismatch));
+        }
+
+        let image = match format {
+            ImageFormat::RGBA8888 => VTFImageWrapper::RGBA8888(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::ABGR8888 => VTFImageWrapper::ABGR8888(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::RGB888 => VTFImageWrapper::RGB888(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::BGR888 => VTFImageWrapper::BGR888(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::RGB565 => VTFImageWrapper::RGB565(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::I8 => VTFImageWrapper::I8(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::IA88 => VTFImageWrapper::IA88(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::A8 => VTFImageWrapper::A8(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::RGB888_BLUESCREEN => VTFImageWrapper::RGB888_BLUESCREEN(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::BGR888_BLUESCREEN => VTFImageWrapper::BGR888_BLUESCREEN(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::ARGB8888 => VTFImageWrapper::ARGB8888(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::BGRA8888 => VTFImageWrapper::BGRA8888(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::DXT1 => VTFImageWrapper::DXT1(try!(Dxt1::encode(pixels, width, height, quality).map_err(VTFLoadError::VTF))),
+            ImageFormat::DXT1_ONEBITALPHA => VTFImageWrapper::DXT1_ONEBITALPHA(try!(Dxt1::encode_one_bit_alpha(pixels, width, height, quality).map_err(VTFLoadError::VTF))),
+            ImageFormat::DXT3 => VTFImageWrapper::DXT3(try!(Dxt3::encode(pixels, width, height, quality).map_err(VTFLoadError::VTF))),
+            ImageFormat::DXT5 => VTFImageWrapper::DXT5(try!(Dxt5::encode(pixels, width, height, quality).map_err(VTFLoadError::VTF))),
+            ImageFormat::BGRX8888 => VTFImageWrapper::BGRX8888(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::BGR565 => VTFImageWrapper::BGR565(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::BGRX5551 => VTFImageWrapper::BGRX5551(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::BGRA4444 => VTFImageWrapper::BGRA4444(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::BGRA5551 => VTFImageWrapper::BGRA5551(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::UV88 => VTFImageWrapper::UV88(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::UVWQ8888 => VTFImageWrapper::UVWQ8888(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::UVLX8888 => VTFImageWrapper::UVLX8888(PixelImage::from_rgba8(pixels, width, height)),
+            ImageFormat::ATI2N => VTFImageWrapper::ATI2N(try!(Ati2n::encode(pixels, width, height, quality).map_err(VTFLoadError::VTF))),
+            ImageFormat::ATI1N => VTFImageWrapper::ATI1N(try!(Ati1n::encode(pixels, width, height, quality).map_err(VTFLoadError::VTF))),
+            _ => return Err(VTFLoadError::VTF(VTFError::UnsupportedFormat(format)))
+        };
+
+        Ok(image)
+    }
+
+    /// Converts an `image` crate buffer to the given format. See `encode` for which
+    /// formats are supported.
+    pub fn from_rgba_image(image: &RgbaImage, format: ImageFormat, quality: DxtQuality) -> Result<VTFImageWrapper, VTFLoadError> {
+        let (width, height) = image.dimensions();
+        if width > u16::max_value() as u32 || height > u16::max_value() as u32 {
+            return Err(VTFLoadError::VTF(VTFError::ImageSize));
+        }
+
+        VTFImageWrapper::encode(&rgba_image_pixels(image), width as u16, height as u16, format, quality)
+    }
+
+    pub fn from_dynamic_image(image: &DynamicImage, format: ImageFormat, quality: DxtQuality) -> Result<VTFImageWrapper, VTFLoadError> {
+        VTFImageWrapper::from_rgba_image(&image.to_rgba(), format, quality)
+    }
+
+    /// Decodes the image into an `image` crate buffer. HDR images are tone-mapped.
+    pub fn to_rgba_image(&self) -> RgbaImage {
+        self.expose().to_rgba_image()
+    }
+
+    pub fn to_dynamic_image(&self) -> DynamicImage {
+        self.expose().to_dynamic_image()
+    }
+
+    pub fn expose(&self) -> &VTFImage {
+        match self {
+            &VTFImageWrapper::RGBA8888(ref im) => im,
+            &VTFImageWrapper::ABGR8888(ref im) => im,
+            &VTFImageWrapper::RGB888(ref im) => im,
+            &VTFImageWrapper::BGR888(ref im) => im,
+            &VTFImageWrapper::RGB565(ref im) => im,
+            &VTFImageWrapper::I8(ref im) => im,
+            &VTFImageWrapper::IA88(ref im) => im,
+            &VTFImageWrapper::P8(ref im) => im,
+            &VTFImageWrapper::A8(ref im) => im,
+            &VTFImageWrapper::RGB888_BLUESCREEN(ref im) => im,
+            &VTFImageWrapper::BGR888_BLUESCREEN(ref im) => im,
+            &VTFImageWrapper::ARGB8888(ref im) => im,
+            &VTFImageWrapper::BGRA8888(ref im) => im,
+            &VTFImageWrapper::DXT1(ref im) => im,
+            &VTFImageWrapper::DXT1_ONEBITALPHA(ref im) => im,
+            &VTFImageWrapper::DXT3(ref im) => im,
+            &VTFImageWrapper::DXT5(ref im) => im,
+            &VTFImageWrapper::BGRX8888(ref im) => im,
+            &VTFImageWrapper::BGR565(ref im) => im,
+            &VTFImageWrapper::BGRX5551(ref im) => im,
+            &VTFImageWrapper::BGRA4444(ref im) => im,
+            &VTFImageWrapper::BGRA5551(ref im) => im,
+            &VTFImageWrapper::UV88(ref im) => im,
+            &VTFImageWrapper::UVWQ8888(ref im) => im,
+            &VTFImageWrapper::RGBA16161616F(ref im) => im,
+            &VTFImageWrapper::RGBA16161616(ref im) => im,
+            &VTFImageWrapper::UVLX8888(ref im) => im,
+            &VTFImageWrapper::R32F(ref im) => im,
+            &VTFImageWrapper::RGB323232F(ref im) => im,
+            &VTFImageWrapper::RGBA32323232F(ref im) => im,
+            &VTFImageWrapper::ATI2N(ref im) => im,
+            &VTFImageWrapper::ATI1N(ref im) => im
+        }
+    }
+
+    /// Sets the tone-map operator used when converting HDR images to 8-bit colors.
+    /// Does nothing for images that aren't HDR.
+    pub fn set_tone_map(&mut self, tone_map: ToneMap) {
+        match self {
+            &mut VTFImageWrapper::RGBA16161616F(ref mut im) => im.set_tone_map(tone_map),
+            &mut VTFImageWrapper::RGBA16161616(ref mut im) => im.set_tone_map(tone_map),
+            &mut VTFImageWrapper::R32F(ref mut im) => im.set_tone_map(tone_map),
+            &mut VTFImageWrapper::RGB323232F(ref mut im) => im.set_tone_map(tone_map),
+            &mut VTFImageWrapper::RGBA32323232F(ref mut im) => im.set_tone_map(tone_map),
+            _ => ()
+        }
+    }
+
+    /// Sets whether ATI2N normal maps reconstruct their Z component when converting to
+    /// RGB. Does nothing for other formats.
+    pub fn set_reconstruct_z(&mut self, reconstruct_z: bool) {
+        match self {
+            &mut VTFImageWrapper::ATI2N(ref mut im) => im.set_reconstruct_z(reconstruct_z),
+            _ => ()
+        }
+    }
+
+    pub fn is_hdr(&self) -> bool {
+        match self {
+            &VTFImageWrapper::RGBA16161616F(_) |
+            &VTFImageWrapper::RGBA16161616(_) |
+            &VTFImageWrapper::R32F(_) |
+            &VTFImageWrapper::RGB323232F(_) |
+            &VTFImageWrapper::RGBA32323232F(_) => true,
+            _ => false
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Dxt1 {
+    data: Vec<u8>,
+    width: u16,
+    height: u16,
+    image_width: u16,
+    image_height: u16
+}
+
+impl Dxt1 {
+    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<Dxt1, io::Error> where R: Read {
+        // Internally to the VTF file format, there are no images that are
+        // smaller than 4x4. This corrects for that. 
+        let (padded_width, padded_height) = try!(load_block_dimensions(width, height));
+
+        let pix_count = padded_width as usize * padded_height as usize;
+
+        // Each 4x4 chunk of pixels is compressed into 8 bytes
+        let mut data: Vec<u8> = vec![0; pix_count / 2];
+        try!(source.read_exact(&mut data));
+
+        Ok(Dxt1 {data: data, width: padded_width, height: padded_height, image_width: width, image_height: height})
+    }
+
+    /// Compresses an image, given as `width * height` pixels. Alpha is ignored, so every
+    /// block uses four colors.
+    pub fn encode(pixels: &[Rgba8], width: u16, height: u16, quality: DxtQuality) -> Result<Dxt1, VTFError> {
+        Dxt1::encode_blocks(pixels, width, height, quality, false)
+    }
+
+    /// Compresses an image for `DXT1_ONEBITALPHA`. Pixels with an alpha below 128 become
+    /// fully transparent, and every other pixel becomes fully opaque.
+    pub fn encode_one_bit_alpha(pixels: &[Rgba8], width: u16, height: u16, quality: DxtQuality) -> Result<Dxt1, VTFError> {
+        Dxt1::encode_blocks(pixels, width, height, quality, true)
+    }
+
+    fn encode_blocks(pixels: &[Rgba8], width: u16, height: u16, quality: DxtQuality, punch_through: bool) -> Result<Dxt1, VTFError> {
+        let (padded_width, padded_height) = try!(check_encode_input(pixels, width, height));
+        let blocks = encode_source_blocks(pixels, width, height);
+        let mut data: Vec<u8> = vec![0; blocks.len() * 8];
+        for (b, block) in data.chunks_mut(8).zip(&blocks) {
+            let (c0, c1, indices) = dxt::encode_color_block(block, quality, punch_through);
+            write_u16(&mut b[0..2], c0);
+            write_u16(&mut b[2..4], c1);
+            b[4..8].copy_from_slice(&indices);
+        }
+
+        Ok(Dxt1 {data: data, width: padded_width, height: padded_height, image_width: width, image_height: height})
+    }
+
+    pub fn save<W>(&self, dest: &mut W) -> Result<(), io::Error> where W: Write {
+        dest.write_all(&self.data)
+    }
+
+    /// The compressed blocks, exactly as they're stored in the file. Blocks are 8 bytes
+    /// each, and run left to right, then top to bottom.
+    pub fn get_blocks(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl VTFImage for Dxt1 {
+    fn to_rgb8(&self) -> Vec<Rgb8> {
+        let rgba = self.to_rgba8();
+
+        let mut rgb: Vec<Rgb8> = Vec::with_capacity(rgba.len());
+        for c in &rgba {
+            rgb.push(Rgb8{red: c.red, green: c.green, blue: c.blue});
+        }
+
+        rgb
+    }
+
+    fn to_rgba8(&self) -> Vec<Rgba8> {
+        rgba8_from_raw(&self.to_rgba8_raw())
+    }
+
+    fn decode_into(&self, dest: &mut [u8]) {
+        decode_blocks_into(&self.data, 8, self.width, dest, decode_dxt1_block);
+    }
+
+    fn get_width(&self) -> u16 {
+        self.width
+    }
+
+    fn get_height(&self) -> u16 {
+        self.height
+    }
+
+    fn image_dimensions(&self) -> (u16, u16) {
+        (self.image_width, self.image_height)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Dxt3 {
+    data: Vec<u8>,
+    width: u16,
+    height: u16,
+    image_width: u16,
+    image_height: u16
+}
+
+impl Dxt3 {
+    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<Dxt3, io::Error> where R: Read {
+        // Internally to the VTF file format, there are no images that are
+        // smaller than 4x4. This corrects for that. 
+        let (padded_width, padded_height) = try!(load_block_dimensions(width, height));
+
+        let pix_count = padded_width as usize * padded_height as usize;
+
+        // Each 4x4 chunk of pixels is compressed into 16 bytes
+        let mut data: Vec<u8> = vec![0; pix_count];
+        try!(source.read_exact(&mut data));
+
+        Ok(Dxt3 {data: data, width: padded_width, height: padded_height, image_width: width, image_height: height})
+    }
+
+    /// Compresses an image, given as `width * height` pixels. Alpha is stored with four
+    /// bits per pixel.
+    pub fn encode(pixels: &[Rgba8], width: u16, height: u16, quality: DxtQuality) -> Result<Dxt3, VTFError> {
+        let (padded_width, padded_height) = try!(check_encode_input(pixels, width, height));
+        let blocks = encode_source_blocks(pixels, width, height);
+        let mut data: Vec<u8> = vec![0; blocks.len() * 16];
+        for (b, block) in data.chunks_mut(16).zip(&blocks) {
+            let (c0, c1, indices) = dxt::encode_color_block(block, quality, false);
+            b[0..8].copy_from_slice(&dxt::encode_explicit_alpha(&block_alphas(block)));
+            write_u16(&mut b[8..10], c0);
+            write_u16(&mut b[10..12], c1);
+            b[12..16].copy_from_slice(&indices);
+        }
+
+        Ok(Dxt3 {data: data, width: padded_width, height: padded_height, image_width: width, image_height: height})
+    }
+
+    pub fn save<W>(&self, dest: &mut W) -> Result<(), io::Error> where W: Write {
+        dest.write_all(&self.data)
+    }
+
+    /// The compressed blocks, exactly as they're stored in the file. Blocks are 16 bytes
+    /// each, and run left to right, then top to bottom.
+    pub fn get_blocks(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl VTFImage for Dxt3 {
+    fn to_rgb8(&self) -> Vec<Rgb8> {
+        let rgba = self.to_rgba8();
+
+        let mut rgb: Vec<Rgb8> = Vec::with_capacity(rgba.len());
+        for c in &rgba {
+            rgb.push(Rgb8{red: c.red, green: c.green, blue: c.blue});
+        }
+
+        rgb
+    }
+
+    fn to_rgba8(&self) -> Vec<Rgba8> {
+        rgba8_from_raw(&self.to_rgba8_raw())
+    }
+
+    fn decode_into(&self, dest: &mut [u8]) {
+        decode_blocks_into(&self.data, 16, self.width, dest, decode_dxt3_block);
+    }
+
+    fn get_width(&self) -> u16 {
+        self.width
+    }
+
+    fn get_height(&self) -> u16 {
+        self.height
+    }
+
+    fn image_dimensions(&self) -> (u16, u16) {
+        (self.image_width, self.image_height)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Dxt5 {
+    data: Vec<u8>,
+    width: u16,
+    height: u16,
+    image_width: u16,
+    image_height: u16
+}
+
+impl Dxt5 {
+    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<Dxt5, io::Error> where R: Read {
+        // Internally to the VTF file format, there are no images that are
+        // smaller than 4x4. This corrects for that. 
+        let (padded_width, padded_height) = try!(load_block_dimensions(width, height));
+
+        let pix_count = padded_width as usize * padded_height as usize;
+
+        // Each 4x4 chunk of pixels is compressed into 16 bytes
+        let mut data: Vec<u8> = vec![0; pix_count];
+        try!(source.read_exact(&mut data));
+
+        Ok(Dxt5 {data: data, width: padded_width, height: padded_height, image_width: width, image_height: height})
+    }
+
+    /// Compresses an image, given as `width * height` pixels. Alpha is stored as an
+    /// interpolated block, like the colors.
+    pub fn encode(pixels: &[Rgba8], width: u16, height: u16, quality: DxtQuality) -> Result<Dxt5, VTFError> {
+        let (padded_width, padded_height) = try!(check_encode_input(pixels, width, height));
+        let blocks = encode_source_blocks(pixels, width, height);
+        let mut data: Vec<u8> = vec![0; blocks.len() * 16];
+        for (b, block) in data.chunks_mut(16).zip(&blocks) {
+            let (a0, a1, alpha_indices) = dxt::encode_interpolated_alpha(&block_alphas(block), quality);
+            let (c0, c1, indices) = dxt::encode_color_block(block, quality, false);
+            b[0] = a0;
+            b[1] = a1;
+            b[2..8].copy_from_slice(&alpha_indices);
+            write_u16(&mut b[8..10], c0);
+            write_u16(&mut b[10..12], c1);
+            b[12..16].copy_from_slice(&indices);
+        }
+
+        Ok(Dxt5 {data: data, width: padded_width, height: padded_height, image_width: width, image_height: height})
+    }
+
+    pub fn save<W>(&self, dest: &mut W) -> Result<(), io::Error> where W: Write {
+        dest.write_all(&self.data)
+    }
+
+    /// The compressed blocks, exactly as they're stored in the file. Blocks are 16 bytes
+    /// each, and run left to right, then top to bottom.
+    pub fn get_blocks(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl VTFImage for Dxt5 {
+    fn to_rgb8(&self) -> Vec<Rgb8> {
+        let rgba = self.to_rgba8();
+
+        let mut rgb: Vec<Rgb8> = Vec::with_capacity(rgba.len());
+        for c in &rgba {
+            rgb.push(Rgb8{red: c.red, green: c.green, blue: c.blue});
+        }
+
+        rgb
+    }
+
+    fn to_rgba8(&self) -> Vec<Rgba8> {
+        rgba8_from_raw(&self.to_rgba8_raw())
+    }
+
+    fn decode_into(&self, dest: &mut [u8]) {
+        decode_blocks_into(&self.data, 16, self.width, dest, decode_dxt5_block);
+    }
+
+    fn get_width(&self) -> u16 {
+        self.width
+    }
+
+    fn get_height(&self) -> u16 {
+        self.height
+    }
+
+    fn image_dimensions(&self) -> (u16, u16) {
+        (self.image_width, self.image_height)
+    }
+}
+
+/// Single-channel block compressed image, also known as BC4. Each 4x4 block is
+/// stored the same way as DXT5's alpha.
+#[derive(Debug, Clone)]
+pub struct Ati1n {
+    data: Vec<u8>,
+    width: u16,
+    height: u16,
+    image_width: u16,
+    image_height: u16
+}
+
+impl Ati1n {
+    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<Ati1n, io::Error> where R: Read {
+        // Internally to the VTF file format, there are no images that are
+        // smaller than 4x4. This corrects for that.
+        let (padded_width, padded_height) = try!(load_block_dimensions(width, height));
+
+        let block_count = padded_width as usize * padded_height as usize / 16;
+        let mut data: Vec<u8> = vec![0; block_count * 8];
+        try!(source.read_exact(&mut data));
+
+        Ok(Ati1n {data: data, width: padded_width, height: padded_height, image_width: width, image_height: height})
+    }
+
+    pub fn save<W>(&self, dest: &mut W) -> Result<(), io::Error> where W: Write {
+        dest.write_all(&self.data)
+    }
+
+    /// The compressed blocks, exactly as they're stored in the file. Blocks are 8 bytes
+    /// each, and run left to right, then top to bottom.
+    pub fn get_blocks(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Compresses the red channel of an image, given as `width * height` pixels.
+    pub fn encode(pixels: &[Rgba8], width: u16, height: u16, quality: DxtQuality) -> Result<Ati1n, VTFError> {
+        let (padded_width, padded_height) = try!(check_encode_input(pixels, width, height));
+        let blocks = encode_source_blocks(pixels, width, height);
+        let mut data: Vec<u8> = vec![0; blocks.len() * 8];
+        for (b, block) in data.chunks_mut(8).zip(&blocks) {
+            let (a0, a1, indices) = dxt::encode_interpolated_alpha(&block_channel(block, 0), quality);
+            b[0] = a0;
+            b[1] = a1;
+            b[2..].copy_from_slice(&indices);
+        }
+
+        Ok(Ati1n {data: data, width: padded_width, height: padded_height, image_width: width, image_height: height})
+    }
+
+    /// Decodes the image into its single channel.
+    pub fn to_r8(&self) -> Vec<u8> {
+        let mut r = vec![0; self.width as usize * self.height as usize];
+
+        for (bi, b) in self.data.chunks(8).enumerate() {
+            let values = decode_alpha_block(b[0], b[1], &[b[2], b[3], b[4], b[5], b[6], b[7]]);
+            for (i, v) in values.iter().enumerate() {
+                r[block_pixel_index(self.width, bi, i)] = *v;
+            }
+        }
+
+        r
+    }
+}
+
+impl VTFImage for Ati1n {
+    fn to_rgb8(&self) -> Vec<Rgb8> {
+        let mut rgb = Vec::with_capacity(self.width as usize * self.height as usize);
+        for r in self.to_r8() {
+            rgb.push(Rgb8 {red: r, green: r, blue: r});
+        }
+        rgb
+    }
+
+    /// Outputs the single channel as grayscale.
     fn to_rgba8(&self) -> Vec<Rgba8> {
+        rgba8_from_raw(&self.to_rgba8_raw())
+    }
+
+    fn decode_into(&self, dest: &mut [u8]) {
+        decode_blocks_into(&self.data, 8, self.width, dest, decode_ati1n_block);
+    }
+
+    fn get_width(&self) -> u16 {
+        self.width
+    }
+
+    fn get_height(&self) -> u16 {
+        self.height
+    }
+
+    fn image_dimensions(&self) -> (u16, u16) {
+        (self.image_width, self.image_height)
+    }
+}
+
+/// Two-channel block compressed image, also known as BC5. Used for normal maps, with
+/// the X and Y components stored as two ATI1N blocks.
+///
+/// By default, converting to RGB places X and Y in red and green, and leaves blue at
+/// zero. Enabling Z reconstruction instead computes blue from X and Y, which gives a
+/// standard RGB normal map.
+#[derive(Debug, Clone)]
+pub struct Ati2n {
+    data: Vec<u8>,
+    width: u16,
+    height: u16,
+    image_width: u16,
+    image_height: u16,
+    reconstruct_z: bool
+}
+
+impl Ati2n {
+    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<Ati2n, io::Error> where R: Read {
+        // Internally to the VTF file format, there are no images that are
+        // smaller than 4x4. This corrects for that.
+        let (padded_width, padded_height) = try!(load_block_dimensions(width, height));
+
+        let block_count = padded_width as usize * padded_height as usize / 16;
+        let mut data: Vec<u8> = vec![0; block_count * 16];
+        try!(source.read_exact(&mut data));
+
+        Ok(Ati2n {data: data, width: padded_width, height: padded_height, image_width: width, image_height: height, reconstruct_z: false})
+    }
+
+    pub fn save<W>(&self, dest: &mut W) -> Result<(), io::Error> where W: Write {
+        dest.write_all(&self.data)
+    }
+
+    /// The compressed blocks, exactly as they're stored in the file. Blocks are 16 bytes
+    /// each, and run left to right, then top to bottom.
+    pub fn get_blocks(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Compresses the red and green channels of an image, given as `width * height`
+    /// pixels, as X and Y.
+    pub fn encode(pixels: &[Rgba8], width: u16, height: u16, quality: DxtQuality) -> Result<Ati2n, VTFError> {
+        let (padded_width, padded_height) = try!(check_encode_input(pixels, width, height));
+        let blocks = encode_source_blocks(pixels, width, height);
+        let mut data: Vec<u8> = vec![0; blocks.len() * 16];
+        for (b, block) in data.chunks_mut(16).zip(&blocks) {
+            for channel in 0..2 {
+                let (a0, a1, indices) = dxt::encode_interpolated_alpha(&block_channel(block, channel), quality);
+                let half = &mut b[channel * 8..channel * 8 + 8];
+                half[0] = a0;
+                half[1] = a1;
+                half[2..].copy_from_slice(&indices);
+            }
+        }
+
+        Ok(Ati2n {data: data, width: padded_width, height: padded_height, image_width: width, image_height: height, reconstruct_z: false})
+    }
+
+    /// Decodes the image into its two channels, X and Y.
+    pub fn to_rg8(&self) -> Vec<(u8, u8)> {
+        let mut rg = vec![(0, 0); self.width as usize * self.height as usize];
+
+        for (bi, b) in self.data.chunks(16).enumerate() {
+            let x = decode_alpha_block(b[0], b[1], &[b[2], b[3], b[4], b[5], b[6], b[7]]);
+            let y = decode_alpha_block(b[8], b[9], &[b[10], b[11], b[12], b[13], b[14], b[15]]);
+            for i in 0..16 {
+                rg[block_pixel_index(self.width, bi, i)] = (x[i], y[i]);
+            }
+        }
+
+        rg
+    }
+
+    pub fn get_reconstruct_z(&self) -> bool {
+        self.reconstruct_z
+    }
+
+    /// Sets whether the blue channel is reconstructed from X and Y when converting to RGB.
+    pub fn set_reconstruct_z(&mut self, reconstruct_z: bool) {
+        self.reconstruct_z = reconstruct_z;
+    }
+}
+
+impl VTFImage for Ati2n {
+    fn to_rgb8(&self) -> Vec<Rgb8> {
+        let rg = self.to_rg8();
+
+        let mut rgb = Vec::with_capacity(rg.len());
+        for (x, y) in rg {
+            let z = match self.reconstruct_z {
+                true => reconstruct_normal_z(x, y),
+                false => 0
+            };
+            rgb.push(Rgb8 {red: x, green: y, blue: z});
+        }
+        rgb
+    }
+
+    fn to_rgba8(&self) -> Vec<Rgba8> {
+        rgba8_from_raw(&self.to_rgba8_raw())
+    }
+
+    fn decode_into(&self, dest: &mut [u8]) {
+        let reconstruct_z = self.reconstruct_z;
+        decode_blocks_into(&self.data, 16, self.width, dest, |b| decode_ati2n_block(b, reconstruct_z));
+    }
+
+    fn get_width(&self) -> u16 {
+        self.width
+    }
+
+    fn get_height(&self) -> u16 {
+        self.height
+    }
+
+    fn image_dimensions(&self) -> (u16, u16) {
+        (self.image_width, self.image_height)
+    }
+}
 
-        let pix_count = self.width as usize * self.height as usize;
-        let mut rgba: Vec<Rgba8> = Vec::with_capacity(pix_count);
-        unsafe{ rgba.set_len(pix_count) };
+/// An uncompressed image made up of pixels of a single `PixelType`.
+#[derive(Debug, Clone)]
+pub struct PixelImage<C> where C: PixelType {
+    data: Vec<C>,
+    width: u16,
+    height: u16
+}
 
-        let mut chunk_offset = 0;
-        for c in &self.data {
-            // Compute color data
-            let c0 = Rgb565::load(c.3).to_rgb8();
-            let c1 = Rgb565::load(c.4).to_rgb8();
-            let c2 = interp_color(&c0, &c1, true);
-            let c3 = interp_color(&c0, &c1, false); 
-
-            let color_data: [u8; 16] = [c.5[0] & 3, c.5[0] >> 2 & 3, c.5[0] >> 4 & 3, c.5[0] >> 6 & 3,
-                                        c.5[1] & 3, c.5[1] >> 2 & 3, c.5[1] >> 4 & 3, c.5[1] >> 6 & 3,
-                                        c.5[2] & 3, c.5[2] >> 2 & 3, c.5[2] >> 4 & 3, c.5[2] >> 6 & 3,
-                                        c.5[3] & 3, c.5[3] >> 2 & 3, c.5[3] >> 4 & 3, c.5[3] >> 6 & 3];
-
-            // Compute alpha data
-            let a0 = c.0;
-            let a1 = c.1;
-            // Array of the raw, interpolated alpha values
-            let alookup: [u8; 8];
-
-            // Note: the following if/else statement is adapted from a
-            // similar alpha computation statement in VTFLib's VTFFile.cpp
-
-            // 8-alpha or 6-alpha block?    
-            if a0 > a1 {
-
-                // 8-bit alpha block.
-                // Bit code 000 = a0, 001 = a1, others are interpolated.
-                alookup = [
-                    a0,
-                    a1,
-                    interp_alpha_8bit(a0, a1, 0),
-                    interp_alpha_8bit(a0, a1, 1),
-                    interp_alpha_8bit(a0, a1, 2),
-                    interp_alpha_8bit(a0, a1, 3),
-                    interp_alpha_8bit(a0, a1, 4),
-                    interp_alpha_8bit(a0, a1, 5)
-                ];
-
-            } else {  
-
-                // 6-alpha block.    
-                // Bit code 000 = alpha_0, 001 = alpha_1, others are interpolated.
-                alookup = [
-                    a0,
-                    a1,
-                    interp_alpha_6bit(a0, a1, 0),
-                    interp_alpha_6bit(a0, a1, 1),
-                    interp_alpha_6bit(a0, a1, 2),
-                    interp_alpha_6bit(a0, a1, 3),
-                    0x00,
-                    0xFF
-                ];
-            }
+pub type Rgba8Image = PixelImage<Rgba8>;
+pub type Abgr8Image = PixelImage<Abgr8>;
+pub type Rgb8Image = PixelImage<Rgb8>;
+pub type Bgr8Image = PixelImage<Bgr8>;
+pub type Rgb565Image = PixelImage<Rgb565>;
+pub type I8Image = PixelImage<I8>;
+pub type Ia8Image = PixelImage<Ia8>;
+pub type A8Image = PixelImage<A8>;
+pub type Argb8Image = PixelImage<Argb8>;
+pub type Bgra8Image = PixelImage<Bgra8>;
+pub type Bgrx8Image = PixelImage<Bgrx8>;
+pub type Bgr565Image = PixelImage<Bgr565>;
+pub type Bgrx5551Image = PixelImage<Bgrx5551>;
+pub type Bgra4444Image = PixelImage<Bgra4444>;
+pub type Bgra5551Image = PixelImage<Bgra5551>;
+pub type Uv8Image = PixelImage<Uv8>;
+pub type Uvwq8Image = PixelImage<Uvwq8>;
+pub type Uvlx8Image = PixelImage<Uvlx8>;
+pub type Rgb8BluescreenImage = PixelImage<Rgb8Bluescreen>;
+pub type Bgr8BluescreenImage = PixelImage<Bgr8Bluescreen>;
+
+impl<C> PixelImage<C> where C: PixelType {
+    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<PixelImage<C>, io::Error> where R: Read {
+        let pix_count = width as usize * height as usize;
+        let pix_size = C::byte_size();
 
-            let alpha_data: [u8; 16] = {
-                use std::mem::transmute;
+        let mut data_buffer: Vec<u8> = vec![0; pix_count * pix_size];
+        try!(source.read_exact(&mut data_buffer));
 
-                let alpha: u64 = unsafe{ transmute([c.2[0], c.2[1], c.2[2], c.2[3], c.2[4], c.2[5], 00, 00]) };
-                [(alpha & 7)       as u8, (alpha >> 3 & 7)  as u8, (alpha >> 6 & 7)  as u8, (alpha >> 9 & 7)  as u8,
-                 (alpha >> 11 & 7) as u8, (alpha >> 15 & 7) as u8, (alpha >> 18 & 7) as u8, (alpha >> 21 & 7) as u8,
-                 (alpha >> 24 & 7) as u8, (alpha >> 27 & 7) as u8, (alpha >> 30 & 7) as u8, (alpha >> 33 & 7) as u8,
-                 (alpha >> 36 & 7) as u8, (alpha >> 39 & 7) as u8, (alpha >> 42 & 7) as u8, (alpha >> 45 & 7) as u8]
-                
-            };
+        let mut data: Vec<C> = Vec::with_capacity(pix_count);
+        for p in data_buffer.chunks(pix_size) {
+            data.push(C::from_bytes(p));
+        }
 
-            // The index for adding data to the chunk array
-            let mut index: usize = 0;
-            // The line of the chunk to write data to
-            let mut cline = 0;
-            let mut i = 0;
-            while i < 16 {
-                let rgba_offset = chunk_offset + index + (self.width*cline) as usize;
-                match color_data[i] {
-                    0 => rgba[rgba_offset] = Rgba8{red: c0.red, green: c0.green, blue: c0.blue, alpha: alookup[alpha_data[i] as usize]},
-                    1 => rgba[rgba_offset] = Rgba8{red: c1.red, green: c1.green, blue: c1.blue, alpha: alookup[alpha_data[i] as usize]},
-                    2 => rgba[rgba_offset] = Rgba8{red: c2.red, green: c2.green, blue: c2.blue, alpha: alookup[alpha_data[i] as usize]},
-                    3 => rgba[rgba_offset] = Rgba8{red: c3.red, green: c3.green, blue: c3.blue, alpha: alookup[alpha_data[i] as usize]},
-                    _ => unreachable!()
-                }
-                
+        Ok(PixelImage{data: data, width: width, height: height})
+    }
 
-                index += 1;
-                if index > 3 {
-                    index = 0;
-                    cline += 1;
-                }
-                i += 1;
-            }
-            
-            chunk_offset += 4;
-            let wusize = self.width as usize;
-            if chunk_offset % wusize == 0 && chunk_offset >= wusize {
-                chunk_offset += wusize * 3;
-            }
+    pub fn save<W>(&self, dest: &mut W) -> Result<(), io::Error> where W: Write {
+        let pix_size = C::byte_size();
+
+        let mut data_buffer: Vec<u8> = vec![0; self.data.len() * pix_size];
+        for (b, p) in data_buffer.chunks_mut(pix_size).zip(&self.data) {
+            p.to_bytes(b);
+        }
+
+        dest.write_all(&data_buffer)
+    }
+
+    /// Converts an image, given as `width * height` pixels, to the pixel type.
+    pub fn from_rgba8(pixels: &[Rgba8], width: u16, height: u16) -> PixelImage<C> {
+        assert_eq!(pixels.len(), width as usize * height as usize, "Pixel count doesn't match the image dimensions");
+
+        PixelImage {
+            data: pixels.iter().map(|p| C::from_rgba8888(p.clone())).collect(),
+            width: width,
+            height: height
+        }
+    }
+
+    pub fn get_pixels(&self) -> &[C] {
+        &self.data
+    }
+}
+
+impl<C> VTFImage for PixelImage<C> where C: PixelType {
+    fn to_rgb8(&self) -> Vec<Rgb8> {
+        let mut rgb: Vec<Rgb8> = Vec::with_capacity(self.data.len());
+
+        for p in &self.data {
+            rgb.push(p.to_rgb8());
+        }
+
+        rgb
+    }
+
+    fn to_rgba8(&self) -> Vec<Rgba8> {
+        let mut rgba: Vec<Rgba8> = Vec::with_capacity(self.data.len());
+
+        for p in &self.data {
+            rgba.push(p.to_rgba8());
         }
 
         rgba
@@ -627,54 +2080,71 @@ impl VTFImage for Dxt5 {
     }
 }
 
+
+
+/// Paletted image with one byte per pixel. VTF files don't store the palette
+/// themselves, so a grayscale ramp is used unless another palette is given.
 #[derive(Debug, Clone)]
-pub struct Bgr8Image {
-    data: Vec<Bgr8>,
+pub struct P8Image {
+    data: Vec<u8>,
     width: u16,
-    height: u16
+    height: u16,
+    palette: Vec<Rgba8>
 }
 
-impl Bgr8Image {
-    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<Bgr8Image, io::Error> where R: Read {
-        use std::mem::transmute;
+impl P8Image {
+    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<P8Image, io::Error> where R: Read {
+        let mut data: Vec<u8> = vec![0; width as usize * height as usize];
+        try!(source.read_exact(&mut data));
 
-        let pix_count = width as usize * height as usize;
+        let mut palette = Vec::with_capacity(256);
+        for i in 0..256 {
+            palette.push(Rgba8 {red: i as u8, green: i as u8, blue: i as u8, alpha: 255});
+        }
+
+        Ok(P8Image {data: data, width: width, height: height, palette: palette})
+    }
 
-        let mut data: Vec<Bgr8> = Vec::with_capacity(pix_count);
+    /// Writes the image's palette indices. The palette itself isn't saved.
+    pub fn save<W>(&self, dest: &mut W) -> Result<(), io::Error> where W: Write {
+        dest.write_all(&self.data)
+    }
 
+    /// Gets the palette index of every pixel.
+    pub fn get_indices(&self) -> &[u8] {
+        &self.data
+    }
 
-        let mut data_buffer: [u8; 3] = [0; 3];
-        let mut index = 0;
-        while index < pix_count {
-            try!(source.read(&mut data_buffer));
-            data.push(unsafe{ transmute(data_buffer) });
-            index += 1;
-        }
+    pub fn get_palette(&self) -> &[Rgba8] {
+        &self.palette
+    }
 
-        Ok(Bgr8Image{data: data, width: width, height: height})
+    /// Sets the palette used to decode the image. Indices past the end of the palette
+    /// decode as transparent black.
+    pub fn set_palette(&mut self, palette: Vec<Rgba8>) {
+        self.palette = palette;
     }
 }
 
-impl VTFImage for Bgr8Image {
+impl VTFImage for P8Image {
     fn to_rgb8(&self) -> Vec<Rgb8> {
-        let pix_count = self.width as usize * self.height as usize;
+        let mut rgb: Vec<Rgb8> = Vec::with_capacity(self.data.len());
 
-        let mut rgb: Vec<Rgb8> = Vec::with_capacity(pix_count);
-
-        for p in &self.data {
-            rgb.push(p.to_rgb8());
+        for c in self.to_rgba8() {
+            rgb.push(c.to_rgb8());
         }
 
         rgb
     }
 
     fn to_rgba8(&self) -> Vec<Rgba8> {
-        let pix_count = self.width as usize * self.height as usize;
-
-        let mut rgba: Vec<Rgba8> = Vec::with_capacity(pix_count);
+        let mut rgba: Vec<Rgba8> = Vec::with_capacity(self.data.len());
 
-        for p in &self.data {
-            rgba.push(p.to_rgba8());
+        for i in &self.data {
+            rgba.push(match self.palette.get(*i as usize) {
+                Some(c) => c.clone(),
+                None => Rgba8 {red: 0, green: 0, blue: 0, alpha: 0}
+            });
         }
 
         rgba
@@ -689,54 +2159,89 @@ impl VTFImage for Bgr8Image {
     }
 }
 
+/// An uncompressed HDR image. Converting it to 8-bit colors applies the image's
+/// tone-map operator, which is `ToneMap::Clamp` unless set otherwise.
 #[derive(Debug, Clone)]
-pub struct Bgra8Image {
-    data: Vec<Bgra8>,
+pub struct HdrImage<C> where C: HdrPixelType {
+    data: Vec<C>,
     width: u16,
-    height: u16
+    height: u16,
+    tone_map: ToneMap
 }
 
-impl Bgra8Image {
-    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<Bgra8Image, io::Error> where R: Read {
-        use std::mem::transmute;
+pub type Rgba16fImage = HdrImage<Rgba16f>;
+pub type Rgba16Image = HdrImage<Rgba16>;
+pub type R32fImage = HdrImage<R32f>;
+pub type Rgb32fImage = HdrImage<Rgb32f>;
+pub type Rgba32fImage = HdrImage<Rgba32f>;
 
+impl<C> HdrImage<C> where C: HdrPixelType {
+    pub fn load<R>(source: &mut R, width: u16, height: u16) -> Result<HdrImage<C>, io::Error> where R: Read {
         let pix_count = width as usize * height as usize;
+        let pix_size = C::byte_size();
+
+        let mut data_buffer: Vec<u8> = vec![0; pix_count * pix_size];
+        try!(source.read_exact(&mut data_buffer));
 
-        let mut data: Vec<Bgra8> = Vec::with_capacity(pix_count);
+        let mut data: Vec<C> = Vec::with_capacity(pix_count);
+        for p in data_buffer.chunks(pix_size) {
+            data.push(C::from_bytes(p));
+        }
+
+        Ok(HdrImage{data: data, width: width, height: height, tone_map: ToneMap::default()})
+    }
 
+    pub fn save<W>(&self, dest: &mut W) -> Result<(), io::Error> where W: Write {
+        let pix_size = C::byte_size();
 
-        let mut data_buffer: [u8; 4] = [0; 4];
-        let mut index = 0;
-        while index < pix_count {
-            try!(source.read(&mut data_buffer));
-            data.push(unsafe{ transmute(data_buffer) });
-            index += 1;
+        let mut data_buffer: Vec<u8> = vec![0; self.data.len() * pix_size];
+        for (b, p) in data_buffer.chunks_mut(pix_size).zip(&self.data) {
+            p.to_bytes(b);
         }
 
-        Ok(Bgra8Image{data: data, width: width, height: height})
+        dest.write_all(&data_buffer)
+    }
+
+    pub fn get_pixels(&self) -> &[C] {
+        &self.data
+    }
+
+    pub fn get_tone_map(&self) -> ToneMap {
+        self.tone_map
+    }
+
+    /// Sets the operator used when converting the image to 8-bit colors.
+    pub fn set_tone_map(&mut self, tone_map: ToneMap) {
+        self.tone_map = tone_map;
     }
 }
 
-impl VTFImage for Bgra8Image {
+impl<C> VTFImage for HdrImage<C> where C: HdrPixelType {
     fn to_rgb8(&self) -> Vec<Rgb8> {
-        let pix_count = self.width as usize * self.height as usize;
-
-        let mut rgb: Vec<Rgb8> = Vec::with_capacity(pix_count);
+        let mut rgb: Vec<Rgb8> = Vec::with_capacity(self.data.len());
 
         for p in &self.data {
-            rgb.push(p.to_rgb8());
+            rgb.push(self.tone_map.apply(p.to_rgba32f()).to_rgb8());
         }
 
         rgb
     }
 
     fn to_rgba8(&self) -> Vec<Rgba8> {
-        let pix_count = self.width as usize * self.height as usize;
+        let mut rgba: Vec<Rgba8> = Vec::with_capacity(self.data.len());
+
+        for p in &self.data {
+            rgba.push(self.tone_map.apply(p.to_rgba32f()));
+        }
+
+        rgba
+    }
 
-        let mut rgba: Vec<Rgba8> = Vec::with_capacity(pix_count);
+    fn to_rgba32f(&self) -> Vec<Rgba32f> {
+        let mut rgba: Vec<Rgba32f> = Vec::with_capacity(self.data.len());
 
         for p in &self.data {
-            rgba.push(p.to_rgba8());
+            rgba.push(p.to_rgba32f());
         }
 
         rgba
@@ -751,8 +2256,6 @@ impl VTFImage for Bgra8Image {
     }
 }
 
-
-
 pub trait VTFImage {
     fn to_rgb8(&self) -> Vec<Rgb8>;
     fn to_rgb8_raw(&self) -> Vec<u8> {
@@ -782,8 +2285,48 @@ pub trait VTFImage {
 
     fn to_rgba8(&self) -> Vec<Rgba8>;
     fn to_rgba8_raw(&self) -> Vec<u8> {
+        let mut rgba_raw = vec![0; self.get_width() as usize * self.get_height() as usize * 4];
+        self.decode_into(&mut rgba_raw);
+        rgba_raw
+    }
+
+    /// Decodes the image as 8-bit RGBA into a buffer provided by the caller, so the
+    /// buffer can be reused between images. `dest` must be exactly `width * height * 4`
+    /// bytes long.
+    ///
+    /// Compressed images are decoded one row of blocks at a time. With the `parallel`
+    /// feature enabled, the rows are split across threads.
+    fn decode_into(&self, dest: &mut [u8]) {
+        assert_eq!(dest.len(), self.get_width() as usize * self.get_height() as usize * 4, "Buffer size doesn't match the image dimensions");
+
+        for (d, p) in dest.chunks_mut(4).zip(self.to_rgba8()) {
+            d[0] = p.red;
+            d[1] = p.green;
+            d[2] = p.blue;
+            d[3] = p.alpha;
+        }
+    }
+
+    /// Decodes the image into linear floating-point colors. For HDR images this returns
+    /// the stored values without any tone-mapping. For everything else this maps each
+    /// 8-bit channel to the 0-1 range.
+    fn to_rgba32f(&self) -> Vec<Rgba32f> {
         let rgba = self.to_rgba8();
 
+        let mut rgba32f = Vec::with_capacity(rgba.len());
+        for p in &rgba {
+            rgba32f.push(Rgba32f {
+                red: p.red as f32 / 255.0,
+                green: p.green as f32 / 255.0,
+                blue: p.blue as f32 / 255.0,
+                alpha: p.alpha as f32 / 255.0
+            });
+        }
+        rgba32f
+    }
+    fn to_rgba32f_raw(&self) -> Vec<f32> {
+        let rgba = self.to_rgba32f();
+
         let mut rgba_raw = Vec::with_capacity(rgba.len() * 4);
         for p in &rgba {
             rgba_raw.push(p.red);
@@ -792,23 +2335,181 @@ pub trait VTFImage {
             rgba_raw.push(p.alpha);
         }
         rgba_raw
+    }
 
-        /*
-        use std::mem;
-
-        let mut rgba = self.to_rgba8();
-        unsafe{
-            let ptr = rgba.as_mut_ptr();
-            let len = rgba.len() * 4;
-            let cap = rgba.capacity() * 4;
-
-            Vec::from_raw_parts(mem::transmute(ptr), len, cap)
+    /// Decodes the image into an `image` crate buffer, leaving out any block padding.
+    fn to_rgba_image(&self) -> RgbaImage {
+        let (width, height) = self.image_dimensions();
+        let mut raw = self.to_rgba8_raw();
+        if (width, height) != (self.get_width(), self.get_height()) {
+            raw = raw.chunks(self.get_width() as usize * 4)
+                .take(height as usize)
+                .flat_map(|row| row[..width as usize * 4].iter().cloned())
+                .collect();
         }
-        */
+
+        ImageBuffer::from_raw(width as u32, height as u32, raw)
+            .expect("Decoded image does not match its dimensions")
+    }
+    fn to_dynamic_image(&self) -> DynamicImage {
+        DynamicImage::ImageRgba8(self.to_rgba_image())
     }
 
     fn get_width(&self) -> u16;
     fn get_height(&self) -> u16;
+
+    /// The width and height the image was created with. Compressed images pad these
+    /// out to whole 4x4 blocks, which `get_width` and `get_height` include and this
+    /// doesn't.
+    fn image_dimensions(&self) -> (u16, u16) {
+        (self.get_width(), self.get_height())
+    }
+}
+
+/// Gets the pixels of an `image` crate buffer.
+pub(super) fn rgba_image_pixels(image: &RgbaImage) -> Vec<Rgba8> {
+    image.pixels()
+        .map(|p| Rgba8 {red: p.data[0], green: p.data[1], blue: p.data[2], alpha: p.data[3]})
+        .collect()
+}
+
+/// Decodes an image, keeping only the top-left `width` by `height` pixels. This drops
+/// the padding that compressed formats add to fill out their last row and column of
+/// blocks.
+pub(super) fn decode_cropped(image: &VTFImageWrapper, width: u16, height: u16) -> Vec<Rgba8> {
+    let image = image.expose();
+    let pixels = image.to_rgba8();
+    if (image.get_width(), image.get_height()) == (width, height) {
+        return pixels;
+    }
+
+    let stored_width = image.get_width() as usize;
+    let width = cmp::min(width as usize, stored_width);
+    pixels.chunks(stored_width)
+        .take(height as usize)
+        .flat_map(|row| row[..width].iter().cloned())
+        .collect()
+}
+
+/// Decodes 4x4 blocks of `block_size` bytes into `dest` as 8-bit RGBA. `width` must be
+/// a multiple of 4, and each row of blocks is decoded independently.
+fn decode_blocks_into<F>(data: &[u8], block_size: usize, width: u16, dest: &mut [u8], decode: F)
+        where F: Fn(&[u8]) -> [[u8; 4]; 16] + Sync {
+    let width = width as usize;
+    assert_eq!(dest.len(), data.len() / block_size * 16 * 4, "Buffer size doesn't match the image dimensions");
+    if width == 0 {
+        return;
+    }
+
+    let block_row_size = width / 4 * block_size;
+    let dest_row_size = width * 4 * 4;
+
+    #[cfg(feature = "parallel")]
+    {
+        dest.par_chunks_mut(dest_row_size).zip(data.par_chunks(block_row_size))
+            .for_each(|(d, row)| decode_block_row(row, block_size, width, d, &decode));
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        for (d, row) in dest.chunks_mut(dest_row_size).zip(data.chunks(block_row_size)) {
+            decode_block_row(row, block_size, width, d, &decode);
+        }
+    }
+}
+
+/// Decodes a single row of blocks into the four rows of pixels it covers.
+fn decode_block_row<F>(row: &[u8], block_size: usize, width: usize, dest: &mut [u8], decode: &F)
+        where F: Fn(&[u8]) -> [[u8; 4]; 16] {
+    for (bx, block) in row.chunks(block_size).enumerate() {
+        for (i, p) in decode(block).iter().enumerate() {
+            let offset = ((i / 4) * width + bx * 4 + i % 4) * 4;
+            dest[offset..offset + 4].copy_from_slice(p);
+        }
+    }
+}
+
+/// Decodes the 8-byte color half of a DXT block. If `punch_through` is set, blocks
+/// whose first endpoint isn't greater than the second only have three colors, and the
+/// fourth index is transparent black. Otherwise every block has four colors.
+fn decode_color_block(b: &[u8], punch_through: bool) -> [[u8; 4]; 16] {
+    let (e0, e1) = (read_u16(&b[0..2]), read_u16(&b[2..4]));
+    let c0 = Rgb565::load(e0).to_rgb8();
+    let c1 = Rgb565::load(e1).to_rgb8();
+
+    let (c2, c3) = match !punch_through || e0 > e1 {
+        true => {
+            let c2 = interp_color(&c0, &c1, true);
+            let c3 = interp_color(&c0, &c1, false);
+            ([c2.red, c2.green, c2.blue, 255], [c3.red, c3.green, c3.blue, 255])
+        },
+        false => {
+            let c2 = [((c0.red as u16 + c1.red as u16) / 2) as u8,
+                      ((c0.green as u16 + c1.green as u16) / 2) as u8,
+                      ((c0.blue as u16 + c1.blue as u16) / 2) as u8,
+                      255];
+            (c2, [0, 0, 0, 0])
+        }
+    };
+    let palette = [[c0.red, c0.green, c0.blue, 255], [c1.red, c1.green, c1.blue, 255], c2, c3];
+
+    let mut pixels = [[0; 4]; 16];
+    for (i, p) in pixels.iter_mut().enumerate() {
+        *p = palette[(b[4 + i / 4] >> (i % 4 * 2) & 3) as usize];
+    }
+    pixels
+}
+
+fn decode_dxt1_block(b: &[u8]) -> [[u8; 4]; 16] {
+    decode_color_block(b, true)
+}
+
+fn decode_dxt3_block(b: &[u8]) -> [[u8; 4]; 16] {
+    let mut pixels = decode_color_block(&b[8..16], false);
+    for (i, p) in pixels.iter_mut().enumerate() {
+        p[3] = (b[i / 2] >> (i % 2 * 4) & 15) * 17;
+    }
+    pixels
+}
+
+fn decode_dxt5_block(b: &[u8]) -> [[u8; 4]; 16] {
+    let mut pixels = decode_color_block(&b[8..16], false);
+    let alpha = decode_alpha_block(b[0], b[1], &[b[2], b[3], b[4], b[5], b[6], b[7]]);
+    for (p, a) in pixels.iter_mut().zip(alpha.iter()) {
+        p[3] = *a;
+    }
+    pixels
+}
+
+fn decode_ati1n_block(b: &[u8]) -> [[u8; 4]; 16] {
+    let values = decode_alpha_block(b[0], b[1], &[b[2], b[3], b[4], b[5], b[6], b[7]]);
+
+    let mut pixels = [[0; 4]; 16];
+    for (p, r) in pixels.iter_mut().zip(values.iter()) {
+        *p = [*r, *r, *r, 255];
+    }
+    pixels
+}
+
+fn decode_ati2n_block(b: &[u8], reconstruct_z: bool) -> [[u8; 4]; 16] {
+    let x = decode_alpha_block(b[0], b[1], &[b[2], b[3], b[4], b[5], b[6], b[7]]);
+    let y = decode_alpha_block(b[8], b[9], &[b[10], b[11], b[12], b[13], b[14], b[15]]);
+
+    let mut pixels = [[0; 4]; 16];
+    for (i, p) in pixels.iter_mut().enumerate() {
+        let z = match reconstruct_z {
+            true => reconstruct_normal_z(x[i], y[i]),
+            false => 0
+        };
+        *p = [x[i], y[i], z, 255];
+    }
+    pixels
+}
+
+/// Splits a buffer of 8-bit RGBA into pixels.
+fn rgba8_from_raw(raw: &[u8]) -> Vec<Rgba8> {
+    raw.chunks(4)
+        .map(|p| Rgba8 {red: p[0], green: p[1], blue: p[2], alpha: p[3]})
+        .collect()
 }
 
 /// Interpolates between colors c0 and c1. When factor is false,
@@ -832,11 +2533,65 @@ fn interp_color(c0: &Rgb8, c1: &Rgb8, factor: bool) -> Rgb8 {
     }
 }
 
+/// Decodes an interpolated 8-byte block, as used by DXT5 alpha and by both ATI1N and
+/// ATI2N, into one value per pixel of the 4x4 block.
+fn decode_alpha_block(a0: u8, a1: u8, indices: &[u8; 6]) -> [u8; 16] {
+    // Array of the raw, interpolated alpha values
+    let alookup: [u8; 8];
+
+    // Note: the following if/else statement is adapted from a
+    // similar alpha computation statement in VTFLib's VTFFile.cpp
+
+    // 8-alpha or 6-alpha block?
+    if a0 > a1 {
+
+        // 8-bit alpha block.
+        // Bit code 000 = a0, 001 = a1, others are interpolated.
+        alookup = [
+            a0,
+            a1,
+            interp_alpha_8bit(a0, a1, 0),
+            interp_alpha_8bit(a0, a1, 1),
+            interp_alpha_8bit(a0, a1, 2),
+            interp_alpha_8bit(a0, a1, 3),
+            interp_alpha_8bit(a0, a1, 4),
+            interp_alpha_8bit(a0, a1, 5)
+        ];
+
+    } else {
+
+        // 6-alpha block.
+        // Bit code 000 = alpha_0, 001 = alpha_1, others are interpolated.
+        alookup = [
+            a0,
+            a1,
+            interp_alpha_6bit(a0, a1, 0),
+            interp_alpha_6bit(a0, a1, 1),
+            interp_alpha_6bit(a0, a1, 2),
+            interp_alpha_6bit(a0, a1, 3),
+            0x00,
+            0xFF
+        ];
+    }
+
+    // The indices are 16 3-bit values packed into a 48-bit little-endian integer
+    let mut bits: u64 = 0;
+    for (i, b) in indices.iter().enumerate() {
+        bits |= (*b as u64) << (i * 8);
+    }
+
+    let mut values = [0; 16];
+    for (i, v) in values.iter_mut().enumerate() {
+        *v = alookup[(bits >> (i * 3) & 7) as usize];
+    }
+    values
+}
+
 /// Interpolates between alphas a0 and a1. Factor is on a scale of
 /// 0-3, with 0 being mostly a0 and 3 being mostly a1. Anything
 /// outside of that range has undefined behavior.
 #[inline]
-fn interp_alpha_8bit(a0: u8, a1: u8, factor: u8) -> u8 {
+pub(super) fn interp_alpha_8bit(a0: u8, a1: u8, factor: u8) -> u8 {
     let a0 = a0 as u32;
     let a1 = a1 as u32;
     let factor = factor as u32;
@@ -847,10 +2602,386 @@ fn interp_alpha_8bit(a0: u8, a1: u8, factor: u8) -> u8 {
 /// 0-5, with 0 being mostly a0 and 5 being mostly a1. Anything
 /// outside of that range has undefined behavior.
 #[inline]
-fn interp_alpha_6bit(a0: u8, a1: u8, factor: u8) -> u8 {
+pub(super) fn interp_alpha_6bit(a0: u8, a1: u8, factor: u8) -> u8 {
     let a0 = a0 as u16;
     let a1 = a1 as u16;
     let factor = factor as u16;
 
     (((4-factor) * a0 + (1+factor) * a1 + 2) / 5) as u8
-}
\ No newline at end of file
+}
+
+/// Expands a five-bit color channel to eight bits
+#[inline]
+fn expand_5bit(c: u8) -> u8 {
+    c << 3 | c >> 2
+}
+
+/// Expands a six-bit color channel to eight bits
+#[inline]
+fn expand_6bit(c: u8) -> u8 {
+    c << 2 | c >> 4
+}
+
+/// Gets the alpha of a bluescreen color. Pure blue is fully transparent, and
+/// everything else is fully opaque.
+#[inline]
+fn bluescreen_alpha(red: u8, green: u8, blue: u8) -> u8 {
+    match (red, green, blue) {
+        (0, 0, 255) => 0,
+        _ => 255
+    }
+}
+
+/// Computes the luminance of a color, using the same weights as VTFLib
+#[inline]
+fn luminance(red: u8, green: u8, blue: u8) -> u8 {
+    (red as f32 * 0.299 + green as f32 * 0.587 + blue as f32 * 0.114) as u8
+}
+
+/// Converts a float in the 0-1 range to an 8-bit channel, clamping anything outside
+/// of that range
+#[inline]
+fn float_to_u8(c: f32) -> u8 {
+    if c >= 1.0 {
+        255
+    } else if c > 0.0 {
+        (c * 255.0 + 0.5) as u8
+    } else {
+        // Also catches NaN
+        0
+    }
+}
+
+#[inline]
+fn read_u16(b: &[u8]) -> u16 {
+    b[0] as u16 | (b[1] as u16) << 8
+}
+
+#[inline]
+fn read_f32(b: &[u8]) -> f32 {
+    let bits = b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24;
+    f32::from_bits(bits)
+}
+
+#[inline]
+fn write_u16(b: &mut [u8], value: u16) {
+    b[0] = value as u8;
+    b[1] = (value >> 8) as u8;
+}
+
+#[inline]
+fn write_f32(b: &mut [u8], value: f32) {
+    let bits = value.to_bits();
+    b[0] = bits as u8;
+    b[1] = (bits >> 8) as u8;
+    b[2] = (bits >> 16) as u8;
+    b[3] = (bits >> 24) as u8;
+}
+
+/// Converts an IEEE 754 half-precision float to a single-precision float
+fn half_to_f32(half: u16) -> f32 {
+    let sign = ((half >> 15) as u32) << 31;
+    let exponent = ((half >> 10) & 0x1F) as u32;
+    let mantissa = (half & 0x3FF) as u32;
+
+    let bits = match exponent {
+        0 => {
+            if mantissa == 0 {
+                // Signed zero
+                sign
+            } else {
+                // Subnormal. Normalize the mantissa, adjusting the exponent to match.
+                let mut exponent = 127 - 15 + 1;
+                let mut mantissa = mantissa;
+                while mantissa & 0x400 == 0 {
+                    mantissa <<= 1;
+                    exponent -= 1;
+                }
+                sign | exponent << 23 | (mantissa & 0x3FF) << 13
+            }
+        },
+        // Infinity or NaN
+        0x1F => sign | 0xFF << 23 | mantissa << 13,
+        _ => sign | (exponent + 127 - 15) << 23 | mantissa << 13
+    };
+
+    f32::from_bits(bits)
+}
+
+/// Pads the dimensions of a block compressed image out to whole 4x4 blocks. Returns
+/// `None` if the padded size doesn't fit in a `u16`, since `ImageFormat::data_size`
+/// would count more blocks than the padded width and height could hold.
+fn block_dimensions(width: u16, height: u16) -> Option<(u16, u16)> {
+    let pad = |d: u16| -> Option<u16> {
+        let padded = cmp::max((d as u32 + 3) / 4, 1) * 4;
+        match padded <= u16::max_value() as u32 {
+            true => Some(padded as u16),
+            false => None
+        }
+    };
+
+    match (pad(width), pad(height)) {
+        (Some(width), Some(height)) => Some((width, height)),
+        _ => None
+    }
+}
+
+fn load_block_dimensions(width: u16, height: u16) -> Result<(u16, u16), io::Error> {
+    block_dimensions(width, height)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Image is too large to pad out to whole blocks"))
+}
+
+/// Checks the image given to a block encoder, and gets its padded dimensions.
+fn check_encode_input(pixels: &[Rgba8], width: u16, height: u16) -> Result<(u16, u16), VTFError> {
+    if pixels.len() != width as usize * height as usize {
+        return Err(VTFError::ImageMismatch);
+    }
+    block_dimensions(width, height).ok_or(VTFError::ImageSize)
+}
+
+/// Splits an image into 4x4 blocks for compression, left to right, top to bottom.
+/// Blocks that hang off the edge of the image repeat its last row and column.
+fn encode_source_blocks(pixels: &[Rgba8], width: u16, height: u16) -> Vec<[[u8; 4]; 16]> {
+    assert_eq!(pixels.len(), width as usize * height as usize, "Pixel count doesn't match the image dimensions");
+
+    let (width, height) = (cmp::max(width, 1) as usize, cmp::max(height, 1) as usize);
+    let (blocks_wide, blocks_high) = ((width + 3) / 4, (height + 3) / 4);
+
+    let mut blocks = Vec::with_capacity(blocks_wide * blocks_high);
+    for by in 0..blocks_high {
+        for bx in 0..blocks_wide {
+            let mut block = [[0; 4]; 16];
+            for (i, p) in block.iter_mut().enumerate() {
+                let x = cmp::min(bx * 4 + i % 4, width - 1);
+                let y = cmp::min(by * 4 + i / 4, height - 1);
+                let c = &pixels[y * width + x];
+                *p = [c.red, c.green, c.blue, c.alpha];
+            }
+            blocks.push(block);
+        }
+    }
+
+    blocks
+}
+
+#[inline]
+fn block_alphas(block: &[[u8; 4]; 16]) -> [u8; 16] {
+    block_channel(block, 3)
+}
+
+/// Pulls a single channel out of a block from `encode_source_blocks`.
+fn block_channel(block: &[[u8; 4]; 16], channel: usize) -> [u8; 16] {
+    let mut values = [0; 16];
+    for (v, p) in values.iter_mut().zip(block.iter()) {
+        *v = p[channel];
+    }
+    values
+}
+
+/// Gets the index into an image's pixel array of pixel `pixel` (0-15) of 4x4 block
+/// `block`, with blocks stored left to right, top to bottom.
+#[inline]
+fn block_pixel_index(width: u16, block: usize, pixel: usize) -> usize {
+    let blocks_wide = width as usize / 4;
+    let x = (block % blocks_wide) * 4 + pixel % 4;
+    let y = (block / blocks_wide) * 4 + pixel / 4;
+    y * width as usize + x
+}
+
+/// Computes the Z component of a unit-length normal from its X and Y components,
+/// each mapped from the 0-255 range to -1 to 1.
+fn reconstruct_normal_z(x: u8, y: u8) -> u8 {
+    let x = x as f32 / 127.5 - 1.0;
+    let y = y as f32 / 127.5 - 1.0;
+    let z_squared = 1.0 - x * x - y * y;
+    let z = if z_squared > 0.0 {z_squared.sqrt()} else {0.0};
+
+    float_to_u8(z * 0.5 + 0.5)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{VTFImage, VTFImageWrapper, DxtQuality, Rgba8, Dxt1, Dxt3, Dxt5, Ati1n, Ati2n, block_dimensions, block_pixel_index,
+                decode_dxt1_block, decode_dxt3_block, decode_dxt5_block, decode_ati1n_block, decode_ati2n_block,
+                PixelType, ColorType, HdrPixelType, Rgb565, Bgr565, Bgrx5551, Bgra5551, Bgra4444, Rgba16f, half_to_f32};
+    use super::super::format::ImageFormat;
+    use super::super::error::{VTFLoadError, VTFError};
+    use ::image::{ImageBuffer, Rgba};
+
+    #[test]
+    fn rgba_image_round_trip_drops_block_padding() {
+        let formats = [ImageFormat::DXT1, ImageFormat::DXT3, ImageFormat::DXT5, ImageFormat::ATI1N, ImageFormat::ATI2N, ImageFormat::BGRA8888];
+        for &format in formats.iter() {
+            for &(width, height) in &[(2, 2), (6, 6), (8, 1)] {
+                let source = ImageBuffer::from_pixel(width, height, Rgba([255, 255, 0, 255]));
+                let image = VTFImageWrapper::from_rgba_image(&source, format, DxtQuality::default()).unwrap();
+                let decoded = image.to_rgba_image();
+
+                assert_eq!(decoded.dimensions(), (width, height), "{:?}", format);
+                assert_eq!(decoded.get_pixel(width - 1, height - 1).data[0], 255, "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn rgba_image_round_trip_2x2() {
+        // Black and white both sit on the block's endpoints, so they come back exactly
+        let source = ImageBuffer::from_fn(2, 2, |x, _| Rgba([(x * 255) as u8, (x * 255) as u8, (x * 255) as u8, 255]));
+        let image = VTFImageWrapper::from_rgba_image(&source, ImageFormat::DXT5, DxtQuality::default()).unwrap();
+        assert_eq!(image.expose().get_width(), 4);
+
+        let decoded = image.to_rgba_image();
+        assert_eq!(decoded.dimensions(), (2, 2));
+        assert_eq!(decoded.into_raw(), source.into_raw());
+    }
+
+    #[test]
+    fn encode_rejects_mismatched_pixel_counts() {
+        let pixels = vec![Rgba8 {red: 0, green: 0, blue: 0, alpha: 255}; 15];
+        for &format in &[ImageFormat::DXT1, ImageFormat::DXT5, ImageFormat::ATI2N, ImageFormat::RGBA8888, ImageFormat::BGR565] {
+            match VTFImageWrapper::encode(&pixels, 4, 4, format, DxtQuality::default()) {
+                Err(VTFLoadError::VTF(VTFError::ImageMismatch)) => (),
+                other => panic!("Unexpected result for {:?}: {:?}", format, other.map(|im| im.format()))
+            }
+        }
+    }
+
+    #[test]
+    fn block_padding_has_to_fit() {
+        assert_eq!(block_dimensions(0, 1), Some((4, 4)));
+        assert_eq!(block_dimensions(65532, 5), Some((65532, 8)));
+        assert_eq!(block_dimensions(65533, 4), None);
+        assert_eq!(block_dimensions(4, 65535), None);
+
+        let pixels = vec![Rgba8 {red: 0, green: 0, blue: 0, alpha: 255}; 65533];
+        assert_eq!(Dxt1::encode(&pixels, 65533, 1, DxtQuality::default()).unwrap_err(), VTFError::ImageSize);
+        assert_eq!(Ati2n::encode(&pixels, 1, 65533, DxtQuality::default()).unwrap_err(), VTFError::ImageSize);
+        assert_eq!(Dxt1::load(&mut &[0; 8][..], 65533, 1).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    /// Deterministic noise, so every block decodes differently
+    fn noise(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len).map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8
+        }).collect()
+    }
+
+    /// Decodes blocks one at a time, without going through `decode_into`
+    fn decode_serially<F>(data: &[u8], block_size: usize, width: u16, height: u16, decode: F) -> Vec<u8> where F: Fn(&[u8]) -> [[u8; 4]; 16] {
+        let mut raw = vec![0; width as usize * height as usize * 4];
+        for (bi, block) in data.chunks(block_size).enumerate() {
+            for (i, p) in decode(block).iter().enumerate() {
+                let offset = block_pixel_index(width, bi, i) * 4;
+                raw[offset..offset + 4].copy_from_slice(p);
+            }
+        }
+        raw
+    }
+
+    #[test]
+    fn decode_into_matches_serial_decoding() {
+        // Neither width is a multiple of 16 blocks, so rows don't split evenly
+        for &(width, height) in &[(36, 8), (20, 12), (4, 68)] {
+            let blocks = width as usize * height as usize / 16;
+            let data8 = noise(blocks * 8, width as u32);
+            let data16 = noise(blocks * 16, height as u32);
+
+            let images: Vec<(Box<VTFImage>, Vec<u8>)> = vec![
+                (Box::new(Dxt1::load(&mut &data8[..], width, height).unwrap()), decode_serially(&data8, 8, width, height, decode_dxt1_block)),
+                (Box::new(Dxt3::load(&mut &data16[..], width, height).unwrap()), decode_serially(&data16, 16, width, height, decode_dxt3_block)),
+                (Box::new(Dxt5::load(&mut &data16[..], width, height).unwrap()), decode_serially(&data16, 16, width, height, decode_dxt5_block)),
+                (Box::new(Ati1n::load(&mut &data8[..], width, height).unwrap()), decode_serially(&data8, 8, width, height, decode_ati1n_block)),
+                (Box::new(Ati2n::load(&mut &data16[..], width, height).unwrap()), decode_serially(&data16, 16, width, height, |b| decode_ati2n_block(b, false)))
+            ];
+
+            // The buffer is reused between images, so stale data would show up
+            let mut buffer = vec![0xAB; width as usize * height as usize * 4];
+            for (index, &(ref image, ref expected)) in images.iter().enumerate() {
+                image.decode_into(&mut buffer);
+                assert!(buffer == *expected, "image {} at {}x{}", index, width, height);
+                assert!(image.to_rgba8_raw() == *expected, "image {} at {}x{}", index, width, height);
+            }
+        }
+    }
+
+    fn decode_pixel<P: PixelType>(bytes: &[u8]) -> [u8; 4] {
+        let rgba = P::from_bytes(bytes).to_rgba8();
+        [rgba.red, rgba.green, rgba.blue, rgba.alpha]
+    }
+
+    #[test]
+    fn rgb565_and_bgr565_swap_red_and_blue() {
+        // The low five bits are red in RGB565 and blue in BGR565
+        assert_eq!(decode_pixel::<Rgb565>(&[0x1F, 0x00]), [255, 0, 0, 255]);
+        assert_eq!(decode_pixel::<Bgr565>(&[0x1F, 0x00]), [0, 0, 255, 255]);
+
+        assert_eq!(decode_pixel::<Rgb565>(&[0x00, 0xF8]), [0, 0, 255, 255]);
+        assert_eq!(decode_pixel::<Bgr565>(&[0x00, 0xF8]), [255, 0, 0, 255]);
+
+        // Green sits in the middle six bits of both
+        assert_eq!(decode_pixel::<Rgb565>(&[0xE0, 0x07]), [0, 255, 0, 255]);
+        assert_eq!(decode_pixel::<Bgr565>(&[0xE0, 0x07]), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn bgrx5551_ignores_the_high_bit() {
+        let pixel = Bgrx5551::from_bytes(&[0x1F, 0x80]);
+        assert_eq!((pixel.blue, pixel.green, pixel.red, pixel.unused), (31, 0, 0, true));
+
+        assert_eq!(decode_pixel::<Bgrx5551>(&[0x1F, 0x80]), [0, 0, 255, 255]);
+        assert_eq!(decode_pixel::<Bgrx5551>(&[0x1F, 0x00]), [0, 0, 255, 255]);
+        assert_eq!(decode_pixel::<Bgrx5551>(&[0xE0, 0x03]), [0, 255, 0, 255]);
+        assert_eq!(decode_pixel::<Bgrx5551>(&[0x00, 0x7C]), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bgra5551_alpha_is_the_high_bit() {
+        assert_eq!(decode_pixel::<Bgra5551>(&[0x00, 0xFC]), [255, 0, 0, 255]);
+        assert_eq!(decode_pixel::<Bgra5551>(&[0x00, 0x7C]), [255, 0, 0, 0]);
+        assert_eq!(decode_pixel::<Bgra5551>(&[0xE0, 0x83]), [0, 255, 0, 255]);
+        assert_eq!(decode_pixel::<Bgra5551>(&[0x1F, 0x00]), [0, 0, 255, 0]);
+    }
+
+    #[test]
+    fn bgra4444_nibble_order() {
+        let pixel = Bgra4444::from_bytes(&[0x21, 0x43]);
+        assert_eq!((pixel.blue, pixel.green, pixel.red, pixel.alpha), (1, 2, 3, 4));
+
+        // Four bit channels expand by repeating the nibble
+        assert_eq!(decode_pixel::<Bgra4444>(&[0x21, 0x43]), [0x33, 0x22, 0x11, 0x44]);
+        assert_eq!(decode_pixel::<Bgra4444>(&[0x0F, 0xF0]), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(half_to_f32(0x0000), 0.0);
+        assert!(half_to_f32(0x8000) == 0.0 && half_to_f32(0x8000).is_sign_negative());
+        assert_eq!(half_to_f32(0x3C00), 1.0);
+        assert_eq!(half_to_f32(0xC000), -2.0);
+        assert_eq!(half_to_f32(0x3555), 0.33325195);
+        assert_eq!(half_to_f32(0x7BFF), 65504.0);
+
+        // Denormals: the smallest, and the largest just below the smallest normal
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x03FF), 1023.0 * 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x8001), -2f32.powi(-24));
+        assert_eq!(half_to_f32(0x0400), 2f32.powi(-14));
+
+        assert_eq!(half_to_f32(0x7C00), ::std::f32::INFINITY);
+        assert_eq!(half_to_f32(0xFC00), ::std::f32::NEG_INFINITY);
+        assert!(half_to_f32(0x7E00).is_nan());
+    }
+
+    #[test]
+    fn rgba16f_decodes_each_channel() {
+        let pixel = Rgba16f::from_bytes(&[0x00, 0x3C, 0x00, 0x00, 0x01, 0x00, 0x00, 0x7C]).to_rgba32f();
+        assert_eq!(pixel.red, 1.0);
+        assert_eq!(pixel.green, 0.0);
+        assert_eq!(pixel.blue, 2f32.powi(-24));
+        assert_eq!(pixel.alpha, ::std::f32::INFINITY);
+    }
+}
@@ -0,0 +1,242 @@
+use std::cmp;
+use std::mem;
+
+use image::{imageops, DynamicImage, GenericImage, FilterType};
+
+use super::{VTFFile, VTFFrame, VTFFace, VTFResources, HeaderVersion, HeaderRoot, Header70, Header72, Header73, ImageFormat, VTFFlag, VTFFlags};
+use super::image::{VTFImageWrapper, Rgba8, DxtQuality, rgba_image_pixels};
+use super::mipmap::{self, MipFilter, MipOptions};
+use super::error::{VTFLoadError, VTFError};
+
+/// Options for building a VTF file from a source image.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportOptions {
+    /// The format the high-res image is stored in. HDR formats and `P8` aren't supported.
+    pub format: ImageFormat,
+    /// The texture's flags. The alpha flags are filled in from the format.
+    pub flags: VTFFlags,
+    /// Whether to generate a full mip chain. Ignored if `flags` contains `NOMIP`.
+    pub mips: bool,
+    /// The filter used to generate the mip chain
+    pub mip_filter: MipFilter,
+    /// The minor version of the file, from 7.0 to 7.5
+    pub version: u32,
+    /// Resizes images whose dimensions aren't powers of two to the nearest power of
+    /// two. If this is off, those images are rejected.
+    pub resize: bool,
+    /// The block encoder used for compressed formats
+    pub quality: DxtQuality,
+    /// Whether to generate the low-res thumbnail
+    pub thumbnail: bool
+}
+
+impl Default for ImportOptions {
+    fn default() -> ImportOptions {
+        ImportOptions {
+            format: ImageFormat::DXT5,
+            flags: VTFFlags::empty(),
+            mips: true,
+            mip_filter: MipFilter::Kaiser,
+            version: 2,
+            resize: true,
+            quality: DxtQuality::default(),
+            thumbnail: true
+        }
+    }
+}
+
+impl VTFFile {
+    /// Builds a single-frame VTF file from an image loaded with the `image` crate.
+    pub fn from_image(image: &DynamicImage, options: &ImportOptions) -> Result<VTFFile, VTFLoadError> {
+        let (mut width, mut height) = image.dimensions();
+        let mut rgba = image.to_rgba();
+
+        if options.resize {
+            let (pow_width, pow_height) = (nearest_power_of_two(width), nearest_power_of_two(height));
+            if (pow_width, pow_height) != (width, height) {
+                rgba = imageops::resize(&rgba, pow_width, pow_height, FilterType::Lanczos3);
+                width = pow_width;
+                height = pow_height;
+            }
+        }
+
+        if width > u16::max_value() as u32 || height > u16::max_value() as u32 {
+            return Err(VTFLoadError::VTF(VTFError::ImageSize));
+        }
+
+        VTFFile::from_rgba8(&rgba_image_pixels(&rgba), width as u16, height as u16, options)
+    }
+
+    /// Builds a single-frame VTF file from an image given as `width * height` pixels,
+    /// generating its mip chain, thumbnail and reflectivity. `options.resize` is ignored,
+    /// so the dimensions must be powers of two.
+    ///
+    /// Environment maps need a full set of faces, so they can't be built this way.
+    pub fn from_rgba8(pixels: &[Rgba8], width: u16, height: u16, options: &ImportOptions) -> Result<VTFFile, VTFLoadError> {
+        let mut flags = options.flags;
+        if flags.check(VTFFlag::ENVMAP) {
+            return Err(VTFLoadError::VTF(VTFError::ImageMismatch));
+        }
+        if !(width.is_power_of_two() && height.is_power_of_two()) {
+            return Err(VTFLoadError::VTF(VTFError::ImageSize));
+        }
+        if pixels.len() != width as usize * height as usize {
+            return Err(VTFLoadError::VTF(VTFError::ImageMismatch));
+        }
+        if options.version > 5 {
+            return Err(VTFLoadError::VTF(VTFError::HeaderVersion));
+        }
+
+        match alpha_flag(options.format) {
+            Some(alpha_flag) => flags.set(alpha_flag),
+            None => {
+                flags.clear(VTFFlag::ONEBITALPHA);
+                flags.clear(VTFFlag::EIGHTBITALPHA);
+            }
+        }
+
+        let mip_count = match options.mips && !flags.check(VTFFlag::NOMIP) {
+            true => mipmap::full_mip_count(width, height),
+            false => 1
+        };
+        let mip_options = MipOptions {
+            filter: options.mip_filter,
+            ..MipOptions::for_flags(flags)
+        };
+
+        let mut levels = Vec::with_capacity(mip_count as usize);
+        for level in try!(mipmap::generate_mip_count(pixels, width, height, mip_count, &mip_options).map_err(VTFLoadError::VTF)) {
+            levels.push(try!(VTFImageWrapper::encode(&level.pixels, level.width, level.height, options.format, options.quality)));
+        }
+        let slices = vec![levels.remove(0)];
+        let face = VTFFace {
+            mips: levels.into_iter().map(|l| vec![l]).collect(),
+            slices: slices
+        };
+
+        let header = new_header(options.version, width, height, 1, 1, flags, options.format, mip_count);
+        let mut vtf = VTFFile::from_parts(header, vec![VTFFrame {faces: vec![face]}]);
+        vtf.update_reflectivity();
+        if options.thumbnail {
+            vtf.generate_thumbnail();
+        }
+
+        Ok(vtf)
+    }
+
+    /// Creates a file with no thumbnail or resources from its header and images.
+    pub(super) fn from_parts(header: HeaderVersion, frames: Vec<VTFFrame>) -> VTFFile {
+        let resources = match header {
+            HeaderVersion::H73(..) => Some(Vec::new()),
+            _ => None
+        };
+
+        VTFFile {
+            header: header,
+            resources: resources,
+            thumb: None,
+            frames: frames,
+            resource_data: VTFResources::default(),
+            image_crc: None
+        }
+    }
+}
+
+/// Builds the header for a new file of the given minor version, with no thumbnail and
+/// a reflectivity of zero.
+pub(super) fn new_header(version: u32, width: u16, height: u16, depth: u16, frames: u16, flags: VTFFlags, format: ImageFormat, mip_count: u8) -> HeaderVersion {
+    let root = HeaderRoot {
+        type_string: unsafe{ mem::transmute(*b"VTF\0") },
+        version: [7, version as i32],
+        header_size: 0
+    };
+    let header70 = Header70 {
+        width: width,
+        height: height,
+        flags: flags.bits(),
+        frames: frames,
+        start_frame: 0,
+        padding_0: [0; 4],
+        reflectivity: [0.0; 3],
+        padding_1: [0; 4],
+        bump_scale: 1.0,
+        image_format: format,
+        mip_count: mip_count,
+        thumbnail_format: ImageFormat::NONE,
+        thumbnail_width: 0,
+        thumbnail_height: 0
+    };
+
+    match version {
+        0 | 1 => HeaderVersion::H70(root, header70),
+        2 => HeaderVersion::H72(root, header70, Header72 {depth: depth}),
+        _ => HeaderVersion::H73(root, header70, Header72 {depth: depth}, Header73 {padding: [0; 3], resource_count: 0})
+    }
+}
+
+/// The flag vtex sets for a format's alpha channel, if it has one.
+pub(super) fn alpha_flag(format: ImageFormat) -> Option<VTFFlag> {
+    match format {
+        ImageFormat::DXT1_ONEBITALPHA |
+        ImageFormat::BGRA5551 => Some(VTFFlag::ONEBITALPHA),
+        ImageFormat::RGBA8888 |
+        ImageFormat::ABGR8888 |
+        ImageFormat::ARGB8888 |
+        ImageFormat::BGRA8888 |
+        ImageFormat::BGRA4444 |
+        ImageFormat::IA88 |
+        ImageFormat::A8 |
+        ImageFormat::DXT3 |
+        ImageFormat::DXT5 => Some(VTFFlag::EIGHTBITALPHA),
+        _ => None
+    }
+}
+
+/// Rounds to the nearest power of two, rounding up on ties and staying within the
+/// range of a VTF's dimensions.
+fn nearest_power_of_two(n: u32) -> u32 {
+    let upper = cmp::min(cmp::max(n, 1).next_power_of_two(), 1 << 15);
+    let lower = cmp::max(upper / 2, 1);
+
+    match n - cmp::min(lower, n) < upper.saturating_sub(n) {
+        true => lower,
+        false => upper
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, ImageBuffer, Rgba};
+
+    use super::ImportOptions;
+    use super::super::{VTFFile, ImageFormat};
+    use super::super::image::Rgba8;
+
+    #[test]
+    fn import_non_square_image_smaller_than_a_block() {
+        let buffer = ImageBuffer::from_fn(8, 2, |x, y| Rgba([(x * 32) as u8, (y * 128) as u8, 64, 255]));
+        let vtf = VTFFile::from_image(&DynamicImage::ImageRgba8(buffer), &ImportOptions::default()).unwrap();
+
+        let header70 = vtf.header.get_h70();
+        assert_eq!((header70.width, header70.height, header70.mip_count), (8, 2, 4));
+        assert_eq!((header70.thumbnail_width, header70.thumbnail_height), (8, 2));
+
+        let bytes = vtf.to_bytes().unwrap();
+        let reloaded = VTFFile::from_reader(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(reloaded.header.get_h70().width, 8);
+    }
+
+    #[test]
+    fn import_one_pixel_wide_images() {
+        let pixels = vec![Rgba8 {red: 10, green: 20, blue: 30, alpha: 40}; 16];
+        for &format in &[ImageFormat::DXT1, ImageFormat::DXT3, ImageFormat::DXT5, ImageFormat::ATI1N, ImageFormat::ATI2N, ImageFormat::BGRA8888] {
+            for &(width, height) in &[(16, 1), (1, 16)] {
+                let options = ImportOptions {format: format, ..ImportOptions::default()};
+                let vtf = VTFFile::from_rgba8(&pixels, width, height, &options).unwrap();
+                assert_eq!(vtf.header.get_h70().mip_count, 5);
+            }
+        }
+    }
+}
@@ -0,0 +1,363 @@
+//! KTX2 export. Each supported format maps to the `VkFormat` with the same memory
+//! layout.
+
+use std::io::Write;
+
+use super::{VTFFile, ImageFormat, VTFFlag};
+use super::error::{VTFLoadError, VTFError};
+use super::resource::write_u32;
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+/// The size of the identifier, header and index, which come before the level index
+const KTX2_HEADER_SIZE: usize = 80;
+const KTX2_WRITER: &'static [u8] = b"KTXwriter\0sorcent\0";
+
+// Data format descriptor values
+const KHR_DF_VERSION: u32 = 2;
+const KHR_DF_MODEL_RGBSDA: u8 = 1;
+const KHR_DF_MODEL_BC1A: u8 = 128;
+const KHR_DF_MODEL_BC2: u8 = 129;
+const KHR_DF_MODEL_BC3: u8 = 130;
+const KHR_DF_MODEL_BC4: u8 = 131;
+const KHR_DF_MODEL_BC5: u8 = 132;
+const KHR_DF_PRIMARIES_BT709: u8 = 1;
+const KHR_DF_TRANSFER_LINEAR: u8 = 1;
+const KHR_DF_TRANSFER_SRGB: u8 = 2;
+const KHR_DF_SAMPLE_LINEAR: u8 = 0x10;
+const KHR_DF_SAMPLE_SIGNED: u8 = 0x40;
+const KHR_DF_SAMPLE_FLOAT: u8 = 0x80;
+
+// Channel IDs. The BC models reuse the low numbers for their own channels.
+const CHANNEL_BC_COLOR: u8 = 0;
+const CHANNEL_R: u8 = 0;
+const CHANNEL_G: u8 = 1;
+const CHANNEL_B: u8 = 2;
+const CHANNEL_A: u8 = 15;
+const CHANNEL_BC1A_ALPHAPRESENT: u8 = 1;
+
+/// How the values in each channel are stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SampleType {
+    Unorm,
+    Snorm,
+    Float
+}
+
+#[derive(Debug, Clone, Copy)]
+struct VkFormatInfo {
+    format: ImageFormat,
+    vk_format: u32,
+    /// The sRGB version of the format, or 0 if there isn't one
+    vk_format_srgb: u32,
+    /// The size of the values the data is made up of, for endian conversion
+    type_size: u32,
+    /// The data format descriptor's color model
+    model: u8,
+    sample_type: SampleType,
+    /// Each channel's ID, bit offset and bit length
+    channels: &'static [(u8, u16, u8)]
+}
+
+const FORMATS: [VkFormatInfo; 22] = [
+    VkFormatInfo {format: ImageFormat::DXT1, vk_format: 131, vk_format_srgb: 132, type_size: 1, model: KHR_DF_MODEL_BC1A, sample_type: SampleType::Unorm, channels: &[(CHANNEL_BC_COLOR, 0, 64)]},
+    VkFormatInfo {format: ImageFormat::DXT1_ONEBITALPHA, vk_format: 133, vk_format_srgb: 134, type_size: 1, model: KHR_DF_MODEL_BC1A, sample_type: SampleType::Unorm, channels: &[(CHANNEL_BC1A_ALPHAPRESENT, 0, 64)]},
+    VkFormatInfo {format: ImageFormat::DXT3, vk_format: 135, vk_format_srgb: 136, type_size: 1, model: KHR_DF_MODEL_BC2, sample_type: SampleType::Unorm, channels: &[(CHANNEL_A, 0, 64), (CHANNEL_BC_COLOR, 64, 64)]},
+    VkFormatInfo {format: ImageFormat::DXT5, vk_format: 137, vk_format_srgb: 138, type_size: 1, model: KHR_DF_MODEL_BC3, sample_type: SampleType::Unorm, channels: &[(CHANNEL_A, 0, 64), (CHANNEL_BC_COLOR, 64, 64)]},
+    VkFormatInfo {format: ImageFormat::ATI1N, vk_format: 139, vk_format_srgb: 0, type_size: 1, model: KHR_DF_MODEL_BC4, sample_type: SampleType::Unorm, channels: &[(CHANNEL_R, 0, 64)]},
+    VkFormatInfo {format: ImageFormat::ATI2N, vk_format: 141, vk_format_srgb: 0, type_size: 1, model: KHR_DF_MODEL_BC5, sample_type: SampleType::Unorm, channels: &[(CHANNEL_R, 0, 64), (CHANNEL_G, 64, 64)]},
+    VkFormatInfo {format: ImageFormat::RGBA8888, vk_format: 37, vk_format_srgb: 43, type_size: 1, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Unorm, channels: &[(CHANNEL_R, 0, 8), (CHANNEL_G, 8, 8), (CHANNEL_B, 16, 8), (CHANNEL_A, 24, 8)]},
+    VkFormatInfo {format: ImageFormat::BGRA8888, vk_format: 44, vk_format_srgb: 50, type_size: 1, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Unorm, channels: &[(CHANNEL_B, 0, 8), (CHANNEL_G, 8, 8), (CHANNEL_R, 16, 8), (CHANNEL_A, 24, 8)]},
+    VkFormatInfo {format: ImageFormat::RGB888, vk_format: 23, vk_format_srgb: 29, type_size: 1, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Unorm, channels: &[(CHANNEL_R, 0, 8), (CHANNEL_G, 8, 8), (CHANNEL_B, 16, 8)]},
+    VkFormatInfo {format: ImageFormat::BGR888, vk_format: 30, vk_format_srgb: 36, type_size: 1, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Unorm, channels: &[(CHANNEL_B, 0, 8), (CHANNEL_G, 8, 8), (CHANNEL_R, 16, 8)]},
+    VkFormatInfo {format: ImageFormat::RGB565, vk_format: 5, vk_format_srgb: 0, type_size: 2, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Unorm, channels: &[(CHANNEL_R, 0, 5), (CHANNEL_G, 5, 6), (CHANNEL_B, 11, 5)]},
+    VkFormatInfo {format: ImageFormat::BGR565, vk_format: 4, vk_format_srgb: 0, type_size: 2, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Unorm, channels: &[(CHANNEL_B, 0, 5), (CHANNEL_G, 5, 6), (CHANNEL_R, 11, 5)]},
+    VkFormatInfo {format: ImageFormat::BGRA5551, vk_format: 8, vk_format_srgb: 0, type_size: 2, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Unorm, channels: &[(CHANNEL_B, 0, 5), (CHANNEL_G, 5, 5), (CHANNEL_R, 10, 5), (CHANNEL_A, 15, 1)]},
+    VkFormatInfo {format: ImageFormat::BGRA4444, vk_format: 1000340000, vk_format_srgb: 0, type_size: 2, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Unorm, channels: &[(CHANNEL_B, 0, 4), (CHANNEL_G, 4, 4), (CHANNEL_R, 8, 4), (CHANNEL_A, 12, 4)]},
+    VkFormatInfo {format: ImageFormat::I8, vk_format: 9, vk_format_srgb: 15, type_size: 1, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Unorm, channels: &[(CHANNEL_R, 0, 8)]},
+    VkFormatInfo {format: ImageFormat::UV88, vk_format: 17, vk_format_srgb: 0, type_size: 1, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Snorm, channels: &[(CHANNEL_R, 0, 8), (CHANNEL_G, 8, 8)]},
+    VkFormatInfo {format: ImageFormat::UVWQ8888, vk_format: 38, vk_format_srgb: 0, type_size: 1, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Snorm, channels: &[(CHANNEL_R, 0, 8), (CHANNEL_G, 8, 8), (CHANNEL_B, 16, 8), (CHANNEL_A, 24, 8)]},
+    VkFormatInfo {format: ImageFormat::RGBA16161616F, vk_format: 97, vk_format_srgb: 0, type_size: 2, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Float, channels: &[(CHANNEL_R, 0, 16), (CHANNEL_G, 16, 16), (CHANNEL_B, 32, 16), (CHANNEL_A, 48, 16)]},
+    VkFormatInfo {format: ImageFormat::RGBA16161616, vk_format: 91, vk_format_srgb: 0, type_size: 2, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Unorm, channels: &[(CHANNEL_R, 0, 16), (CHANNEL_G, 16, 16), (CHANNEL_B, 32, 16), (CHANNEL_A, 48, 16)]},
+    VkFormatInfo {format: ImageFormat::R32F, vk_format: 100, vk_format_srgb: 0, type_size: 4, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Float, channels: &[(CHANNEL_R, 0, 32)]},
+    VkFormatInfo {format: ImageFormat::RGB323232F, vk_format: 106, vk_format_srgb: 0, type_size: 4, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Float, channels: &[(CHANNEL_R, 0, 32), (CHANNEL_G, 32, 32), (CHANNEL_B, 64, 32)]},
+    VkFormatInfo {format: ImageFormat::RGBA32323232F, vk_format: 109, vk_format_srgb: 0, type_size: 4, model: KHR_DF_MODEL_RGBSDA, sample_type: SampleType::Float, channels: &[(CHANNEL_R, 0, 32), (CHANNEL_G, 32, 32), (CHANNEL_B, 64, 32), (CHANNEL_A, 96, 32)]}
+];
+
+impl VTFFile {
+    /// Writes the file as a KTX2 file. See `to_ktx2_bytes`.
+    pub fn to_ktx2_writer<W>(&self, dest: &mut W, srgb: Option<bool>) -> Result<(), VTFLoadError> where W: Write {
+        let bytes = try!(self.to_ktx2_bytes(srgb));
+        dest.write_all(&bytes).map_err(VTFLoadError::Io)
+    }
+
+    /// Converts the file to a KTX2 file. Like `to_dds_bytes`, frames become array
+    /// layers and everything but the image data is dropped.
+    ///
+    /// The color data is marked as sRGB if `srgb` is `Some(true)`, or if it's `None`
+    /// and the file has the `PRE_SRGB` flag. Formats without an sRGB `VkFormat` are
+    /// always written as linear.
+    ///
+    /// Formats without a `VkFormat` of the same layout return `UnsupportedFormat`.
+    pub fn to_ktx2_bytes(&self, srgb: Option<bool>) -> Result<Vec<u8>, VTFLoadError> {
+        let header = &self.header;
+        let header70 = header.get_h70();
+        let format = header70.image_format;
+        let info = match FORMATS.iter().find(|i| i.format == format) {
+            Some(info) => *info,
+            None => return Err(VTFLoadError::VTF(VTFError::UnsupportedFormat(format)))
+        };
+
+        let srgb = srgb.unwrap_or(header.flags().check(VTFFlag::PRE_SRGB)) && info.vk_format_srgb != 0;
+        let vk_format = if srgb {info.vk_format_srgb} else {info.vk_format};
+
+        let face_count = if header.flags().check(VTFFlag::ENVMAP) {6} else {1};
+        let depth = header.depth();
+        let mip_count = VTFFile::mip_count(header) as usize;
+        let frame_count = VTFFile::frame_count_of(header);
+
+        // Each level holds every frame, face and depth slice of a single mip
+        let mut levels = Vec::with_capacity(mip_count);
+        for mip_level in 0..mip_count {
+            let mut level = Vec::new();
+            for frame in &self.frames {
+                for face_index in 0..face_count {
+                    let slices = match frame.faces.get(face_index).and_then(|f| f.mip_slices(mip_level)) {
+                        Some(slices) => slices,
+                        None => return Err(VTFLoadError::VTF(VTFError::ImageMismatch))
+                    };
+
+                    for slice in slices {
+                        try!(slice.save(&mut level).map_err(VTFLoadError::Io));
+                    }
+                }
+            }
+            levels.push(level);
+        }
+
+        let dfd = data_format_descriptor(&info, srgb);
+        let mut kvd = Vec::new();
+        write_u32(&mut kvd, KTX2_WRITER.len() as u32);
+        kvd.extend_from_slice(KTX2_WRITER);
+        pad_to(&mut kvd, 4);
+
+        let dfd_offset = KTX2_HEADER_SIZE + mip_count * 24;
+        let kvd_offset = dfd_offset + dfd.len();
+
+        // Levels are stored smallest first, each aligned to both the texel block size
+        // and 4 bytes
+        let block_size = format.block_size().unwrap_or(1) as usize;
+        let alignment = lcm(block_size, 4);
+        let mut level_offsets = vec![0; mip_count];
+        let mut end = kvd_offset + kvd.len();
+        for mip_level in (0..mip_count).rev() {
+            end += (alignment - end % alignment) % alignment;
+            level_offsets[mip_level] = end;
+            end += levels[mip_level].len();
+        }
+
+        let mut data = Vec::with_capacity(end);
+        data.extend_from_slice(&KTX2_IDENTIFIER);
+        let fields = [
+            vk_format,
+            info.type_size,
+            header70.width as u32,
+            header70.height as u32,
+            if depth > 1 {depth as u32} else {0},
+            if frame_count > 1 {frame_count as u32} else {0},
+            face_count as u32,
+            mip_count as u32,
+            0,
+            dfd_offset as u32,
+            dfd.len() as u32,
+            kvd_offset as u32,
+            kvd.len() as u32
+        ];
+        for &field in fields.iter() {
+            write_u32(&mut data, field);
+        }
+        // No supercompression global data
+        write_u64(&mut data, 0);
+        write_u64(&mut data, 0);
+
+        for (level, &offset) in levels.iter().zip(&level_offsets) {
+            write_u64(&mut data, offset as u64);
+            write_u64(&mut data, level.len() as u64);
+            write_u64(&mut data, level.len() as u64);
+        }
+
+        data.extend_from_slice(&dfd);
+        data.extend_from_slice(&kvd);
+        for mip_level in (0..mip_count).rev() {
+            data.resize(level_offsets[mip_level], 0);
+            data.extend_from_slice(&levels[mip_level]);
+        }
+
+        Ok(data)
+    }
+}
+
+/// Builds the data format descriptor, including its total size, with a single basic
+/// descriptor block.
+fn data_format_descriptor(info: &VkFormatInfo, srgb: bool) -> Vec<u8> {
+    let block_size = 24 + 16 * info.channels.len() as u32;
+    let is_compressed = info.format.is_compressed();
+
+    let mut dfd = Vec::with_capacity(4 + block_size as usize);
+    write_u32(&mut dfd, 4 + block_size);
+    // Khronos vendor, basic descriptor type
+    write_u32(&mut dfd, 0);
+    write_u32(&mut dfd, KHR_DF_VERSION | block_size << 16);
+    dfd.push(info.model);
+    dfd.push(KHR_DF_PRIMARIES_BT709);
+    dfd.push(if srgb {KHR_DF_TRANSFER_SRGB} else {KHR_DF_TRANSFER_LINEAR});
+    // Straight alpha
+    dfd.push(0);
+
+    // Texel block dimensions, each stored minus one
+    match is_compressed {
+        true => dfd.extend_from_slice(&[3, 3, 0, 0]),
+        false => dfd.extend_from_slice(&[0, 0, 0, 0])
+    }
+    let mut bytes_planes = [0; 8];
+    bytes_planes[0] = info.format.block_size().unwrap_or(0) as u8;
+    dfd.extend_from_slice(&bytes_planes);
+
+    for &(channel, offset, length) in info.channels {
+        let mut qualifiers = match info.sample_type {
+            SampleType::Unorm => 0,
+            SampleType::Snorm => KHR_DF_SAMPLE_SIGNED,
+            SampleType::Float => KHR_DF_SAMPLE_SIGNED | KHR_DF_SAMPLE_FLOAT
+        };
+        // Alpha is never stored with the sRGB transfer function
+        if srgb && channel == CHANNEL_A {
+            qualifiers |= KHR_DF_SAMPLE_LINEAR;
+        }
+
+        let (lower, upper) = match (info.sample_type, is_compressed) {
+            (_, true) => (0, u32::max_value()),
+            (SampleType::Unorm, false) => (0, if length >= 32 {u32::max_value()} else {(1 << length) - 1}),
+            (SampleType::Snorm, false) => {
+                let max = (1i32 << (length - 1)) - 1;
+                (-max as u32, max as u32)
+            },
+            // -1.0 and 1.0
+            (SampleType::Float, false) => (0xBF800000, 0x3F800000)
+        };
+
+        dfd.push(offset as u8);
+        dfd.push((offset >> 8) as u8);
+        dfd.push(length - 1);
+        dfd.push(channel | qualifiers);
+        dfd.extend_from_slice(&[0, 0, 0, 0]);
+        write_u32(&mut dfd, lower);
+        write_u32(&mut dfd, upper);
+    }
+
+    dfd
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let t = x % y;
+        x = y;
+        y = t;
+    }
+    a / x * b
+}
+
+fn pad_to(data: &mut Vec<u8>, alignment: usize) {
+    let padded = (data.len() + alignment - 1) / alignment * alignment;
+    data.resize(padded, 0);
+}
+
+#[inline]
+fn write_u64(data: &mut Vec<u8>, value: u64) {
+    write_u32(data, value as u32);
+    write_u32(data, (value >> 32) as u32);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KTX2_IDENTIFIER, KTX2_HEADER_SIZE};
+    use super::super::{VTFFile, ImageFormat, ImportOptions};
+    use super::super::image::Rgba8;
+    use super::super::resource::read_u32;
+
+    fn u32_at(data: &[u8], pos: usize) -> u32 {
+        read_u32(data, &mut {pos}).unwrap()
+    }
+
+    /// An 8x4 file with a full mip chain
+    fn vtf(format: ImageFormat) -> VTFFile {
+        let pixels = vec![Rgba8 {red: 200, green: 100, blue: 50, alpha: 255}; 32];
+        let options = ImportOptions {format: format, ..ImportOptions::default()};
+        VTFFile::from_rgba8(&pixels, 8, 4, &options).unwrap()
+    }
+
+    /// Checks the header and level index, and returns the data format descriptor
+    fn check_layout<'a>(data: &'a [u8], vk_format: u32, type_size: u32, level_sizes: &[usize], alignment: usize) -> &'a [u8] {
+        assert_eq!(&data[..12], &KTX2_IDENTIFIER);
+        let fields: Vec<u32> = (0..9).map(|i| u32_at(data, 12 + i * 4)).collect();
+        assert_eq!(fields, vec![vk_format, type_size, 8, 4, 0, 0, 1, level_sizes.len() as u32, 0]);
+
+        let dfd_offset = u32_at(data, 48) as usize;
+        let dfd_len = u32_at(data, 52) as usize;
+        let kvd_offset = u32_at(data, 56) as usize;
+        let kvd_len = u32_at(data, 60) as usize;
+        assert_eq!(dfd_offset, KTX2_HEADER_SIZE + level_sizes.len() * 24);
+        assert_eq!(kvd_offset, dfd_offset + dfd_len);
+        assert_eq!(&data[kvd_offset + 4..kvd_offset + 13], b"KTXwriter");
+
+        // Levels are stored smallest first, right after the key/value data
+        let mut end = kvd_offset + kvd_len;
+        for (level, &size) in level_sizes.iter().enumerate().rev() {
+            let entry = KTX2_HEADER_SIZE + level * 24;
+            let offset = u32_at(data, entry) as usize;
+            assert_eq!(offset % alignment, 0);
+            assert!(offset >= end && offset < end + alignment);
+            assert_eq!(u32_at(data, entry + 8) as usize, size);
+            assert_eq!(u32_at(data, entry + 16) as usize, size);
+            end = offset + size;
+        }
+        assert_eq!(end, data.len());
+
+        &data[dfd_offset..dfd_offset + dfd_len]
+    }
+
+    #[test]
+    fn bc_format() {
+        let data = vtf(ImageFormat::DXT1).to_ktx2_bytes(None).unwrap();
+        let dfd = check_layout(&data, 131, 1, &[16, 8, 8, 8], 8);
+
+        assert_eq!(dfd.len(), 44);
+        assert_eq!(u32_at(dfd, 0), 44);
+        assert_eq!(u32_at(dfd, 4), 0);
+        assert_eq!(u32_at(dfd, 8), 2 | 40 << 16);
+        // BC1A model, BT.709 primaries, linear transfer, 4x4 blocks of 8 bytes
+        assert_eq!(&dfd[12..24], &[128, 1, 1, 0, 3, 3, 0, 0, 8, 0, 0, 0]);
+        // A single 64-bit color sample covering the full range
+        assert_eq!(&dfd[28..36], &[0, 0, 63, 0, 0, 0, 0, 0]);
+        assert_eq!((u32_at(dfd, 36), u32_at(dfd, 40)), (0, u32::max_value()));
+    }
+
+    #[test]
+    fn packed_format() {
+        // RGB565 has no sRGB format, so it stays linear
+        let data = vtf(ImageFormat::RGB565).to_ktx2_bytes(Some(true)).unwrap();
+        let dfd = check_layout(&data, 5, 2, &[64, 16, 4, 2], 4);
+
+        assert_eq!(dfd.len(), 76);
+        assert_eq!(u32_at(dfd, 0), 76);
+        assert_eq!(u32_at(dfd, 8), 2 | 72 << 16);
+        // RGBSDA model, linear transfer, single texel blocks of 2 bytes
+        assert_eq!(&dfd[12..24], &[1, 1, 1, 0, 0, 0, 0, 0, 2, 0, 0, 0]);
+
+        let samples = [(0, 5, 0), (5, 6, 1), (11, 5, 2)];
+        for (i, &(offset, length, channel)) in samples.iter().enumerate() {
+            let sample = &dfd[28 + i * 16..44 + i * 16];
+            assert_eq!(&sample[..8], &[offset, 0, length - 1, channel, 0, 0, 0, 0]);
+            assert_eq!((u32_at(sample, 8), u32_at(sample, 12)), (0, (1 << length) - 1));
+        }
+    }
+}
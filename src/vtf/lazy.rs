@@ -0,0 +1,158 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use super::{VTFFile, VTFProbe, VTFResources};
+use super::format::HeaderVersion;
+use super::image::VTFImageWrapper;
+use super::error::VTFLoadError;
+
+/// A VTF file that only reads and decodes images when they're requested. Created with
+/// `VTFFile::lazy`.
+///
+/// Images aren't cached, so requesting the same image twice reads it from the source
+/// twice. Resources are read the first time they're requested, and kept after that.
+#[derive(Debug)]
+pub struct LazyVTFFile<R> where R: Read + Seek {
+    source: R,
+    probe: VTFProbe,
+    resources: Option<VTFResources>
+}
+
+impl<R> LazyVTFFile<R> where R: Read + Seek {
+    pub(super) fn new(source: R, probe: VTFProbe) -> LazyVTFFile<R> {
+        LazyVTFFile {
+            source: source,
+            probe: probe,
+            resources: None
+        }
+    }
+
+    pub fn header(&self) -> &HeaderVersion {
+        &self.probe.header
+    }
+
+    /// Gets the header and resource table, along with the dimensions and counts
+    /// derived from them.
+    pub fn probe(&self) -> &VTFProbe {
+        &self.probe
+    }
+
+    /// Gets the typed contents of the file's resources, reading them from the source
+    /// on the first call.
+    pub fn resources(&mut self) -> Result<&VTFResources, VTFLoadError> {
+        if self.resources.is_none() {
+            self.resources = Some(try!(self.probe.load_resources(&mut self.source)));
+        }
+        Ok(self.resources.as_ref().unwrap())
+    }
+
+    /// Checks the CRC resource against the CRC32 of the high-res image data, reading
+    /// the data from the source. Returns `None` if the file doesn't have a CRC resource.
+    pub fn verify_crc(&mut self) -> Result<Option<bool>, VTFLoadError> {
+        match self.probe.crc() {
+            Some(stored) => Ok(Some(stored == try!(self.probe.image_crc(&mut self.source)))),
+            None => Ok(None)
+        }
+    }
+
+    pub fn load_thumb(&mut self) -> Result<Option<VTFImageWrapper>, VTFLoadError> {
+        self.probe.load_thumb(&mut self.source)
+    }
+
+    /// Reads and decodes a single image. Mip level 0 is the full-size image.
+    pub fn load_image(&mut self, frame: u16, face: usize, slice: u16, mip_level: u8) -> Result<VTFImageWrapper, VTFLoadError> {
+        self.probe.load_image(&mut self.source, frame, face, slice, mip_level)
+    }
+
+    /// Reads and decodes the full-size image of the first face and slice of the
+    /// starting frame. Like `VTFFile::image`, this falls back to the first frame if the
+    /// header's start frame is out of range.
+    pub fn load_main_image(&mut self) -> Result<VTFImageWrapper, VTFLoadError> {
+        let start_frame = match self.probe.header.get_h70().start_frame {
+            frame if frame < self.probe.frame_count() => frame,
+            _ => 0
+        };
+        self.load_image(start_frame, 0, 0, 0)
+    }
+
+    /// Reads every image and returns the fully loaded file.
+    pub fn load_all(mut self) -> Result<VTFFile, VTFLoadError> {
+        let start = self.probe.start;
+        try!(self.source.seek(SeekFrom::Start(start)).map_err(VTFLoadError::Io));
+        VTFFile::from_reader(&mut self.source)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::super::{VTFFile, VTFFrame, VTFFace, VTFFlags, ImageFormat};
+    use super::super::image::{VTFImageWrapper, Rgba8, DxtQuality};
+    use super::super::import::new_header;
+    use super::super::resource::write_u32;
+
+    /// A 4x4 RGBA8888 7.3 file whose KVD resource points past the end of the file
+    fn file_with_bad_key_values() -> Vec<u8> {
+        let header_size = 88;
+        let mut data = b"VTF\0".to_vec();
+        for &field in &[7, 3, header_size] {
+            write_u32(&mut data, field);
+        }
+        data.extend_from_slice(&[4, 0, 4, 0]);
+        write_u32(&mut data, 0);
+        data.extend_from_slice(&[1, 0, 0, 0]);
+        data.extend_from_slice(&[0; 20]);
+        write_u32(&mut data, 0x3f800000);
+        write_u32(&mut data, ImageFormat::RGBA8888 as u32);
+        data.push(1);
+        write_u32(&mut data, ImageFormat::NONE as u32);
+        data.extend_from_slice(&[0, 0, 1, 0, 0, 0, 0]);
+        write_u32(&mut data, 2);
+
+        write_u32(&mut data, 0x30);
+        write_u32(&mut data, header_size);
+        write_u32(&mut data, 0x44564b);
+        write_u32(&mut data, 1000);
+        assert_eq!(data.len(), header_size as usize);
+
+        data.extend_from_slice(&[128; 64]);
+        data
+    }
+
+    #[test]
+    fn probe_doesnt_read_resource_chunks() {
+        let data = file_with_bad_key_values();
+        let probe = VTFFile::probe(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(probe.resources.as_ref().map(|r| r.len()), Some(2));
+
+        let mut lazy = VTFFile::lazy(Cursor::new(&data)).unwrap();
+        assert_eq!(lazy.load_main_image().unwrap().raw_data().len(), 64);
+        assert!(lazy.resources().is_err());
+        assert!(VTFFile::from_bytes(&data).is_err());
+    }
+
+    #[test]
+    fn main_image_is_from_the_start_frame() {
+        let frames = (0..2u8).map(|f| {
+            let pixels = vec![Rgba8 {red: f * 200, green: 0, blue: 0, alpha: 255}; 16];
+            let image = VTFImageWrapper::encode(&pixels, 4, 4, ImageFormat::RGBA8888, DxtQuality::default()).unwrap();
+            VTFFrame {faces: vec![VTFFace {mips: Vec::new(), slices: vec![image]}]}
+        }).collect();
+        let mut vtf = VTFFile::from_parts(new_header(2, 4, 4, 1, 2, VTFFlags::empty(), ImageFormat::RGBA8888, 1), frames);
+
+        // An out-of-range start frame falls back to the first frame
+        for &(start_frame, red) in &[(1, 200), (5, 0)] {
+            vtf.header.get_h70_mut().start_frame = start_frame;
+            let bytes = vtf.to_bytes().unwrap();
+
+            let eager = VTFFile::from_bytes(&bytes).unwrap();
+            let lazy = VTFFile::lazy(Cursor::new(&bytes)).unwrap().load_main_image().unwrap();
+            assert_eq!(lazy.raw_data(), eager.image().raw_data());
+            assert_eq!(lazy.expose().to_rgba8()[0].red, red);
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Mip chain generation. Each level is filtered down from the one above it, in linear
+//! space unless told otherwise, and keeps its intermediate values as floats so rounding
+//! errors don't build up across levels.
+
+use std::cmp;
+use std::f32::consts::PI;
+
+use super::VTFFile;
+use super::format::{VTFFlags, VTFFlag};
+use super::image::Rgba8;
+use super::error::VTFError;
+
+/// The filter used to shrink each mip level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MipFilter {
+    /// Averages each 2x2 block of pixels
+    Box,
+    /// A tent filter, one pixel wide on either side
+    Triangle,
+    /// A Kaiser-windowed sinc, three pixels wide on either side. This is the filter
+    /// vtex and nvtt use by default.
+    Kaiser,
+    /// A three-lobed Lanczos filter. Sharper than Kaiser, with more ringing.
+    Lanczos
+}
+
+impl MipFilter {
+    /// How far the filter reaches on either side, in destination pixels
+    fn radius(&self) -> f32 {
+        match self {
+            &MipFilter::Box => 0.5,
+            &MipFilter::Triangle => 1.0,
+            &MipFilter::Kaiser |
+            &MipFilter::Lanczos => 3.0
+        }
+    }
+
+    fn weight(&self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            &MipFilter::Box => if x <= 0.5 {1.0} else {0.0},
+            &MipFilter::Triangle => (1.0 - x).max(0.0),
+            &MipFilter::Kaiser => {
+                const ALPHA: f32 = 4.0;
+                let t = x / 3.0;
+                if t >= 1.0 {
+                    0.0
+                } else {
+                    sinc(x) * bessel_i0(ALPHA * (1.0 - t * t).sqrt()) / bessel_i0(ALPHA)
+                }
+            },
+            &MipFilter::Lanczos => if x >= 3.0 {0.0} else {sinc(x) * sinc(x / 3.0)}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MipOptions {
+    pub filter: MipFilter,
+    /// Converts the color channels from sRGB to linear before filtering, and back
+    /// afterwards. Alpha is always filtered as-is.
+    pub linear: bool,
+    /// Treats the color channels as a tangent-space normal, renormalizing every pixel of
+    /// each mip after it's filtered. Normals are never filtered in linear space.
+    pub normal_map: bool
+}
+
+impl Default for MipOptions {
+    fn default() -> MipOptions {
+        MipOptions {
+            filter: MipFilter::Kaiser,
+            linear: true,
+            normal_map: false
+        }
+    }
+}
+
+impl MipOptions {
+    /// Gets the default options for a texture with the given flags. Textures flagged
+    /// `NORMAL` are renormalized at each level.
+    pub fn for_flags(flags: VTFFlags) -> MipOptions {
+        MipOptions {
+            normal_map: flags.check(VTFFlag::NORMAL),
+            ..MipOptions::default()
+        }
+    }
+}
+
+/// A single generated mip level.
+#[derive(Debug, Clone)]
+pub struct MipLevel {
+    pub width: u16,
+    pub height: u16,
+    pub pixels: Vec<Rgba8>
+}
+
+/// The number of mip levels needed to go from the given dimensions down to 1x1.
+pub fn full_mip_count(width: u16, height: u16) -> u8 {
+    let largest = cmp::max(cmp::max(width, height), 1);
+    (16 - largest.leading_zeros()) as u8
+}
+
+/// Generates every mip level from the base image down to 1x1. The base image is given
+/// as `width * height` pixels, and is returned unchanged as level 0.
+pub fn generate_mips(pixels: &[Rgba8], width: u16, height: u16, options: &MipOptions) -> Result<Vec<MipLevel>, VTFError> {
+    generate_mip_count(pixels, width, height, full_mip_count(width, height), options)
+}
+
+/// Generates the first `mip_count` mip levels, with level 0 being the base image.
+///
+/// Returns `ImageSize` if either dimension is zero, and `ImageMismatch` if `pixels`
+/// doesn't hold `width * height` pixels.
+pub fn generate_mip_count(pixels: &[Rgba8], width: u16, height: u16, mip_count: u8, options: &MipOptions) -> Result<Vec<MipLevel>, VTFError> {
+    if width == 0 || height == 0 {
+        return Err(VTFError::ImageSize);
+    }
+    if pixels.len() != width as usize * height as usize {
+        return Err(VTFError::ImageMismatch);
+    }
+
+    let linear = options.linear && !options.normal_map;
+    let to_float = |c: u8| match linear {
+        true => srgb_to_linear(c),
+        false => c as f32 / 255.0
+    };
+
+    let mut levels = vec![MipLevel {width: width, height: height, pixels: pixels.to_vec()}];
+    let mut current: Vec<[f32; 4]> = pixels.iter()
+        .map(|p| [to_float(p.red), to_float(p.green), to_float(p.blue), p.alpha as f32 / 255.0])
+        .collect();
+    let (mut current_width, mut current_height) = (width, height);
+
+    for mip_level in 1..mip_count {
+        let (mip_width, mip_height) = VTFFile::compute_mip_dimensions(width, height, mip_level);
+
+        let rows = resample_rows(&current, current_width as usize, current_height as usize, mip_width as usize, options.filter);
+        current = resample_columns(&rows, mip_width as usize, current_height as usize, mip_height as usize, options.filter);
+        current_width = mip_width;
+        current_height = mip_height;
+
+        if options.normal_map {
+            for p in &mut current {
+                renormalize(p);
+            }
+        }
+
+        let from_float = |c: f32| match linear {
+            true => linear_to_srgb(c),
+            false => unit_to_u8(c)
+        };
+        levels.push(MipLevel {
+            width: mip_width,
+            height: mip_height,
+            pixels: current.iter()
+                .map(|p| Rgba8 {red: from_float(p[0]), green: from_float(p[1]), blue: from_float(p[2]), alpha: unit_to_u8(p[3])})
+                .collect()
+        });
+    }
+
+    Ok(levels)
+}
+
+/// The weights used to produce each destination pixel along one axis, as the first
+/// source pixel and the weight of each source pixel from there.
+fn filter_weights(src: usize, dst: usize, filter: MipFilter) -> Vec<(usize, Vec<f32>)> {
+    let scale = src as f32 / dst as f32;
+    // Only shrink the filter's footprint when downsampling
+    let support = filter.radius() * scale.max(1.0);
+
+    (0..dst).map(|i| {
+        let center = (i as f32 + 0.5) * scale;
+        let first = cmp::max((center - support).floor() as isize, 0) as usize;
+        let last = cmp::min((center + support).ceil() as usize, src);
+
+        let mut weights: Vec<f32> = (first..last)
+            .map(|j| filter.weight((j as f32 + 0.5 - center) / scale.max(1.0)))
+            .collect();
+
+        let total: f32 = weights.iter().sum();
+        if total.abs() > 1e-6 {
+            for w in &mut weights {
+                *w /= total;
+            }
+        } else {
+            // Nothing fell under the filter, so fall back to the nearest pixel
+            let nearest = cmp::min(center as usize, src - 1);
+            return (nearest, vec![1.0]);
+        }
+
+        (first, weights)
+    }).collect()
+}
+
+fn resample_rows(src: &[[f32; 4]], width: usize, height: usize, dst_width: usize, filter: MipFilter) -> Vec<[f32; 4]> {
+    if width == dst_width {
+        return src.to_vec();
+    }
+
+    let weights = filter_weights(width, dst_width, filter);
+    let mut dst = vec![[0.0; 4]; dst_width * height];
+    for y in 0..height {
+        let row = &src[y * width..(y + 1) * width];
+        for (x, &(first, ref w)) in weights.iter().enumerate() {
+            dst[y * dst_width + x] = weighted_sum(w.iter().enumerate().map(|(i, &w)| (w, &row[first + i])));
+        }
+    }
+
+    dst
+}
+
+fn resample_columns(src: &[[f32; 4]], width: usize, height: usize, dst_height: usize, filter: MipFilter) -> Vec<[f32; 4]> {
+    if height == dst_height {
+        return src.to_vec();
+    }
+
+    let weights = filter_weights(height, dst_height, filter);
+    let mut dst = vec![[0.0; 4]; width * dst_height];
+    for (y, &(first, ref w)) in weights.iter().enumerate() {
+        for x in 0..width {
+            dst[y * width + x] = weighted_sum(w.iter().enumerate().map(|(i, &w)| (w, &src[(first + i) * width + x])));
+        }
+    }
+
+    dst
+}
+
+#[inline]
+fn weighted_sum<'a, I>(pixels: I) -> [f32; 4] where I: Iterator<Item=(f32, &'a [f32; 4])> {
+    let mut sum = [0.0; 4];
+    for (w, p) in pixels {
+        for c in 0..4 {
+            sum[c] += w * p[c];
+        }
+    }
+    sum
+}
+
+/// Renormalizes a normal stored in the color channels, mapped from 0-1 to -1 to 1.
+fn renormalize(p: &mut [f32; 4]) {
+    let n = [p[0] * 2.0 - 1.0, p[1] * 2.0 - 1.0, p[2] * 2.0 - 1.0];
+    let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+
+    if len > 1e-6 {
+        for c in 0..3 {
+            p[c] = (n[c] / len + 1.0) * 0.5;
+        }
+    } else {
+        // Fully cancelled-out normals point straight out of the surface
+        p[0] = 0.5;
+        p[1] = 0.5;
+        p[2] = 1.0;
+    }
+}
+
+pub fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.max(0.0).min(1.0);
+    let s = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    unit_to_u8(s)
+}
+
+#[inline]
+fn unit_to_u8(c: f32) -> u8 {
+    (c.max(0.0).min(1.0) * 255.0 + 0.5) as u8
+}
+
+#[inline]
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// The zeroth-order modified Bessel function of the first kind, used by the Kaiser window
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x * x / 4.0;
+
+    let mut k = 1.0;
+    while term > sum * 1e-8 {
+        term *= half / (k * k);
+        sum += term;
+        k += 1.0;
+    }
+
+    sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_mips, MipOptions, MipFilter};
+    use super::super::VTFFile;
+    use super::super::image::Rgba8;
+    use super::super::error::VTFError;
+
+    fn gray(value: u8) -> Rgba8 {
+        Rgba8 {red: value, green: value, blue: value, alpha: 255}
+    }
+
+    fn box_options(linear: bool) -> MipOptions {
+        MipOptions {filter: MipFilter::Box, linear: linear, normal_map: false}
+    }
+
+    #[test]
+    fn level_sizes_match_mip_dimensions() {
+        let levels = generate_mips(&vec![gray(0); 16], 8, 2, &MipOptions::default()).unwrap();
+
+        let sizes: Vec<(u16, u16)> = levels.iter().map(|l| (l.width, l.height)).collect();
+        assert_eq!(sizes, vec![(8, 2), (4, 1), (2, 1), (1, 1)]);
+        for (mip_level, level) in levels.iter().enumerate() {
+            assert_eq!((level.width, level.height), VTFFile::compute_mip_dimensions(8, 2, mip_level as u8));
+            assert_eq!(level.pixels.len(), level.width as usize * level.height as usize);
+        }
+    }
+
+    #[test]
+    fn box_filter_averages_a_block() {
+        let pixels = [
+            Rgba8 {red: 0, green: 10, blue: 20, alpha: 0},
+            Rgba8 {red: 100, green: 10, blue: 40, alpha: 100},
+            Rgba8 {red: 200, green: 30, blue: 60, alpha: 200},
+            Rgba8 {red: 60, green: 30, blue: 80, alpha: 100}
+        ];
+        let levels = generate_mips(&pixels, 2, 2, &box_options(false)).unwrap();
+
+        let p = &levels[1].pixels[0];
+        assert_eq!((p.red, p.green, p.blue, p.alpha), (90, 20, 50, 100));
+    }
+
+    #[test]
+    fn linear_and_gamma_averages() {
+        let pixels = [gray(0), gray(255), gray(255), gray(0)];
+
+        let linear = generate_mips(&pixels, 2, 2, &box_options(true)).unwrap();
+        assert_eq!(linear[1].pixels[0].red, 188);
+
+        let gamma = generate_mips(&pixels, 2, 2, &box_options(false)).unwrap();
+        assert_eq!(gamma[1].pixels[0].red, 128);
+    }
+
+    #[test]
+    fn normal_maps_are_renormalized() {
+        // Normals pointing along +X and +Z average out to a vector shorter than one
+        let x = Rgba8 {red: 255, green: 128, blue: 128, alpha: 255};
+        let z = Rgba8 {red: 128, green: 128, blue: 255, alpha: 255};
+        let options = MipOptions {normal_map: true, ..box_options(true)};
+        let levels = generate_mips(&[x.clone(), z.clone(), z, x], 2, 2, &options).unwrap();
+
+        let p = &levels[1].pixels[0];
+        let n: Vec<f32> = [p.red, p.green, p.blue].iter().map(|&c| c as f32 / 255.0 * 2.0 - 1.0).collect();
+        let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+        assert!((len - 1.0).abs() < 0.02, "length {}", len);
+        assert!((n[0] - n[2]).abs() < 0.02);
+    }
+
+    #[test]
+    fn bad_input_is_rejected() {
+        let options = MipOptions::default();
+        assert_eq!(generate_mips(&[], 0, 4, &options).unwrap_err(), VTFError::ImageSize);
+        assert_eq!(generate_mips(&[], 4, 0, &options).unwrap_err(), VTFError::ImageSize);
+        assert_eq!(generate_mips(&vec![gray(0); 3], 2, 2, &options).unwrap_err(), VTFError::ImageMismatch);
+    }
+}
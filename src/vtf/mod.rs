@@ -3,37 +3,316 @@ mod format;
 pub mod error;
 #[allow(dead_code)]
 pub mod image;
+pub mod mipmap;
+mod lazy;
+mod resource;
+mod writer;
+mod thumb;
+mod import;
+mod export;
+mod dds;
+mod ktx2;
+mod dxt;
 
 use std::fs::File;
-use std::io::{Seek, SeekFrom};
+use std::io::{Read, Seek, SeekFrom, Cursor};
 use std::mem;
+use std::cmp;
+use std::ops::Index;
 
-pub use self::format::{VTFData, HeaderRoot, Header70, Header72, Header73, Resource, ResourceID, HeaderVersion, ImageFormat};
-use self::image::{VTFImageWrapper};
+pub use self::format::{VTFData, HeaderRoot, Header70, Header72, Header73, Resource, ResourceID, HeaderVersion, ImageFormat, CubeFace, VTFFlag, VTFFlags, VTFFlagIter, FlagConflict};
+use self::image::{VTFImageWrapper, ToneMap};
 
 use self::error::{VTFLoadError, VTFError};
+pub use self::lazy::LazyVTFFile;
+pub use self::import::ImportOptions;
+pub use self::resource::{VTFResources, LodSettings, TextureSettingsEx, KeyValueData, KeyValueBlock, Sheet, SheetSequence, SheetFrame, SheetRect, UnknownResource};
 
 #[derive(Debug)]
 pub struct VTFFile {
     pub header: HeaderVersion,
     pub resources: Option<Vec<Resource>>,
-    pub thumb: VTFImageWrapper,
-    pub mips: Vec<VTFImageWrapper>,
-    pub image: VTFImageWrapper
+    /// The low-res thumbnail, if the file has one.
+    pub thumb: Option<VTFImageWrapper>,
+    /// Every animation frame in the file, in the order they are stored.
+    pub frames: Vec<VTFFrame>,
+    resource_data: VTFResources,
+    /// The CRC32 of the high-res image data, computed on load if the file has a CRC resource
+    image_crc: Option<u32>
+}
+
+/// A single animation frame. Environment maps store a mip chain for each cubemap
+/// face, while all other textures store a single mip chain.
+#[derive(Debug, Clone)]
+pub struct VTFFrame {
+    pub faces: Vec<VTFFace>
+}
+
+impl VTFFrame {
+    /// Gets the full-size image of the frame's first face.
+    pub fn image(&self) -> &VTFImageWrapper {
+        self.faces[0].image()
+    }
+
+    /// Gets the given mip level of the frame's first face, with level 0 being the
+    /// full-size image. Returns `None` if the level does not exist.
+    pub fn mip(&self, mip_level: usize) -> Option<&VTFImageWrapper> {
+        self.faces[0].mip(mip_level)
+    }
+
+    /// Gets the given cubemap face. Returns `None` if the frame isn't part of an
+    /// environment map, or if the requested face is the spheremap and the file
+    /// doesn't contain one.
+    pub fn face(&self, face: CubeFace) -> Option<&VTFFace> {
+        if self.faces.len() < 6 {
+            None
+        } else {
+            self.faces.get(face as usize)
+        }
+    }
+
+    pub fn is_cubemap(&self) -> bool {
+        self.faces.len() >= 6
+    }
+}
+
+impl Index<CubeFace> for VTFFrame {
+    type Output = VTFFace;
+
+    fn index(&self, face: CubeFace) -> &VTFFace {
+        self.face(face).expect("Frame does not contain the requested cubemap face")
+    }
+}
+
+/// A single face of a frame, along with its mip chain. Volume textures store several
+/// depth slices at each mip level, with the depth halving at each level down to a
+/// minimum of one slice. All other textures store a single slice per level.
+#[derive(Debug, Clone)]
+pub struct VTFFace {
+    /// The face's mips, with mip level n stored at index n - 1. Each mip holds
+    /// every depth slice for that level.
+    pub mips: Vec<Vec<VTFImageWrapper>>,
+    /// The depth slices of the full-size image.
+    pub slices: Vec<VTFImageWrapper>
+}
+
+impl VTFFace {
+    /// Gets the first depth slice of the full-size image.
+    pub fn image(&self) -> &VTFImageWrapper {
+        &self.slices[0]
+    }
+
+    /// Gets the first depth slice of the given mip level, with level 0 being the
+    /// full-size image.
+    pub fn mip(&self, mip_level: usize) -> Option<&VTFImageWrapper> {
+        self.mip_slices(mip_level).map(|s| &s[0])
+    }
+
+    /// Gets every depth slice of the given mip level, with level 0 being the
+    /// full-size image.
+    pub fn mip_slices(&self, mip_level: usize) -> Option<&[VTFImageWrapper]> {
+        if mip_level == 0 {
+            Some(&self.slices)
+        } else {
+            self.mips.get(mip_level - 1).map(|s| &s[..])
+        }
+    }
+
+    /// Gets a single depth slice of the given mip level.
+    pub fn slice(&self, mip_level: usize, slice: usize) -> Option<&VTFImageWrapper> {
+        self.mip_slices(mip_level).and_then(|s| s.get(slice))
+    }
+
+    /// Decodes every depth slice of the given mip level into a single RGBA8 buffer.
+    /// Slices are laid out one after another, starting with slice 0.
+    pub fn volume_rgba8_raw(&self, mip_level: usize) -> Option<Vec<u8>> {
+        self.mip_slices(mip_level).map(|slices| {
+            let mut volume = Vec::new();
+            for s in slices {
+                volume.extend(s.expose().to_rgba8_raw());
+            }
+            volume
+        })
+    }
+}
+
+/// The header and resource table of a VTF file, along with where its image data is
+/// stored. Produced by `VTFFile::probe`.
+#[derive(Debug)]
+pub struct VTFProbe {
+    pub header: HeaderVersion,
+    pub resources: Option<Vec<Resource>>,
+    /// The position of the start of the VTF data in the source
+    start: u64,
+    /// The length of the source from the start of the VTF data
+    len: u64,
+    // Offsets are relative to the start of the VTF data.
+    thumb_offset: Option<u64>,
+    image_offset: u64
+}
+
+impl VTFProbe {
+    pub fn width(&self) -> u16 {
+        self.header.get_h70().width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.header.get_h70().height
+    }
+
+    pub fn depth(&self) -> u16 {
+        self.header.depth()
+    }
+
+    pub fn image_format(&self) -> ImageFormat {
+        self.header.get_h70().image_format
+    }
+
+    pub fn mip_count(&self) -> u8 {
+        VTFFile::mip_count(&self.header)
+    }
+
+    pub fn frame_count(&self) -> u16 {
+        VTFFile::frame_count_of(&self.header)
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.header.face_count()
+    }
+
+    pub fn has_thumb(&self) -> bool {
+        self.thumb_offset.is_some()
+    }
+
+    /// The CRC32 stored in the file's `CRC` resource. This is stored in the resource
+    /// table itself, so it's available without reading anything else.
+    pub fn crc(&self) -> Option<u32> {
+        self.resources.as_ref()
+            .and_then(|r| r.iter().find(|r| r.id == ResourceID::Crc))
+            .map(|r| r.data)
+    }
+
+    /// Reads and parses the file's resources from the source the probe was read from.
+    pub fn load_resources<R>(&self, file: &mut R) -> Result<VTFResources, VTFLoadError> where R: Read + Seek {
+        match self.resources {
+            Some(ref resources) => VTFResources::load(&mut *file, self.start, self.len, resources),
+            None => Ok(VTFResources::default())
+        }
+    }
+
+    /// Computes the CRC32 of the high-res image data, reading it from the source the
+    /// probe was read from.
+    pub fn image_crc<R>(&self, file: &mut R) -> Result<u32, VTFLoadError> where R: Read + Seek {
+        // The probe has already checked that the data fits in the file
+        let mut remaining = VTFFile::image_data_size(&self.header).unwrap_or(0);
+        try!(file.seek(SeekFrom::Start(self.start + self.image_offset)).map_err(VTFLoadError::Io));
+
+        let mut buffer = vec![0; 64 * 1024];
+        let mut crc = 0xFFFFFFFF;
+        while remaining > 0 {
+            let chunk = cmp::min(remaining, buffer.len() as u64) as usize;
+            try!(file.read_exact(&mut buffer[..chunk]).map_err(VTFLoadError::Io));
+            crc = resource::crc32_update(crc, &buffer[..chunk]);
+            remaining -= chunk as u64;
+        }
+
+        Ok(crc ^ 0xFFFFFFFF)
+    }
+
+    /// Reads and decodes the thumbnail from the source the probe was read from.
+    pub fn load_thumb<R>(&self, file: &mut R) -> Result<Option<VTFImageWrapper>, VTFLoadError> where R: Read + Seek {
+        let header70 = self.header.get_h70();
+
+        match self.thumb_offset {
+            Some(offset) => {
+                try!(file.seek(SeekFrom::Start(self.start + offset)).map_err(VTFLoadError::Io));
+                Ok(Some(try!(VTFImageWrapper::load(&mut *file, header70.thumbnail_width as u16, header70.thumbnail_height as u16, header70.thumbnail_format))))
+            },
+            None => Ok(None)
+        }
+    }
+
+    /// Reads and decodes a single image from the source the probe was read from.
+    /// Only the requested image is read.
+    pub fn load_image<R>(&self, file: &mut R, frame: u16, face: usize, slice: u16, mip_level: u8) -> Result<VTFImageWrapper, VTFLoadError> where R: Read + Seek {
+        let offset = match self.image_data_offset(frame, face, slice, mip_level) {
+            Some(offset) => offset,
+            None => return Err(VTFLoadError::VTF(VTFError::ImageIndex))
+        };
+        let header70 = self.header.get_h70();
+        let mip_dims = VTFFile::compute_mip_dimensions(header70.width, header70.height, mip_level);
+
+        try!(file.seek(SeekFrom::Start(self.start + offset)).map_err(VTFLoadError::Io));
+        VTFImageWrapper::load(&mut *file, mip_dims.0, mip_dims.1, header70.image_format)
+    }
+
+    /// Computes where a single image is stored, relative to the start of the VTF data.
+    /// Returns `None` if the image doesn't exist.
+    fn image_data_offset(&self, frame: u16, face: usize, slice: u16, mip_level: u8) -> Option<u64> {
+        let header = &self.header;
+        let header70 = header.get_h70();
+        let frame_count = VTFFile::frame_count_of(header) as u64;
+        let face_count = header.face_count() as u64;
+        let mip_depth = VTFFile::compute_mip_depth(header.depth(), mip_level);
+
+        if mip_level >= self.mip_count() || frame as u64 >= frame_count || face as u64 >= face_count || slice >= mip_depth {
+            return None;
+        }
+
+        // Mips are stored smallest first, so skip past every mip smaller than the
+        // requested one
+        let mut offset = self.image_offset;
+        for level in (mip_level + 1)..self.mip_count() {
+            let mip_dims = VTFFile::compute_mip_dimensions(header70.width, header70.height, level);
+            let level_depth = VTFFile::compute_mip_depth(header.depth(), level) as u64;
+            offset += header70.image_format.data_size(mip_dims.0, mip_dims.1).unwrap_or(0) * frame_count * face_count * level_depth;
+        }
+
+        // Then skip to the requested image within the mip
+        let mip_dims = VTFFile::compute_mip_dimensions(header70.width, header70.height, mip_level);
+        let image_size = header70.image_format.data_size(mip_dims.0, mip_dims.1).unwrap_or(0);
+        let image_index = (frame as u64 * face_count + face as u64) * mip_depth as u64 + slice as u64;
+
+        Some(offset + image_index * image_size)
+    }
 }
 
 impl VTFFile {
     pub fn open(file: &mut File) -> Result<VTFFile, VTFLoadError> {
-        let metadata = file.metadata().unwrap();        
+        VTFFile::from_reader(file)
+    }
+
+    /// Loads a VTF file from an in-memory buffer.
+    pub fn from_bytes(bytes: &[u8]) -> Result<VTFFile, VTFLoadError> {
+        VTFFile::from_reader(&mut Cursor::new(bytes))
+    }
+
+    /// Reads only the header and resource table of a VTF file, without loading any
+    /// image data or resource data chunks. The source is expected to be positioned at
+    /// the start of the VTF data.
+    pub fn probe<R>(file: &mut R) -> Result<VTFProbe, VTFLoadError> where R: Read + Seek {
+        // Get the length of the stream, then return to where the VTF data starts
+        let start = try!(file.seek(SeekFrom::Current(0)).map_err(VTFLoadError::Io));
+        let len = try!(file.seek(SeekFrom::End(0)).map_err(VTFLoadError::Io)) - start;
+        try!(file.seek(SeekFrom::Start(start)).map_err(VTFLoadError::Io));
 
         //Size check
-        if metadata.len() < mem::size_of::<format::Header70>() as u64 {
+        if len < mem::size_of::<format::Header70>() as u64 {
             return Err(VTFLoadError::VTF(VTFError::FileSize));
         }
 
         let header: HeaderVersion;
         let header_root = try!(HeaderRoot::load(&mut *file));
 
+        if header_root.header_size < 0 || header_root.header_size as u64 > len {
+            return Err(VTFLoadError::VTF(VTFError::BadResourceOffset));
+        }
+
+        // The offsets of the thumbnail and the high-res image data, relative to the start
+        // of the VTF data. Files without a thumbnail have no thumbnail offset.
+        let thumb_offset: Option<u64>;
+        let image_offset: u64;
+        let mut resources: Option<Vec<Resource>> = None;
 
         if header_root.version == [7, 3] || header_root.version == [7, 4] || header_root.version == [7, 5] {
             header = HeaderVersion::H73(
@@ -42,122 +321,332 @@ impl VTFFile {
                                 try!(Header72::load(&mut *file)),
                                 try!(Header73::load(&mut *file)));
 
-            
-            
-            let thumb: VTFImageWrapper;
-            let mips: Vec<VTFImageWrapper>;
-            let image: VTFImageWrapper;
-            //Create a vector with a capacity of the header's listed resource count
-            let mut resources: Vec<Resource>;
-            {
-                let header_root = header.get_root();
-                let header70 = header.get_h70();
-                let header73 = header.get_h73().unwrap();
-                let resource_count = header73.resource_count as usize;
-                
-
-                file.seek(SeekFrom::Start((header_root.header_size - header73.resource_count as i32*8) as u64)).unwrap();
-                resources = Vec::with_capacity(resource_count as usize);
-
-                let mut thumb_ri: usize = 0; //Index of thumbnail resource
-                let mut image_ri: usize = 0; //Index of image resource
-                let mut ri = 0; //Resource index for loop
-                while ri < resource_count {
-                    resources.push(try!(Resource::load(&mut *file)));
-
-                    // Figure out if the loaded resource is a thumbnail or image resource, and if it is
-                    // store the index
-                    match &resources[ri].id {
-                        &ResourceID::LegacyLowResImage => thumb_ri = ri,
-                        &ResourceID::LegacyImage => image_ri = ri,
-                        _ => ()
-                    }
-                    ri += 1;
+            let header_size = header.get_root().header_size as u64;
+            let resource_count = header.get_h73().map(|h| h.resource_count).unwrap_or(0) as u64;
+
+            // The resource table sits at the end of the header
+            if resource_count * 8 > header_size {
+                return Err(VTFLoadError::VTF(VTFError::BadResourceOffset));
+            }
+            try!(file.seek(SeekFrom::Start(start + header_size - resource_count * 8)).map_err(VTFLoadError::Io));
+
+            let mut resource_vec = Vec::with_capacity(resource_count as usize);
+            let mut thumb_ri: Option<usize> = None; //Index of thumbnail resource
+            let mut image_ri: Option<usize> = None; //Index of image resource
+            for ri in 0..resource_count as usize {
+                resource_vec.push(try!(Resource::load(&mut *file)));
+
+                // Figure out if the loaded resource is a thumbnail or image resource, and if it is
+                // store the index
+                match &resource_vec[ri].id {
+                    &ResourceID::LegacyLowResImage => thumb_ri = Some(ri),
+                    &ResourceID::LegacyImage => image_ri = Some(ri),
+                    _ => ()
                 }
-                let (thumb_ri, image_ri) = (thumb_ri, image_ri); //Remove mutability from indices
-
-                // Go to the start of the thumbnail
-                file.seek(SeekFrom::Start(resources[thumb_ri].data as u64)).unwrap();
-                thumb = try!(VTFImageWrapper::load(&mut *file, header70.thumbnail_width as u16, header70.thumbnail_height as u16, header70.thumbnail_format).map_err(VTFLoadError::Io));
-                
-
-                // Go to the start of the mips. This does not have to be re-done for the image proper
-                // as that comes dirctly after the mips.
-                file.seek(SeekFrom::Start(resources[image_ri].data as u64)).unwrap();
-                mips = VTFFile::load_mips(&mut *file, header70.width, header70.height, header70.mip_count, header70.image_format);
-                image = try!(VTFImageWrapper::load(&mut *file, header70.width, header70.height, header70.image_format).map_err(VTFLoadError::Io));
             }
 
-            Ok(VTFFile {header: header, resources: Some(resources), thumb: thumb, mips: mips, image: image})
+            thumb_offset = thumb_ri.map(|ri| resource_vec[ri].data as u64);
+            image_offset = match image_ri {
+                Some(ri) => resource_vec[ri].data as u64,
+                None => return Err(VTFLoadError::VTF(VTFError::MissingImage))
+            };
+            resources = Some(resource_vec);
 
-
-        } else if header_root.version == [7, 2] {
-            header = HeaderVersion::H72(
+        } else if header_root.version == [7, 2] || header_root.version == [7, 1] || header_root.version == [7, 0] {
+            header = match header_root.version[1] {
+                2 => HeaderVersion::H72(
                                 header_root, 
                                 try!(Header70::load(&mut *file)),
-                                try!(Header72::load(&mut *file)));
-            let thumb: VTFImageWrapper;
-            let mips: Vec<VTFImageWrapper>;
-            let image: VTFImageWrapper;
-            {
-                let header_root = header.get_root();
-                let header70 = header.get_h70();
-
-                // Go to the end of the header
-                file.seek(SeekFrom::Start(header_root.header_size as u64)).unwrap();
-                thumb = try!(VTFImageWrapper::load(&mut *file, header70.thumbnail_width as u16, header70.thumbnail_height as u16, header70.thumbnail_format).map_err(VTFLoadError::Io));
-
-                mips = VTFFile::load_mips(&mut *file, header70.width, header70.height, header70.mip_count, header70.image_format);
-                image = try!(VTFImageWrapper::load(&mut *file, header70.width, header70.height, header70.image_format).map_err(VTFLoadError::Io));
+                                try!(Header72::load(&mut *file))),
+                _ => HeaderVersion::H70(
+                                header_root, 
+                                try!(Header70::load(&mut *file)))
+            };
+
+            // The thumbnail comes directly after the header, and the high-res image
+            // directly after that
+            let header_size = header.get_root().header_size as u64;
+            thumb_offset = Some(header_size);
+            image_offset = header_size + VTFFile::thumb_data_size(&header);
+
+        } else {
+            return Err(VTFLoadError::VTF(VTFError::HeaderVersion))
+        }
+
+        let image_format = header.get_h70().image_format;
+        let thumb_format = header.get_h70().thumbnail_format;
+
+        // Make sure the file's long enough to hold everything the header says it does
+        // before reading any of it
+        let thumb_size = VTFFile::thumb_data_size(&header);
+        let thumb_offset = match thumb_offset {
+            Some(offset) if thumb_format != ImageFormat::NONE && thumb_size != 0 => Some(offset),
+            _ => None
+        };
+        if let Some(offset) = thumb_offset {
+            if offset > len {
+                return Err(VTFLoadError::VTF(VTFError::BadResourceOffset));
+            } else if offset + thumb_size > len {
+                return Err(VTFLoadError::VTF(VTFError::Truncated{expected: offset + thumb_size, actual: len}));
             }
-            Ok(VTFFile {header: header, resources: None, thumb: thumb, mips: mips, image: image})
+        }
 
+        if image_offset > len {
+            return Err(VTFLoadError::VTF(VTFError::BadResourceOffset));
+        }
+        match VTFFile::image_data_size(&header) {
+            Some(size) => if size > len - image_offset {
+                return Err(VTFLoadError::VTF(VTFError::Truncated{expected: image_offset.saturating_add(size), actual: len}));
+            },
+            None => return Err(VTFLoadError::VTF(match image_format.block_size() {
+                Some(_) => VTFError::Truncated{expected: u64::max_value(), actual: len},
+                None => VTFError::UnsupportedFormat(image_format)
+            }))
+        }
 
-        } else if header_root.version == [7, 1] || header_root.version == [7, 0] {
-            header = HeaderVersion::H70(
-                                header_root, 
-                                try!(Header70::load(&mut *file)));
-            let thumb: VTFImageWrapper;
-            let mips: Vec<VTFImageWrapper>;
-            let image: VTFImageWrapper;
-            {
-                let header_root = header.get_root();
-                let header70 = header.get_h70();
-
-                file.seek(SeekFrom::Start(header_root.header_size as u64)).unwrap();
-                thumb = try!(VTFImageWrapper::load(&mut *file, header70.thumbnail_width as u16, header70.thumbnail_height as u16, header70.thumbnail_format).map_err(VTFLoadError::Io));
-
-                mips = VTFFile::load_mips(&mut *file, header70.width, header70.height, header70.mip_count, header70.image_format);
-                image = try!(VTFImageWrapper::load(&mut *file, header70.width, header70.height, header70.image_format).map_err(VTFLoadError::Io));
+        Ok(VTFProbe {
+            header: header,
+            resources: resources,
+            start: start,
+            len: len,
+            thumb_offset: thumb_offset,
+            image_offset: image_offset
+        })
+    }
+
+    /// Loads a VTF file from any seekable source. The source is expected to be
+    /// positioned at the start of the VTF data.
+    pub fn from_reader<R>(file: &mut R) -> Result<VTFFile, VTFLoadError> where R: Read + Seek {
+        let probe = try!(VTFFile::probe(&mut *file));
+        let resource_data = try!(probe.load_resources(&mut *file));
+        let thumb = try!(probe.load_thumb(&mut *file));
+
+        let frames;
+        let mut image_crc = None;
+        {
+            let header = &probe.header;
+            let header70 = header.get_h70();
+
+            // Go to the start of the mips. This does not have to be re-done for the image proper
+            // as that comes directly after the mips.
+            try!(file.seek(SeekFrom::Start(probe.start + probe.image_offset)).map_err(VTFLoadError::Io));
+
+            // If there's a CRC to check the image data against, read the data into memory once
+            // so it can be both checked and decoded
+            if probe.crc().is_some() {
+                let mut image_data = vec![0; VTFFile::image_data_size(header).unwrap_or(0) as usize];
+                try!(file.read_exact(&mut image_data).map_err(VTFLoadError::Io));
+                image_crc = Some(resource::crc32(&image_data));
+
+                frames = try!(VTFFile::load_mips(&mut Cursor::new(image_data), header70.width, header70.height, header.depth(), VTFFile::mip_count(header), VTFFile::frame_count_of(header), header.face_count(), header70.image_format));
+            } else {
+                frames = try!(VTFFile::load_mips(&mut *file, header70.width, header70.height, header.depth(), VTFFile::mip_count(header), VTFFile::frame_count_of(header), header.face_count(), header70.image_format));
             }
-            Ok(VTFFile {header: header, resources: None, thumb: thumb, mips: mips, image: image})
+        }
 
+        Ok(VTFFile {
+            header: probe.header,
+            resources: probe.resources,
+            thumb: thumb,
+            frames: frames,
+            resource_data: resource_data,
+            image_crc: image_crc
+        })
+    }
 
-        } else {
-            Err(VTFLoadError::VTF(VTFError::HeaderVersion))
+    /// Opens a VTF file without loading any image data. Individual images are read and
+    /// decoded from the source only when they're requested.
+    pub fn lazy<R>(mut file: R) -> Result<LazyVTFFile<R>, VTFLoadError> where R: Read + Seek {
+        let probe = try!(VTFFile::probe(&mut file));
+        Ok(LazyVTFFile::new(file, probe))
+    }
+
+    /// Gets a frame and its mip chain by its index in the file. Returns `None` if the
+    /// frame does not exist.
+    pub fn frame(&self, frame: usize) -> Option<&VTFFrame> {
+        self.frames.get(frame)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Gets the frame the animation starts on, as listed in the header. Falls back
+    /// to the first frame if the header's start frame is out of range.
+    pub fn start_frame(&self) -> &VTFFrame {
+        let start_frame = self.header.get_h70().start_frame as usize;
+        self.frames.get(start_frame).unwrap_or(&self.frames[0])
+    }
+
+    /// Gets the full-size image of the starting frame. For environment maps, this
+    /// is the first cubemap face.
+    pub fn image(&self) -> &VTFImageWrapper {
+        self.start_frame().image()
+    }
+
+    /// Gets the given mip level of the starting frame, with level 0 being the
+    /// full-size image.
+    pub fn mip(&self, mip_level: usize) -> Option<&VTFImageWrapper> {
+        self.start_frame().mip(mip_level)
+    }
+
+    /// The depth of the full-size image. This is 1 for everything but volume textures.
+    pub fn depth(&self) -> u16 {
+        self.header.depth()
+    }
+
+    /// Gets a single depth slice from the given frame, face and mip level. Returns
+    /// `None` if any of those don't exist.
+    pub fn slice(&self, frame: usize, face: usize, mip_level: usize, slice: usize) -> Option<&VTFImageWrapper> {
+        self.frames.get(frame)
+            .and_then(|f| f.faces.get(face))
+            .and_then(|f| f.slice(mip_level, slice))
+    }
+
+    /// Decodes the given mip level of the starting frame's volume into a single RGBA8
+    /// buffer, with depth slices laid out one after another.
+    pub fn volume_rgba8_raw(&self, mip_level: usize) -> Option<Vec<u8>> {
+        self.start_frame().faces[0].volume_rgba8_raw(mip_level)
+    }
+
+    /// The number of faces in each frame. This is 6 or 7 for environment maps, and
+    /// 1 for everything else.
+    pub fn face_count(&self) -> usize {
+        self.header.face_count()
+    }
+
+    /// Gets the typed contents of the file's resources. Files older than 7.3 have no
+    /// resources, so every accessor returns `None`.
+    pub fn resources(&self) -> &VTFResources {
+        &self.resource_data
+    }
+
+    /// Checks the CRC resource against the CRC32 of the high-res image data. Returns
+    /// `None` if the file doesn't have a CRC resource.
+    ///
+    /// Note that Valve's vtex computes this CRC from the source image rather than the
+    /// compiled data, so files it produces are not expected to match.
+    pub fn verify_crc(&self) -> Option<bool> {
+        match (self.resource_data.crc(), self.image_crc) {
+            (Some(stored), Some(computed)) => Some(stored == computed),
+            _ => None
         }
     }
 
-    fn load_mips(file: &mut File, width: u16, height: u16, mip_count: u8, image_format: ImageFormat) -> Vec<VTFImageWrapper> {
-        let mut mips: Vec<VTFImageWrapper> = Vec::with_capacity((mip_count - 1) as usize);
-        unsafe{ mips.set_len(mip_count as usize - 1) };
+    /// Sets the tone-map operator used when converting the file's HDR images to 8-bit
+    /// colors. Does nothing if the file isn't HDR.
+    pub fn set_tone_map(&mut self, tone_map: ToneMap) {
+        for frame in &mut self.frames {
+            for face in &mut frame.faces {
+                for slice in &mut face.slices {
+                    slice.set_tone_map(tone_map);
+                }
+                for mip in &mut face.mips {
+                    for slice in mip {
+                        slice.set_tone_map(tone_map);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Loads the mip chains for every frame, face and slice in the image. Within the file,
+    /// mips are stored from smallest to largest. Each mip level stores every frame, each
+    /// frame stores every face, and each face stores every depth slice before moving on.
+    fn load_mips<R>(file: &mut R, width: u16, height: u16, depth: u16, mip_count: u8, frame_count: u16, face_count: usize, image_format: ImageFormat) -> Result<Vec<VTFFrame>, VTFLoadError> where R: Read {
+        // Mip levels are pushed as they are read, so each vector ends up ordered from
+        // the smallest mip to the full image.
+        let mut face_mips: Vec<Vec<Vec<Vec<VTFImageWrapper>>>> = Vec::with_capacity(frame_count as usize);
+        for _ in 0..frame_count {
+            let mut faces = Vec::with_capacity(face_count);
+            for _ in 0..face_count {
+                faces.push(Vec::with_capacity(mip_count as usize));
+            }
+            face_mips.push(faces);
+        }
 
         let mut mip_level = mip_count;
 
         let mut mip_dims: (u16, u16);
 
-        while mip_level > 1 {
+        while mip_level > 0 {
             mip_level -= 1;
             mip_dims = VTFFile::compute_mip_dimensions(width, height, mip_level);
-            mips[mip_level as usize - 1] = VTFImageWrapper::load(&mut *file, mip_dims.0, mip_dims.1, image_format).unwrap();
+            let mip_depth = VTFFile::compute_mip_depth(depth, mip_level);
+            for faces in &mut face_mips {
+                for mips in faces {
+                    let mut slices = Vec::with_capacity(mip_depth as usize);
+                    for _ in 0..mip_depth {
+                        slices.push(try!(VTFImageWrapper::load(&mut *file, mip_dims.0, mip_dims.1, image_format)));
+                    }
+                    mips.push(slices);
+                }
+            }
         }
-        
-        mips
+
+        let mut frames = Vec::with_capacity(frame_count as usize);
+        for faces in face_mips {
+            let mut frame = VTFFrame {faces: Vec::with_capacity(face_count)};
+            for mut mips in faces {
+                // mip_count is never 0, so there's always a full-size image to pop
+                let slices = mips.pop().unwrap_or_else(Vec::new);
+                // Store the remaining mips with the largest first, so that mip level n is at index n - 1
+                mips.reverse();
+                frame.faces.push(VTFFace {mips: mips, slices: slices});
+            }
+            frames.push(frame);
+        }
+
+        Ok(frames)
+    }
+
+    /// The number of mips listed in the header, treating a count of 0 as 1.
+    fn mip_count(header: &HeaderVersion) -> u8 {
+        cmp::max(header.get_h70().mip_count, 1)
     }
 
-    fn compute_mip_dimensions(width: u16, height: u16, mip_level: u8) -> (u16, u16) {
-        let mut mip_width = width >> mip_level;
-        let mut mip_height = height >> mip_level;
+    /// The number of frames listed in the header, treating a count of 0 as 1.
+    fn frame_count_of(header: &HeaderVersion) -> u16 {
+        cmp::max(header.get_h70().frames, 1)
+    }
+
+    /// The number of bytes taken up by the thumbnail.
+    fn thumb_data_size(header: &HeaderVersion) -> u64 {
+        let header70 = header.get_h70();
+        header70.thumbnail_format.data_size(header70.thumbnail_width as u16, header70.thumbnail_height as u16).unwrap_or(0)
+    }
+
+    /// The number of bytes taken up by every mip, frame, face and slice of the high-res
+    /// image. Returns `None` if the format has no known size or if the size overflows.
+    fn image_data_size(header: &HeaderVersion) -> Option<u64> {
+        let header70 = header.get_h70();
+        let images_per_slice = (VTFFile::frame_count_of(header) as u64) * (header.face_count() as u64);
+
+        let mut size: u64 = 0;
+        for mip_level in 0..VTFFile::mip_count(header) {
+            let mip_dims = VTFFile::compute_mip_dimensions(header70.width, header70.height, mip_level);
+            let mip_depth = VTFFile::compute_mip_depth(header.depth(), mip_level) as u64;
+            let mip_size = match header70.image_format.data_size(mip_dims.0, mip_dims.1) {
+                Some(mip_size) => mip_size,
+                None => return None
+            };
+
+            size = match mip_size.checked_mul(mip_depth)
+                        .and_then(|s| s.checked_mul(images_per_slice))
+                        .and_then(|s| s.checked_add(size)) {
+                Some(size) => size,
+                None => return None
+            };
+        }
+
+        Some(size)
+    }
+
+    /// Computes the dimensions of a mip level, halving the full-size dimensions at each
+    /// level down to a minimum of 1.
+    pub fn compute_mip_dimensions(width: u16, height: u16, mip_level: u8) -> (u16, u16) {
+        let mut mip_width = width.checked_shr(mip_level as u32).unwrap_or(0);
+        let mut mip_height = height.checked_shr(mip_level as u32).unwrap_or(0);
 
         if mip_width < 1 {
             mip_width = 1;
@@ -168,4 +657,14 @@ impl VTFFile {
 
         (mip_width, mip_height)
     }
+
+    fn compute_mip_depth(depth: u16, mip_level: u8) -> u16 {
+        let mip_depth = depth.checked_shr(mip_level as u32).unwrap_or(0);
+
+        if mip_depth < 1 {
+            1
+        } else {
+            mip_depth
+        }
+    }
 }
\ No newline at end of file
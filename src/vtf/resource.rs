@@ -0,0 +1,720 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::mem;
+
+use common::Token;
+use super::format::{Resource, ResourceID};
+use super::error::{VTFLoadError, VTFError};
+
+/// The typed contents of the resources that a 7.3+ VTF file can carry alongside its
+/// image data. Files older than 7.3 have no resources, so every field is empty.
+#[derive(Debug, Clone, Default)]
+pub struct VTFResources {
+    crc: Option<u32>,
+    lod_settings: Option<LodSettings>,
+    settings_ex: Option<TextureSettingsEx>,
+    key_values: Option<KeyValueData>,
+    sheet: Option<Sheet>,
+    unknown: Vec<UnknownResource>,
+    /// The raw data chunks of known resources, kept so they can be written back out
+    /// unchanged
+    chunks: Vec<(ResourceID, Vec<u8>)>
+}
+
+impl VTFResources {
+    /// Parses the resources listed in the resource table. Resources that store their
+    /// data out-of-line are read from `file`, with their offsets taken relative to `start`.
+    pub fn load<R>(file: &mut R, start: u64, len: u64, resources: &[Resource]) -> Result<VTFResources, VTFLoadError> where R: Read + Seek {
+        let mut parsed = VTFResources::default();
+
+        for rsrc in resources {
+            match &rsrc.id {
+                &ResourceID::Crc => parsed.crc = Some(rsrc.data),
+                &ResourceID::TextureLODSettings => parsed.lod_settings = Some(LodSettings::from_data(rsrc.data)),
+                &ResourceID::TextureSettingsEx => parsed.settings_ex = Some(TextureSettingsEx::from_data(rsrc.data)),
+                &ResourceID::KeyValueData => {
+                    let chunk = try!(load_chunk(&mut *file, start, len, rsrc.data));
+                    parsed.key_values = Some(KeyValueData::parse(&chunk));
+                    parsed.chunks.push((rsrc.id, chunk));
+                }
+                &ResourceID::Sheet => {
+                    let chunk = try!(load_chunk(&mut *file, start, len, rsrc.data));
+                    // A sheet that doesn't parse is only kept as its raw chunk, like
+                    // unknown resources, rather than failing the whole file
+                    parsed.sheet = Sheet::parse(&chunk).ok();
+                    parsed.chunks.push((rsrc.id, chunk));
+                }
+                &ResourceID::Unknown(id) => {
+                    let bytes = match rsrc.id.has_data_chunk() {
+                        true => Some(try!(load_chunk(&mut *file, start, len, rsrc.data))),
+                        false => None
+                    };
+                    parsed.unknown.push(UnknownResource {id: id, data: rsrc.data, bytes: bytes});
+                }
+                _ => ()
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    /// The CRC32 stored in the file's `CRC` resource.
+    pub fn crc(&self) -> Option<u32> {
+        self.crc
+    }
+
+    pub fn lod_settings(&self) -> Option<&LodSettings> {
+        self.lod_settings.as_ref()
+    }
+
+    pub fn settings_ex(&self) -> Option<&TextureSettingsEx> {
+        self.settings_ex.as_ref()
+    }
+
+    pub fn key_values(&self) -> Option<&KeyValueData> {
+        self.key_values.as_ref()
+    }
+
+    /// The sprite sheet used by the `SpriteCard` shader. This is `None` if the sheet
+    /// couldn't be parsed, though its raw data is still available from `chunk`.
+    pub fn sheet(&self) -> Option<&Sheet> {
+        self.sheet.as_ref()
+    }
+
+    /// Resources this crate doesn't know about, in the order they're listed in the
+    /// resource table.
+    pub fn unknown(&self) -> &[UnknownResource] {
+        &self.unknown
+    }
+
+    /// Gets the raw contents of a resource's data chunk, as read from the file.
+    pub fn chunk(&self, id: ResourceID) -> Option<&[u8]> {
+        match id {
+            ResourceID::Unknown(raw_id) => self.unknown.iter()
+                .find(|r| r.id == raw_id)
+                .and_then(|r| r.bytes.as_ref())
+                .map(|b| &b[..]),
+            _ => self.chunks.iter()
+                .find(|&&(chunk_id, _)| chunk_id == id)
+                .map(|&(_, ref b)| &b[..])
+        }
+    }
+}
+
+/// A resource with an ID this crate doesn't know about. Its contents are kept as-is so
+/// they can be written back out unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownResource {
+    pub id: u32,
+    /// The value stored in the resource table. This is the resource's inline data if
+    /// it has no data chunk, and the chunk's offset otherwise.
+    pub data: u32,
+    /// The contents of the resource's data chunk, not including its size prefix.
+    /// `None` if the data is stored inline.
+    pub bytes: Option<Vec<u8>>
+}
+
+/// The `LOD` resource, which clamps the resolution the texture is loaded at. Clamps
+/// are given as the log2 of the largest allowed size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LodSettings {
+    pub clamp_u: u8,
+    pub clamp_v: u8,
+    /// Clamps used on the Xbox 360
+    pub clamp_u_360: u8,
+    pub clamp_v_360: u8
+}
+
+impl LodSettings {
+    fn from_data(data: u32) -> LodSettings {
+        let bytes: [u8; 4] = unsafe{ mem::transmute(data) };
+        LodSettings {
+            clamp_u: bytes[0],
+            clamp_v: bytes[1],
+            clamp_u_360: bytes[2],
+            clamp_v_360: bytes[3]
+        }
+    }
+}
+
+/// The `TSO` resource, which holds extended texture settings. None of the flags are
+/// used by the engine, but they're kept so tools can inspect them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureSettingsEx {
+    pub flags: [u8; 4]
+}
+
+impl TextureSettingsEx {
+    fn from_data(data: u32) -> TextureSettingsEx {
+        TextureSettingsEx {
+            flags: unsafe{ mem::transmute(data) }
+        }
+    }
+}
+
+/// How deeply KeyValues blocks can be nested before the text is rejected
+const KEY_VALUE_MAX_DEPTH: usize = 64;
+
+/// The `KVD` resource, an arbitrary block of KeyValues text.
+#[derive(Debug, Clone)]
+pub struct KeyValueData {
+    /// The text as stored in the file
+    pub text: String,
+    /// The parsed key/values. Top-level values and blocks are stored in an unnamed
+    /// root block. `None` if the text isn't valid KeyValues.
+    pub root: Option<KeyValueBlock>
+}
+
+impl KeyValueData {
+    /// Parses the contents of a KeyValueData resource, not including its size prefix.
+    /// The resource is only metadata, so text that fails to parse is still kept rather
+    /// than failing the whole file.
+    pub fn parse(data: &[u8]) -> KeyValueData {
+        let text = String::from_utf8_lossy(data).trim_right_matches('\0').to_string();
+
+        let root = KeyValueBlock::parse(&text).ok();
+        KeyValueData {text: text, root: root}
+    }
+
+    /// Gets a top-level value by its key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.root.as_ref().and_then(|r| r.get(key))
+    }
+
+    /// Gets a top-level block by its name.
+    pub fn block(&self, name: &str) -> Option<&KeyValueBlock> {
+        self.root.as_ref().and_then(|r| r.block(name))
+    }
+}
+
+/// A named block of key/value pairs, which may contain further blocks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyValueBlock {
+    pub name: String,
+    pub values: Vec<(String, String)>,
+    pub blocks: Vec<KeyValueBlock>
+}
+
+impl KeyValueBlock {
+    /// Parses KeyValues text into an unnamed root block.
+    pub fn parse(text: &str) -> Result<KeyValueBlock, VTFError> {
+        let tokens = try!(tokenize_key_values(text));
+        let mut root = KeyValueBlock::default();
+        let mut pos = 0;
+
+        try!(root.parse_tokens(&tokens, &mut pos, 0));
+        Ok(root)
+    }
+
+    fn parse_tokens(&mut self, tokens: &[Token], pos: &mut usize, depth: usize) -> Result<(), VTFError> {
+        while *pos < tokens.len() {
+            let token = tokens[*pos];
+            *pos += 1;
+
+            match token {
+                Token::Start => (),
+                Token::BlockType(name) => {
+                    if depth >= KEY_VALUE_MAX_DEPTH {
+                        return Err(VTFError::KeyValueSyntax);
+                    }
+                    match tokens.get(*pos) {
+                        Some(&Token::BlockStart) => *pos += 1,
+                        _ => return Err(VTFError::KeyValueSyntax)
+                    }
+
+                    let mut block = KeyValueBlock {name: name.to_string(), values: Vec::new(), blocks: Vec::new()};
+                    try!(block.parse_tokens(tokens, pos, depth + 1));
+                    self.blocks.push(block);
+                }
+                Token::ParamType(key) => match tokens.get(*pos) {
+                    Some(&Token::ParamValue(value)) => {
+                        *pos += 1;
+                        self.values.push((key.to_string(), value.to_string()));
+                    }
+                    _ => return Err(VTFError::KeyValueSyntax)
+                },
+                Token::BlockEnd if depth > 0 => return Ok(()),
+                Token::End if depth == 0 => return Ok(()),
+                _ => return Err(VTFError::KeyValueSyntax)
+            }
+        }
+
+        Err(VTFError::KeyValueSyntax)
+    }
+
+    /// Gets the first value with the given key. Keys are case-insensitive.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.iter()
+            .find(|&&(ref k, _)| k.eq_ignore_ascii_case(key))
+            .map(|&(_, ref v)| &v[..])
+    }
+
+    /// Gets the first block with the given name. Names are case-insensitive.
+    pub fn block(&self, name: &str) -> Option<&KeyValueBlock> {
+        self.blocks.iter().find(|b| b.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A piece of KeyValues text, before it's known whether a string is a key, a value or
+/// the name of a block
+#[derive(Clone, Copy, PartialEq)]
+enum KeyValueItem<'s> {
+    Str(&'s str),
+    Open,
+    Close
+}
+
+/// Splits KeyValues text into the same tokens the VMT lexer produces. The text comes
+/// straight from the file, so this only ever slices it next to ASCII delimiters, which
+/// are always on character boundaries.
+fn tokenize_key_values(text: &str) -> Result<Vec<Token>, VTFError> {
+    let bytes = text.as_bytes();
+    let is_delimiter = |b: u8| b == b'"' || b == b'{' || b == b'}' || b.is_ascii_whitespace();
+
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'{' => {
+                items.push(KeyValueItem::Open);
+                i += 1;
+            }
+            b'}' => {
+                items.push(KeyValueItem::Close);
+                i += 1;
+            }
+            b'"' => {
+                let start = i + 1;
+                let end = match bytes[start..].iter().position(|&b| b == b'"') {
+                    Some(len) => start + len,
+                    None => return Err(VTFError::KeyValueSyntax)
+                };
+                items.push(KeyValueItem::Str(&text[start..end]));
+                i = end + 1;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b if is_delimiter(b) => i += 1,
+            _ => {
+                let start = i;
+                while i < bytes.len() && !is_delimiter(bytes[i]) {
+                    i += 1;
+                }
+                items.push(KeyValueItem::Str(&text[start..i]));
+            }
+        }
+    }
+
+    // A string followed by a brace names a block. Otherwise, strings alternate between
+    // keys and values.
+    let mut tokens = vec![Token::Start];
+    for (index, item) in items.iter().enumerate() {
+        let token = match *item {
+            KeyValueItem::Open => Token::BlockStart,
+            KeyValueItem::Close => Token::BlockEnd,
+            KeyValueItem::Str(s) => match (items.get(index + 1), tokens[tokens.len() - 1]) {
+                (Some(&KeyValueItem::Open), _) => Token::BlockType(s),
+                (_, Token::ParamType(_)) => Token::ParamValue(s),
+                _ => Token::ParamType(s)
+            }
+        };
+        tokens.push(token);
+    }
+    tokens.push(Token::End);
+
+    Ok(tokens)
+}
+
+/// The `Sheet` resource, which splits the texture into the animated sprite sequences
+/// used by `SpriteCard` particles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sheet {
+    pub version: u32,
+    pub sequences: Vec<SheetSequence>
+}
+
+impl Sheet {
+    /// Parses the contents of a sheet resource, not including its size prefix.
+    pub fn parse(data: &[u8]) -> Result<Sheet, VTFError> {
+        let mut pos = 0;
+
+        let version = try!(read_u32(data, &mut pos));
+        // Version 0 sheets store a single rectangle per frame. Later versions store one
+        // for each of the four images a frame can blend between.
+        let rects_per_frame = match version {
+            0 => 1,
+            _ => 4
+        };
+
+        let sequence_count = try!(read_u32(data, &mut pos));
+        let mut sequences = Vec::new();
+        for _ in 0..sequence_count {
+            let number = try!(read_u32(data, &mut pos));
+            let clamp = try!(read_u32(data, &mut pos)) != 0;
+            let frame_count = try!(read_u32(data, &mut pos));
+            let total_time = try!(read_f32(data, &mut pos));
+
+            // Every frame takes up at least 20 bytes, so don't trust counts that can't fit
+            if frame_count as u64 * 20 > (data.len() - pos) as u64 {
+                return Err(VTFError::Truncated{expected: (pos as u64).saturating_add(frame_count as u64 * 20), actual: data.len() as u64});
+            }
+
+            let mut frames = Vec::with_capacity(frame_count as usize);
+            for _ in 0..frame_count {
+                let duration = try!(read_f32(data, &mut pos));
+                let mut rects = Vec::with_capacity(rects_per_frame);
+                for _ in 0..rects_per_frame {
+                    rects.push(SheetRect {
+                        u0: try!(read_f32(data, &mut pos)),
+                        v0: try!(read_f32(data, &mut pos)),
+                        u1: try!(read_f32(data, &mut pos)),
+                        v1: try!(read_f32(data, &mut pos))
+                    });
+                }
+                frames.push(SheetFrame {duration: duration, rects: rects});
+            }
+
+            sequences.push(SheetSequence {
+                number: number,
+                clamp: clamp,
+                total_time: total_time,
+                frames: frames
+            });
+        }
+
+        Ok(Sheet {version: version, sequences: sequences})
+    }
+
+    /// Gets a sequence by its sequence number.
+    pub fn sequence(&self, number: u32) -> Option<&SheetSequence> {
+        self.sequences.iter().find(|s| s.number == number)
+    }
+
+    /// Gets the UV rectangles to display for the given sequence, `time` seconds after
+    /// it started. Returns `None` if the sequence doesn't exist or has no frames.
+    pub fn uv_rects(&self, sequence: u32, time: f32) -> Option<&[SheetRect]> {
+        self.sequence(sequence).and_then(|s| s.rects_at(time))
+    }
+}
+
+/// A single animated sequence within a sprite sheet.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SheetSequence {
+    pub number: u32,
+    /// If true, the sequence stops on its last frame instead of looping
+    pub clamp: bool,
+    /// The length of the whole sequence, in seconds
+    pub total_time: f32,
+    pub frames: Vec<SheetFrame>
+}
+
+impl SheetSequence {
+    /// The length of the sequence, in seconds. Falls back to the sum of the frame
+    /// durations if the stored total isn't usable.
+    pub fn duration(&self) -> f32 {
+        match self.total_time > 0.0 && self.total_time.is_finite() {
+            true => self.total_time,
+            false => self.frames.iter().map(|f| f.duration.max(0.0)).sum()
+        }
+    }
+
+    /// Gets the index of the frame shown `time` seconds after the sequence started.
+    /// Looping sequences wrap around, while clamped sequences hold their last frame.
+    pub fn frame_index_at(&self, time: f32) -> Option<usize> {
+        if self.frames.is_empty() {
+            return None;
+        }
+
+        let duration = self.duration();
+        if !(duration > 0.0) || !time.is_finite() {
+            return Some(0);
+        }
+
+        let time = match self.clamp {
+            true => time.max(0.0).min(duration),
+            false => {
+                let wrapped = time % duration;
+                match wrapped < 0.0 {
+                    true => wrapped + duration,
+                    false => wrapped
+                }
+            }
+        };
+
+        // Frame durations are scaled so they add up to the sequence's length
+        let frame_total: f32 = self.frames.iter().map(|f| f.duration.max(0.0)).sum();
+        let scale = match frame_total > 0.0 {
+            true => duration / frame_total,
+            false => return Some(((time / duration * self.frames.len() as f32) as usize).min(self.frames.len() - 1))
+        };
+
+        let mut elapsed = 0.0;
+        for (i, frame) in self.frames.iter().enumerate() {
+            elapsed += frame.duration.max(0.0) * scale;
+            if time < elapsed {
+                return Some(i);
+            }
+        }
+
+        Some(self.frames.len() - 1)
+    }
+
+    /// Gets the frame shown `time` seconds after the sequence started.
+    pub fn frame_at(&self, time: f32) -> Option<&SheetFrame> {
+        self.frame_index_at(time).map(|i| &self.frames[i])
+    }
+
+    /// Gets the UV rectangles shown `time` seconds after the sequence started.
+    pub fn rects_at(&self, time: f32) -> Option<&[SheetRect]> {
+        self.frame_at(time).map(|f| &f.rects[..])
+    }
+}
+
+/// A single frame of a sprite sheet sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SheetFrame {
+    /// How long the frame is shown, in seconds
+    pub duration: f32,
+    /// The area of the texture shown by the frame. Version 0 sheets have a single
+    /// rectangle, and later versions have four.
+    pub rects: Vec<SheetRect>
+}
+
+/// A rectangle of texture coordinates, from (u0, v0) at the top left to (u1, v1) at
+/// the bottom right.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SheetRect {
+    pub u0: f32,
+    pub v0: f32,
+    pub u1: f32,
+    pub v1: f32
+}
+
+/// Reads an out-of-line resource's data, which is prefixed with its length in bytes.
+fn load_chunk<R>(file: &mut R, start: u64, len: u64, offset: u32) -> Result<Vec<u8>, VTFLoadError> where R: Read + Seek {
+    let offset = offset as u64;
+    if offset + 4 > len {
+        return Err(VTFLoadError::VTF(VTFError::BadResourceOffset));
+    }
+    try!(file.seek(SeekFrom::Start(start + offset)).map_err(VTFLoadError::Io));
+
+    let mut size_buffer: [u8; 4] = [0; 4];
+    try!(file.read_exact(&mut size_buffer).map_err(VTFLoadError::Io));
+    let size: u32 = unsafe{ mem::transmute(size_buffer) };
+    if size as u64 > len - offset - 4 {
+        return Err(VTFLoadError::VTF(VTFError::Truncated{expected: offset + 4 + size as u64, actual: len}));
+    }
+
+    let mut buffer = vec![0; size as usize];
+    try!(file.read_exact(&mut buffer).map_err(VTFLoadError::Io));
+    Ok(buffer)
+}
+
+pub(super) fn read_u32(data: &[u8], pos: &mut usize) -> Result<u32, VTFError> {
+    if data.len() < *pos + 4 {
+        return Err(VTFError::Truncated{expected: *pos as u64 + 4, actual: data.len() as u64});
+    }
+
+    let mut bytes: [u8; 4] = [0; 4];
+    bytes.copy_from_slice(&data[*pos..*pos + 4]);
+    *pos += 4;
+    Ok(unsafe{ mem::transmute(bytes) })
+}
+
+#[inline]
+pub(super) fn write_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]);
+}
+
+fn read_f32(data: &[u8], pos: &mut usize) -> Result<f32, VTFError> {
+    read_u32(data, pos).map(|bits| unsafe{ mem::transmute::<u32, f32>(bits) })
+}
+
+/// Computes the standard CRC32 (as used by zlib) of a buffer.
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32_update(0xFFFFFFFF, data) ^ 0xFFFFFFFF
+}
+
+/// Continues a CRC32 over another buffer. The first call should start with `0xFFFFFFFF`,
+/// and the final value should be inverted.
+pub fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB88320,
+                _ => crc >> 1
+            };
+        }
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{VTFResources, KeyValueData, KeyValueBlock, Sheet, SheetRect, KEY_VALUE_MAX_DEPTH};
+    use super::super::format::{Resource, ResourceID};
+
+    fn push_u32(data: &mut Vec<u8>, value: u32) {
+        data.extend_from_slice(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]);
+    }
+
+    fn push_f32(data: &mut Vec<u8>, value: f32) {
+        push_u32(data, value.to_bits());
+    }
+
+    /// Builds a sheet from `(number, clamp, total_time, frame durations)` for each
+    /// sequence. Each frame's rectangles are offset by its index, so they can be told
+    /// apart.
+    fn sheet_data(version: u32, sequences: &[(u32, bool, f32, &[f32])]) -> Vec<u8> {
+        let rects_per_frame = if version == 0 {1} else {4};
+
+        let mut data = Vec::new();
+        push_u32(&mut data, version);
+        push_u32(&mut data, sequences.len() as u32);
+        for &(number, clamp, total_time, durations) in sequences {
+            push_u32(&mut data, number);
+            push_u32(&mut data, clamp as u32);
+            push_u32(&mut data, durations.len() as u32);
+            push_f32(&mut data, total_time);
+            for (i, &duration) in durations.iter().enumerate() {
+                push_f32(&mut data, duration);
+                for r in 0..rects_per_frame {
+                    let offset = i as f32 + r as f32 / 4.0;
+                    for &coord in &[offset, 0.0, offset + 0.25, 1.0] {
+                        push_f32(&mut data, coord);
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn key_values_parse() {
+        let text = "\"Information\"\n{\n\t\"Author\" \"Valve\" // a comment\n\tcontact none\n}\n\"version\" \"2\"\n";
+        let kvd = KeyValueData::parse(text.as_bytes());
+
+        assert_eq!(kvd.get("Version"), Some("2"));
+        let info = kvd.block("information").unwrap();
+        assert_eq!(info.get("author"), Some("Valve"));
+        assert_eq!(info.get("contact"), Some("none"));
+    }
+
+    #[test]
+    fn key_values_keep_multi_byte_characters() {
+        let kvd = KeyValueData::parse("\"é\" \"b\"\n\"clé\" \"über\"".as_bytes());
+        assert_eq!(kvd.get("é"), Some("b"));
+        assert_eq!(kvd.get("clé"), Some("über"));
+    }
+
+    #[test]
+    fn invalid_key_values_are_kept_as_text() {
+        for text in &["a{b{", "{x}", "\"a\" \"b", "}", "\"a\" \"b\" {", "// é\u{85}\n\u{a0}{"] {
+            assert!(KeyValueBlock::parse(text).is_err(), "{:?} parsed", text);
+
+            let kvd = KeyValueData::parse(text.as_bytes());
+            assert!(kvd.root.is_none());
+            assert_eq!(&kvd.text, text);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_key_values_are_rejected() {
+        let depth = KEY_VALUE_MAX_DEPTH * 1000;
+        let text = "a{".repeat(depth) + &"}".repeat(depth);
+        assert!(KeyValueBlock::parse(&text).is_err());
+
+        let text = "a{".repeat(KEY_VALUE_MAX_DEPTH) + &"}".repeat(KEY_VALUE_MAX_DEPTH);
+        assert!(KeyValueBlock::parse(&text).is_ok());
+    }
+
+    #[test]
+    fn invalid_key_values_dont_fail_the_load() {
+        let text = b"\xc3\xa9 \"b\"\n{";
+        let mut chunk = vec![text.len() as u8, 0, 0, 0];
+        chunk.extend_from_slice(text);
+
+        let resources = [Resource {id: ResourceID::KeyValueData, data: 0}];
+        let loaded = VTFResources::load(&mut Cursor::new(&chunk), 0, chunk.len() as u64, &resources).unwrap();
+        assert!(loaded.key_values().unwrap().root.is_none());
+        assert_eq!(loaded.chunk(ResourceID::KeyValueData), Some(&text[..]));
+    }
+
+    #[test]
+    fn version_0_sheet() {
+        let data = sheet_data(0, &[(0, true, 1.0, &[0.25, 0.5, 0.25]), (5, false, 1.0, &[0.5, 0.5])]);
+        let sheet = Sheet::parse(&data).unwrap();
+
+        assert_eq!(sheet.version, 0);
+        assert_eq!(sheet.sequences.len(), 2);
+        assert!(sheet.sequences.iter().all(|s| s.frames.iter().all(|f| f.rects.len() == 1)));
+        assert_eq!(sheet.uv_rects(5, 0.75).unwrap(), &[SheetRect {u0: 1.0, v0: 0.0, u1: 1.25, v1: 1.0}]);
+        assert!(sheet.uv_rects(1, 0.0).is_none());
+
+        // Clamped sequences hold their first and last frames outside of their length
+        let clamped = sheet.sequence(0).unwrap();
+        let expected = [(-1.0, 0), (0.0, 0), (0.3, 1), (0.8, 2), (1.0, 2), (5.0, 2)];
+        for &(time, index) in &expected {
+            assert_eq!(clamped.frame_index_at(time), Some(index), "clamped at {}", time);
+        }
+
+        // Looping sequences wrap around, in both directions
+        let looping = sheet.sequence(5).unwrap();
+        let expected = [(0.25, 0), (0.75, 1), (1.0, 0), (1.25, 0), (1.75, 1), (-0.25, 1)];
+        for &(time, index) in &expected {
+            assert_eq!(looping.frame_index_at(time), Some(index), "looping at {}", time);
+        }
+    }
+
+    #[test]
+    fn version_1_sheet() {
+        // The frame durations add up to half a second, so they're scaled to fill the two
+        // second total
+        let data = sheet_data(1, &[(3, false, 2.0, &[0.25, 0.25]), (4, true, 2.0, &[0.25, 0.25])]);
+        let sheet = Sheet::parse(&data).unwrap();
+
+        assert_eq!(sheet.version, 1);
+        let looping = sheet.sequence(3).unwrap();
+        assert!(looping.frames.iter().all(|f| f.rects.len() == 4));
+        assert_eq!(looping.frames[1].rects[2], SheetRect {u0: 1.5, v0: 0.0, u1: 1.75, v1: 1.0});
+
+        let expected = [(0.5, 0), (1.5, 1), (2.0, 0), (2.5, 0), (3.5, 1)];
+        for &(time, index) in &expected {
+            assert_eq!(looping.frame_index_at(time), Some(index), "looping at {}", time);
+        }
+
+        let clamped = sheet.sequence(4).unwrap();
+        let expected = [(0.5, 0), (1.5, 1), (2.0, 1), (2.5, 1)];
+        for &(time, index) in &expected {
+            assert_eq!(clamped.frame_index_at(time), Some(index), "clamped at {}", time);
+        }
+        assert_eq!(sheet.uv_rects(4, 10.0).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn truncated_sheet_is_rejected() {
+        let data = sheet_data(1, &[(0, false, 1.0, &[0.5, 0.5])]);
+        for len in 0..data.len() {
+            assert!(Sheet::parse(&data[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn invalid_sheet_doesnt_fail_the_load() {
+        let data = sheet_data(1, &[(0, false, 1.0, &[0.5, 0.5])]);
+        let truncated = &data[..data.len() - 4];
+        let mut chunk = vec![truncated.len() as u8, 0, 0, 0];
+        chunk.extend_from_slice(truncated);
+
+        let resources = [Resource {id: ResourceID::Sheet, data: 0}];
+        let loaded = VTFResources::load(&mut Cursor::new(&chunk), 0, chunk.len() as u64, &resources).unwrap();
+        assert!(loaded.sheet().is_none());
+        assert_eq!(loaded.chunk(ResourceID::Sheet), Some(truncated));
+    }
+}
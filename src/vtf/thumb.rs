@@ -0,0 +1,145 @@
+//! Header data derived from the high-res image: the reflectivity vector and the low-res
+//! thumbnail. Both are computed the same way VTFLib computes them.
+
+use super::{VTFFile, ImageFormat};
+use super::image::{VTFImageWrapper, Dxt1, DxtQuality, decode_cropped};
+use super::mipmap::{self, MipOptions, MipFilter};
+
+/// The largest width or height a thumbnail is allowed to have
+const THUMB_MAX_SIZE: u16 = 16;
+
+impl VTFFile {
+    /// Computes the average color of the full-size image in linear space. Every frame,
+    /// face and depth slice contributes equally to the average.
+    pub fn compute_reflectivity(&self) -> [f32; 3] {
+        let (width, height) = {
+            let header70 = self.header.get_h70();
+            (header70.width, header70.height)
+        };
+
+        // VTFLib treats colors as having a plain 2.2 gamma rather than being sRGB
+        let mut gamma_lut = [0.0f32; 256];
+        for (i, l) in gamma_lut.iter_mut().enumerate() {
+            *l = (i as f32 / 255.0).powf(2.2);
+        }
+
+        let mut reflectivity = [0.0f32; 3];
+        let mut image_count = 0;
+        for frame in &self.frames {
+            for face in &frame.faces {
+                for slice in &face.slices {
+                    let pixels = decode_cropped(slice, width, height);
+                    if pixels.is_empty() {
+                        continue;
+                    }
+
+                    let mut sum = [0.0f32; 3];
+                    for p in &pixels {
+                        sum[0] += gamma_lut[p.red as usize];
+                        sum[1] += gamma_lut[p.green as usize];
+                        sum[2] += gamma_lut[p.blue as usize];
+                    }
+
+                    for c in 0..3 {
+                        reflectivity[c] += sum[c] / pixels.len() as f32;
+                    }
+                    image_count += 1;
+                }
+            }
+        }
+
+        if image_count > 0 {
+            for c in &mut reflectivity {
+                *c /= image_count as f32;
+            }
+        }
+        reflectivity
+    }
+
+    /// Recomputes the header's reflectivity vector from the full-size image.
+    pub fn update_reflectivity(&mut self) {
+        let reflectivity = self.compute_reflectivity();
+        self.header.get_h70_mut().reflectivity = reflectivity;
+    }
+
+    /// Generates the DXT1 low-res thumbnail from the full-size image of the starting
+    /// frame, replacing any existing thumbnail and updating the header to match.
+    ///
+    /// The thumbnail is the first mip level that fits within 16x16, and is filtered
+    /// down from the full-size image with a box filter. Nothing changes if the image is
+    /// empty or smaller than the header says it is.
+    pub fn generate_thumbnail(&mut self) {
+        let (width, height) = {
+            let header70 = self.header.get_h70();
+            (header70.width, header70.height)
+        };
+
+        let mut thumb_level = 0;
+        let mut thumb_dims = (width, height);
+        while thumb_dims.0 > THUMB_MAX_SIZE || thumb_dims.1 > THUMB_MAX_SIZE {
+            thumb_level += 1;
+            thumb_dims = VTFFile::compute_mip_dimensions(width, height, thumb_level);
+        }
+
+        let pixels = decode_cropped(self.image(), width, height);
+        let options = MipOptions {
+            filter: MipFilter::Box,
+            linear: false,
+            normal_map: false
+        };
+        let thumb_pixels = match mipmap::generate_mip_count(&pixels, width, height, thumb_level + 1, &options) {
+            Ok(mut levels) => levels.pop().unwrap().pixels,
+            Err(_) => return
+        };
+
+        // Thumbnails are at most 16x16, so they can always be padded out to whole blocks
+        let thumb = Dxt1::encode(&thumb_pixels, thumb_dims.0, thumb_dims.1, DxtQuality::default()).unwrap();
+        self.thumb = Some(VTFImageWrapper::DXT1(thumb));
+
+        let header70 = self.header.get_h70_mut();
+        header70.thumbnail_format = ImageFormat::DXT1;
+        header70.thumbnail_width = thumb_dims.0 as u8;
+        header70.thumbnail_height = thumb_dims.1 as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{VTFFile, VTFFrame, VTFFace, VTFFlags, ImageFormat};
+    use super::super::image::{VTFImageWrapper, Dxt1, Rgba8, DxtQuality};
+    use super::super::import::new_header;
+
+    fn single_image_file(image: VTFImageWrapper, width: u16, height: u16, format: ImageFormat) -> VTFFile {
+        let header = new_header(2, width, height, 1, 1, VTFFlags::empty(), format, 1);
+        let face = VTFFace {mips: Vec::new(), slices: vec![image]};
+        VTFFile::from_parts(header, vec![VTFFrame {faces: vec![face]}])
+    }
+
+    #[test]
+    fn thumbnail_of_dxt5_smaller_than_a_block() {
+        let pixels: Vec<Rgba8> = (0..16).map(|i| Rgba8 {red: i * 16, green: 255 - i * 16, blue: 0, alpha: 255}).collect();
+        let image = VTFImageWrapper::encode(&pixels, 8, 2, ImageFormat::DXT5, DxtQuality::default()).unwrap();
+        let mut vtf = single_image_file(image, 8, 2, ImageFormat::DXT5);
+
+        vtf.generate_thumbnail();
+        let header70 = vtf.header.get_h70();
+        assert_eq!((header70.thumbnail_width, header70.thumbnail_height), (8, 2));
+        assert!(vtf.thumb.is_some());
+    }
+
+    #[test]
+    fn padding_is_ignored_for_dxt1_smaller_than_a_block() {
+        // The top-left 2x2 pixels of the block are white, and the padding around them is black
+        let block = [0xFF, 0xFF, 0x00, 0x00, 0x50, 0x50, 0x55, 0x55];
+        let image = Dxt1::load(&mut &block[..], 2, 2).unwrap();
+        let mut vtf = single_image_file(VTFImageWrapper::DXT1(image), 2, 2, ImageFormat::DXT1);
+
+        assert_eq!(vtf.compute_reflectivity(), [1.0, 1.0, 1.0]);
+
+        vtf.generate_thumbnail();
+        let header70 = vtf.header.get_h70();
+        assert_eq!((header70.thumbnail_width, header70.thumbnail_height), (2, 2));
+        let thumb = vtf.thumb.as_ref().unwrap().expose().to_rgba8();
+        assert_eq!((thumb[0].red, thumb[1].red, thumb[4].red, thumb[5].red), (255, 255, 255, 255));
+    }
+}
@@ -0,0 +1,403 @@
+use std::fs::File;
+use std::io::Write;
+
+use super::{VTFFile, VTFData, HeaderRoot, Header73, HeaderVersion, Resource, ResourceID, ImageFormat};
+use super::image::VTFImageWrapper;
+use super::error::{VTFLoadError, VTFError};
+use super::resource::write_u32;
+
+impl VTFFile {
+    pub fn save(&self, file: &mut File) -> Result<(), VTFLoadError> {
+        self.to_writer(file)
+    }
+
+    /// Writes the file to any destination. See `to_bytes` for how the file is laid out.
+    pub fn to_writer<W>(&self, dest: &mut W) -> Result<(), VTFLoadError> where W: Write {
+        let bytes = try!(self.to_bytes());
+        dest.write_all(&bytes).map_err(VTFLoadError::Io)
+    }
+
+    /// Serializes the file using the version given in its header.
+    ///
+    /// The header size and the offsets of any resource data are kept from the file that
+    /// was loaded, as long as they still fit the data being written. Otherwise, resource
+    /// data is laid out in the order of the resource table, directly after the header.
+    /// Any padding at the end of the header or between resources is written as zeroes.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, VTFLoadError> {
+        let header = &self.header;
+        let root = header.get_root();
+        let header70 = header.get_h70();
+
+        // Make sure the header's layout matches its version number
+        let version_matches = match header {
+            &HeaderVersion::H70(..) => root.version[1] == 0 || root.version[1] == 1,
+            &HeaderVersion::H72(..) => root.version[1] == 2,
+            &HeaderVersion::H73(..) => root.version[1] >= 3 && root.version[1] <= 5
+        };
+        if root.version[0] != 7 || !version_matches {
+            return Err(VTFLoadError::VTF(VTFError::HeaderVersion));
+        }
+
+        let thumb_data = try!(self.thumb_data());
+        let image_data = try!(self.image_data());
+
+        let mut data = Vec::new();
+        match header {
+            &HeaderVersion::H73(_, _, ref h72, ref h73) => {
+                let resources = try!(self.resource_chunks(&thumb_data, &image_data));
+
+                // The resource table sits at the end of the header
+                let min_header_size = 72 + resources.len() * 8;
+                let header_size = match root.header_size as usize >= min_header_size {
+                    true => root.header_size as usize,
+                    false => 80 + resources.len() * 8
+                };
+
+                try!(VTFFile::save_root(&mut data, root, header_size));
+                try!(header70.save(&mut data));
+                try!(h72.save(&mut data));
+                try!(Header73 {padding: h73.padding, resource_count: resources.len() as u32}.save(&mut data));
+                data.resize(header_size - resources.len() * 8, 0);
+
+                let offsets = VTFFile::chunk_offsets(&resources, header_size);
+                for (&(id, inline, _), offset) in resources.iter().zip(&offsets) {
+                    try!(Resource {id: id, data: offset.unwrap_or(inline)}.save(&mut data));
+                }
+
+                for (&(_, _, ref chunk), offset) in resources.iter().zip(&offsets) {
+                    if let (&Some(ref chunk), &Some(offset)) = (chunk, offset) {
+                        let offset = offset as usize;
+                        if data.len() < offset + chunk.len() {
+                            data.resize(offset + chunk.len(), 0);
+                        }
+                        data[offset..offset + chunk.len()].copy_from_slice(chunk);
+                    }
+                }
+            },
+            _ => {
+                // The thumbnail comes directly after the header, and the high-res image
+                // directly after that
+                let min_header_size = match header.get_h72() {
+                    Some(_) => 65,
+                    None => 63
+                };
+                let header_size = match root.header_size as usize >= min_header_size {
+                    true => root.header_size as usize,
+                    false => if min_header_size == 65 {80} else {64}
+                };
+
+                try!(VTFFile::save_root(&mut data, root, header_size));
+                try!(header70.save(&mut data));
+                if let Some(h72) = header.get_h72() {
+                    try!(h72.save(&mut data));
+                }
+                data.resize(header_size, 0);
+
+                if let Some(ref thumb_data) = thumb_data {
+                    data.extend_from_slice(thumb_data);
+                }
+                data.extend_from_slice(&image_data);
+            }
+        }
+
+        Ok(data)
+    }
+
+    fn save_root(data: &mut Vec<u8>, root: &HeaderRoot, header_size: usize) -> Result<(), VTFLoadError> {
+        HeaderRoot {
+            type_string: root.type_string,
+            version: root.version,
+            header_size: header_size as i32
+        }.save(data)
+    }
+
+    /// Serializes the thumbnail. Returns `None` if the header says there isn't one.
+    fn thumb_data(&self) -> Result<Option<Vec<u8>>, VTFLoadError> {
+        let header70 = self.header.get_h70();
+        let thumb_size = VTFFile::thumb_data_size(&self.header);
+
+        if header70.thumbnail_format == ImageFormat::NONE || thumb_size == 0 {
+            return Ok(None);
+        }
+
+        let mut thumb_data = Vec::with_capacity(thumb_size as usize);
+        match self.thumb {
+            Some(ref thumb) => try!(VTFFile::save_image(&mut thumb_data, thumb, header70.thumbnail_format, thumb_size)),
+            None => return Err(VTFLoadError::VTF(VTFError::ImageMismatch))
+        }
+
+        Ok(Some(thumb_data))
+    }
+
+    /// Serializes every mip, frame, face and slice in the order `load_mips` reads them.
+    fn image_data(&self) -> Result<Vec<u8>, VTFLoadError> {
+        let header = &self.header;
+        let header70 = header.get_h70();
+        let frame_count = VTFFile::frame_count_of(header) as usize;
+        let face_count = header.face_count();
+
+        if self.frames.len() != frame_count || self.frames.iter().any(|f| f.faces.len() != face_count) {
+            return Err(VTFLoadError::VTF(VTFError::ImageMismatch));
+        }
+
+        let mut image_data = Vec::with_capacity(VTFFile::image_data_size(header).unwrap_or(0) as usize);
+        for mip_level in (0..VTFFile::mip_count(header)).rev() {
+            let mip_dims = VTFFile::compute_mip_dimensions(header70.width, header70.height, mip_level);
+            let mip_depth = VTFFile::compute_mip_depth(header.depth(), mip_level) as usize;
+            let mip_size = match header70.image_format.data_size(mip_dims.0, mip_dims.1) {
+                Some(mip_size) => mip_size,
+                None => return Err(VTFLoadError::VTF(VTFError::UnsupportedFormat(header70.image_format)))
+            };
+
+            for frame in &self.frames {
+                for face in &frame.faces {
+                    let slices = match face.mip_slices(mip_level as usize) {
+                        Some(slices) if slices.len() == mip_depth => slices,
+                        _ => return Err(VTFLoadError::VTF(VTFError::ImageMismatch))
+                    };
+
+                    for slice in slices {
+                        try!(VTFFile::save_image(&mut image_data, slice, header70.image_format, mip_size));
+                    }
+                }
+            }
+        }
+
+        Ok(image_data)
+    }
+
+    /// Writes a single image, making sure it has the expected format and size.
+    fn save_image(data: &mut Vec<u8>, image: &VTFImageWrapper, format: ImageFormat, size: u64) -> Result<(), VTFLoadError> {
+        if image.format() != format {
+            return Err(VTFLoadError::VTF(VTFError::ImageMismatch));
+        }
+
+        let start = data.len();
+        try!(image.save(data).map_err(VTFLoadError::Io));
+        if (data.len() - start) as u64 != size {
+            return Err(VTFLoadError::VTF(VTFError::ImageMismatch));
+        }
+
+        Ok(())
+    }
+
+    /// Builds the resource table, along with each resource's data chunk. Resources
+    /// stored inline in the table have no chunk.
+    fn resource_chunks(&self, thumb_data: &Option<Vec<u8>>, image_data: &[u8]) -> Result<Vec<(ResourceID, u32, Option<Vec<u8>>)>, VTFLoadError> {
+        let mut resources: Vec<(ResourceID, u32)> = match self.resources {
+            Some(ref resources) => resources.iter().map(|r| (r.id, r.data)).collect(),
+            None => Vec::new()
+        };
+
+        // Keep the thumbnail and image entries in sync with the data being written
+        let has_thumb = resources.iter().any(|&(id, _)| id == ResourceID::LegacyLowResImage);
+        match (has_thumb, thumb_data.is_some()) {
+            (false, true) => resources.insert(0, (ResourceID::LegacyLowResImage, 0)),
+            (true, false) => resources.retain(|&(id, _)| id != ResourceID::LegacyLowResImage),
+            _ => ()
+        }
+        if !resources.iter().any(|&(id, _)| id == ResourceID::LegacyImage) {
+            resources.push((ResourceID::LegacyImage, 0));
+        }
+
+        let mut chunks = Vec::with_capacity(resources.len());
+        for (id, inline) in resources {
+            let chunk = match id {
+                ResourceID::LegacyLowResImage => thumb_data.clone(),
+                ResourceID::LegacyImage => Some(image_data.to_vec()),
+                _ if id.has_data_chunk() => match self.resource_data.chunk(id) {
+                    // Other chunks are prefixed with their length
+                    Some(bytes) => {
+                        let mut chunk = Vec::with_capacity(bytes.len() + 4);
+                        write_u32(&mut chunk, bytes.len() as u32);
+                        chunk.extend_from_slice(bytes);
+                        Some(chunk)
+                    },
+                    None => return Err(VTFLoadError::VTF(VTFError::MissingResourceData))
+                },
+                _ => None
+            };
+
+            chunks.push((id, inline, chunk));
+        }
+
+        Ok(chunks)
+    }
+
+    /// Picks where each resource's data chunk is written. The offsets stored in the
+    /// resource table are reused if none of the chunks overlap each other or the header.
+    fn chunk_offsets(resources: &[(ResourceID, u32, Option<Vec<u8>>)], header_size: usize) -> Vec<Option<u32>> {
+        let mut stored: Vec<(u64, u64)> = resources.iter()
+            .filter_map(|&(_, inline, ref chunk)| chunk.as_ref().map(|c| (inline as u64, c.len() as u64)))
+            .collect();
+        stored.sort();
+
+        let mut end = header_size as u64;
+        let mut keep_stored = true;
+        for &(offset, len) in &stored {
+            if offset < end {
+                keep_stored = false;
+                break;
+            }
+            end = offset + len;
+        }
+        keep_stored = keep_stored && end <= u32::max_value() as u64;
+
+        let mut next_offset = header_size as u32;
+        resources.iter().map(|&(_, inline, ref chunk)| {
+            chunk.as_ref().map(|c| match keep_stored {
+                true => inline,
+                false => {
+                    let offset = next_offset;
+                    next_offset += c.len() as u32;
+                    offset
+                }
+            })
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::super::VTFFile;
+
+    /// The thumbnail, a single 4x2 DXT1 block
+    const THUMB: [u8; 8] = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    fn le16(data: &mut Vec<u8>, value: u16) {
+        data.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+    }
+
+    fn le32(data: &mut Vec<u8>, value: u32) {
+        data.extend_from_slice(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8]);
+    }
+
+    /// Both mips of an 8x4 DXT1 image, smallest first
+    fn image() -> Vec<u8> {
+        (100..124).collect()
+    }
+
+    /// The header of an 8x4 DXT1 file with two mips and a thumbnail, up to the end of
+    /// the version's fields
+    fn header(minor: u32, header_size: u32, resource_count: u32) -> Vec<u8> {
+        let mut data = b"VTF\0".to_vec();
+        le32(&mut data, 7);
+        le32(&mut data, minor);
+        le32(&mut data, header_size);
+
+        le16(&mut data, 8);
+        le16(&mut data, 4);
+        le32(&mut data, 0);
+        le16(&mut data, 1);
+        le16(&mut data, 0);
+        data.extend_from_slice(&[9, 9, 9, 9]);
+        for _ in 0..3 {
+            le32(&mut data, 0x3f000000);
+        }
+        data.extend_from_slice(&[9, 9, 9, 9]);
+        le32(&mut data, 0x3f800000);
+        le32(&mut data, 13);
+        data.push(2);
+        le32(&mut data, 13);
+        data.push(4);
+        data.push(2);
+
+        if minor >= 2 {
+            le16(&mut data, 1);
+        }
+        if minor >= 3 {
+            data.extend_from_slice(&[0, 0, 0]);
+            le32(&mut data, resource_count);
+        }
+        data
+    }
+
+    /// A file with the thumbnail and image straight after the header, using resource
+    /// entries for 7.3+
+    fn simple_file(minor: u32, header_size: u32) -> Vec<u8> {
+        let resource_count = if minor >= 3 {2} else {0};
+        let mut data = header(minor, header_size, resource_count);
+        data.resize(header_size as usize - resource_count as usize * 8, 0);
+
+        if minor >= 3 {
+            le32(&mut data, 0x01);
+            le32(&mut data, header_size);
+            le32(&mut data, 0x30);
+            le32(&mut data, header_size + THUMB.len() as u32);
+        }
+        data.extend_from_slice(&THUMB);
+        data.extend_from_slice(&image());
+        data
+    }
+
+    fn assert_round_trip(bytes: &[u8]) {
+        let vtf = VTFFile::from_reader(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(&vtf.to_bytes().unwrap()[..], bytes);
+    }
+
+    #[test]
+    fn round_trip_every_version() {
+        for minor in 0..6 {
+            let header_size = match minor {
+                0 | 1 => 64,
+                2 => 80,
+                _ => 96
+            };
+            assert_round_trip(&simple_file(minor, header_size));
+        }
+    }
+
+    #[test]
+    fn round_trip_padded_headers() {
+        for &(minor, header_size) in &[(0, 80), (1, 96), (2, 128), (3, 104), (4, 128), (5, 256)] {
+            assert_round_trip(&simple_file(minor, header_size));
+        }
+    }
+
+    #[test]
+    fn round_trip_resources() {
+        let kvd = b"\"Information\" { \"Author\" \"me\" }";
+        let unknown = [0xAA, 0xBB, 0xCC];
+
+        for minor in 3..6 {
+            // Eight resources, with 16 bytes of padding before the table
+            let header_size = 72 + 16 + 8 * 8;
+            let image_offset = header_size;
+            let kvd_offset = image_offset + image().len() as u32;
+            // Leave a gap after the KVD chunk, which should be written back as zeroes
+            let unknown_offset = kvd_offset + 4 + kvd.len() as u32 + 5;
+            let thumb_offset = unknown_offset + 4 + unknown.len() as u32;
+
+            let mut data = header(minor, header_size, 8);
+            data.resize(header_size as usize - 8 * 8, 0);
+            for &(id, value) in &[(0x01, thumb_offset),
+                                  (0x30, image_offset),
+                                  (0x02435243, 0xDEADBEEF),
+                                  (0x02444f4c, 0x0a0a0b0b),
+                                  (0x024f5354, 0x01020304),
+                                  (0x44564b, kvd_offset),
+                                  (0x00434241, unknown_offset),
+                                  (0x02585858, 0x12345678)] {
+                le32(&mut data, id);
+                le32(&mut data, value);
+            }
+
+            data.extend_from_slice(&image());
+            le32(&mut data, kvd.len() as u32);
+            data.extend_from_slice(kvd);
+            data.extend_from_slice(&[0; 5]);
+            le32(&mut data, unknown.len() as u32);
+            data.extend_from_slice(&unknown);
+            data.extend_from_slice(&THUMB);
+
+            assert_round_trip(&data);
+
+            let vtf = VTFFile::from_reader(&mut Cursor::new(&data)).unwrap();
+            assert_eq!(vtf.resources().unknown().len(), 2);
+            assert_eq!(vtf.resources().key_values().unwrap().get("information"), None);
+            assert!(vtf.resources().key_values().unwrap().block("information").is_some());
+        }
+    }
+}